@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+
+use super::res::{
+    u16_at, u32_at, u8_at, ChunkHeader, StringPool, Value, NO_INDEX, RES_STRING_POOL_TYPE,
+    RES_TABLE_PACKAGE_TYPE, RES_TABLE_TYPE, RES_TABLE_TYPE_TYPE,
+};
+use super::ApkError;
+
+/// Entry offsets are pairs of entry index and offset / 4
+const TYPE_FLAG_SPARSE: u8 = 0x01;
+/// Entry offsets are 16-bit offset / 4
+const TYPE_FLAG_OFFSET16: u8 = 0x02;
+/// Entries holding a bag of values, like styles, rather than a single value
+const ENTRY_FLAG_COMPLEX: u16 = 0x0001;
+/// Entries storing their value inline, with the type in the upper byte of the flags
+const ENTRY_FLAG_COMPACT: u16 = 0x0008;
+
+/// Longest chain of references followed, to stop on reference cycles
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+/// The simple values of an APK's `resources.arsc`, used to resolve manifest
+/// attributes like `android:versionName="@string/version"`
+#[derive(Debug, Clone, Default)]
+pub struct ResourceTable {
+    /// Value of each resource, and whether it is from the default configuration
+    values: HashMap<u32, (bool, Value)>,
+}
+
+impl ResourceTable {
+    pub fn parse(data: &[u8]) -> Result<Self, ApkError> {
+        let table = ChunkHeader::read(data, 0)?;
+        if table.kind != RES_TABLE_TYPE {
+            return Err(ApkError::Malformed("not a resource table".to_string()));
+        }
+
+        let mut strings = None;
+        let mut values = HashMap::new();
+        for chunk in table.children(data)? {
+            match chunk.kind {
+                RES_STRING_POOL_TYPE if strings.is_none() => {
+                    strings = Some(StringPool::parse(data, &chunk)?);
+                }
+                RES_TABLE_PACKAGE_TYPE => {
+                    let strings = strings.as_ref().ok_or_else(|| {
+                        ApkError::Malformed("package before the string pool".to_string())
+                    })?;
+                    let package_id = u32_at(data, chunk.offset + 8)?;
+                    for child in chunk.children(data)? {
+                        if child.kind == RES_TABLE_TYPE_TYPE {
+                            parse_type(data, &child, package_id, strings, &mut values)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { values })
+    }
+
+    /// Follow a reference to the value it points to, preferring the value for the
+    /// default configuration. Other values are returned as they are.
+    pub fn resolve(&self, value: &Value) -> Value {
+        let mut value = value.clone();
+        for _ in 0..MAX_REFERENCE_DEPTH {
+            match value {
+                Value::Reference(id) => match self.values.get(&id) {
+                    Some((_, target)) => value = target.clone(),
+                    None => break,
+                },
+                _ => break,
+            }
+        }
+        value
+    }
+}
+
+/// Collect the simple values of one type (like `string`) in one configuration
+fn parse_type(
+    data: &[u8],
+    chunk: &ChunkHeader,
+    package_id: u32,
+    strings: &StringPool,
+    values: &mut HashMap<u32, (bool, Value)>,
+) -> Result<(), ApkError> {
+    let type_id = u8_at(data, chunk.offset + 8)? as u32;
+    let flags = u8_at(data, chunk.offset + 9)?;
+    let entry_count = u32_at(data, chunk.offset + 12)? as usize;
+    let entries_start = chunk.offset + u32_at(data, chunk.offset + 16)? as usize;
+
+    // The configuration follows with its size first; all zeros is the default
+    let config_size = u32_at(data, chunk.offset + 20)? as usize;
+    let is_default = data
+        .get(chunk.offset + 24..chunk.offset + 20 + config_size.max(4))
+        .is_some_and(|config| config.iter().all(|&byte| byte == 0));
+
+    let index = chunk.body();
+    for i in 0..entry_count {
+        let (entry, offset) = if flags & TYPE_FLAG_SPARSE != 0 {
+            let pair = index + i * 4;
+            (u16_at(data, pair)? as u32, u16_at(data, pair + 2)? as usize * 4)
+        } else if flags & TYPE_FLAG_OFFSET16 != 0 {
+            match u16_at(data, index + i * 2)? {
+                u16::MAX => continue,
+                offset => (i as u32, offset as usize * 4),
+            }
+        } else {
+            match u32_at(data, index + i * 4)? {
+                NO_INDEX => continue,
+                offset => (i as u32, offset as usize),
+            }
+        };
+
+        let entry_offset = entries_start + offset;
+        if entry_offset >= chunk.end() {
+            return Err(ApkError::Malformed("resource entry out of bounds".to_string()));
+        }
+        let entry_flags = u16_at(data, entry_offset + 2)?;
+        let (data_type, value) = if entry_flags & ENTRY_FLAG_COMPACT != 0 {
+            ((entry_flags >> 8) as u8, u32_at(data, entry_offset + 4)?)
+        } else if entry_flags & ENTRY_FLAG_COMPLEX != 0 {
+            continue;
+        } else {
+            let value_offset = entry_offset + u16_at(data, entry_offset)? as usize;
+            (u8_at(data, value_offset + 3)?, u32_at(data, value_offset + 4)?)
+        };
+
+        let id = (package_id << 24) | (type_id << 16) | entry;
+        let value = Value::parse(data_type, value, strings);
+        match values.get(&id) {
+            Some((true, _)) => {}
+            Some((false, _)) if !is_default => {}
+            _ => {
+                values.insert(id, (is_default, value));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::resource_table;
+
+    #[test]
+    fn test_resolve_prefers_default_config() {
+        let data = resource_table(&[
+            // A German translation listed before the default
+            (0x7f02_0000, Some("de"), Value::String("Beispiel".to_string())),
+            (0x7f02_0000, None, Value::String("Example".to_string())),
+            (0x7f02_0001, None, Value::String("1.2.3".to_string())),
+            (0x7f03_0000, None, Value::Int(10203)),
+            // @string/alias -> @string/version_name
+            (0x7f02_0002, None, Value::Reference(0x7f02_0001)),
+            (0x7f02_0003, None, Value::Reference(0x7f02_0003)),
+        ]);
+        let table = ResourceTable::parse(&data).unwrap();
+
+        assert_eq!(
+            table.resolve(&Value::Reference(0x7f02_0000)),
+            Value::String("Example".to_string())
+        );
+        assert_eq!(table.resolve(&Value::Reference(0x7f03_0000)), Value::Int(10203));
+        assert_eq!(
+            table.resolve(&Value::Reference(0x7f02_0002)),
+            Value::String("1.2.3".to_string())
+        );
+        // Cycles and unknown resources stay references
+        assert_eq!(table.resolve(&Value::Reference(0x7f02_0003)), Value::Reference(0x7f02_0003));
+        assert_eq!(table.resolve(&Value::Reference(0x7f09_0000)), Value::Reference(0x7f09_0000));
+        assert_eq!(table.resolve(&Value::Int(1)), Value::Int(1));
+    }
+
+    #[test]
+    fn test_rejects_invalid_tables() {
+        assert!(ResourceTable::parse(b"").is_err());
+        let data = resource_table(&[]);
+        assert!(ResourceTable::parse(&data[..data.len() - 8]).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use super::res::{
+    u16_at, u32_at, u8_at, ChunkHeader, StringPool, Value, RES_STRING_POOL_TYPE,
+    RES_XML_END_ELEMENT_TYPE, RES_XML_RESOURCE_MAP_TYPE, RES_XML_START_ELEMENT_TYPE, RES_XML_TYPE,
+};
+use super::ApkError;
+
+/// An attribute of a binary XML element
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlAttribute {
+    pub namespace: Option<String>,
+    pub name: String,
+    /// ID of the attribute's resource, like `android:versionCode`; names of these
+    /// attributes may be stripped by obfuscators, their IDs never are
+    pub resource_id: Option<u32>,
+    pub value: Value,
+}
+
+/// An element of a binary XML document and everything nested in it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<XmlAttribute>,
+    pub children: Vec<XmlElement>,
+}
+
+impl XmlElement {
+    /// Look up an `android:` attribute by resource ID, falling back to its name
+    pub fn android_attribute(&self, resource_id: u32, name: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.resource_id == Some(resource_id))
+            .or_else(|| {
+                self.attributes
+                    .iter()
+                    .find(|attribute| attribute.resource_id.is_none() && attribute.name == name)
+            })
+            .map(|attribute| &attribute.value)
+    }
+
+    /// Look up an attribute without a namespace, like the manifest's `package`
+    pub fn attribute(&self, name: &str) -> Option<&Value> {
+        self.attributes
+            .iter()
+            .find(|attribute| attribute.namespace.is_none() && attribute.name == name)
+            .map(|attribute| &attribute.value)
+    }
+
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+}
+
+/// Parse a binary XML document, such as an APK's `AndroidManifest.xml`, into its
+/// root element. Text and namespace declarations are skipped.
+pub fn parse(data: &[u8]) -> Result<XmlElement, ApkError> {
+    let document = ChunkHeader::read(data, 0)?;
+    if document.kind != RES_XML_TYPE {
+        return Err(ApkError::Malformed("not a binary XML document".to_string()));
+    }
+
+    let mut strings = None;
+    let mut resource_ids = Vec::new();
+    let mut open: Vec<XmlElement> = Vec::new();
+    let mut root = None;
+    for chunk in document.children(data)? {
+        match chunk.kind {
+            RES_STRING_POOL_TYPE if strings.is_none() => {
+                strings = Some(StringPool::parse(data, &chunk)?);
+            }
+            RES_XML_RESOURCE_MAP_TYPE => {
+                resource_ids = (chunk.body()..chunk.end().saturating_sub(3))
+                    .step_by(4)
+                    .map(|offset| u32_at(data, offset))
+                    .collect::<Result<_, _>>()?;
+            }
+            RES_XML_START_ELEMENT_TYPE => {
+                let strings = strings
+                    .as_ref()
+                    .ok_or_else(|| ApkError::Malformed("element before the string pool".to_string()))?;
+                open.push(parse_element(data, &chunk, strings, &resource_ids)?);
+            }
+            RES_XML_END_ELEMENT_TYPE => {
+                let element = open
+                    .pop()
+                    .ok_or_else(|| ApkError::Malformed("unbalanced end of element".to_string()))?;
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => {
+                        root.get_or_insert(element);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    root.ok_or_else(|| ApkError::Malformed("the document has no root element".to_string()))
+}
+
+fn parse_element(
+    data: &[u8],
+    chunk: &ChunkHeader,
+    strings: &StringPool,
+    resource_ids: &[u32],
+) -> Result<XmlElement, ApkError> {
+    // Missing strings, like the namespace of `package`, are NO_INDEX
+    let string = |index: u32| -> Option<String> { strings.get(index).map(str::to_string) };
+
+    let start = chunk.body();
+    let name = string(u32_at(data, start + 4)?).unwrap_or_default();
+    let attributes_start = start + u16_at(data, start + 8)? as usize;
+    let attribute_size = u16_at(data, start + 10)? as usize;
+    let attribute_count = u16_at(data, start + 12)? as usize;
+    if attribute_count > 0 && attribute_size < 20 {
+        return Err(ApkError::Malformed(format!("invalid attributes of <{}>", name)));
+    }
+
+    let attributes = (0..attribute_count)
+        .map(|i| {
+            let offset = attributes_start + i * attribute_size;
+            if offset + 20 > chunk.end() {
+                return Err(ApkError::Malformed(format!("invalid attributes of <{}>", name)));
+            }
+            let name_index = u32_at(data, offset + 4)?;
+            Ok(XmlAttribute {
+                namespace: string(u32_at(data, offset)?),
+                name: string(name_index).unwrap_or_default(),
+                resource_id: resource_ids.get(name_index as usize).copied(),
+                value: Value::parse(u8_at(data, offset + 15)?, u32_at(data, offset + 16)?, strings),
+            })
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(XmlElement {
+        name,
+        attributes,
+        children: Vec::new(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::{Attr, Axml};
+
+    #[test]
+    fn test_parse_document() {
+        let data = Axml::new()
+            .start(
+                "manifest",
+                &[
+                    ("package", Attr::Str("com.example")),
+                    ("versionCode", Attr::Int(42)),
+                ],
+            )
+            .start("application", &[("debuggable", Attr::Bool(true))])
+            .start("activity", &[("name", Attr::Str(".Main"))])
+            .end()
+            .end()
+            .start("uses-permission", &[("name", Attr::Str("android.permission.INTERNET"))])
+            .end()
+            .end()
+            .finish();
+
+        let root = parse(&data).unwrap();
+        assert_eq!(root.name, "manifest");
+        assert_eq!(root.attribute("package"), Some(&Value::String("com.example".to_string())));
+        assert_eq!(root.android_attribute(0x0101_021b, "versionCode"), Some(&Value::Int(42)));
+        // `versionCode` is in the android namespace
+        assert_eq!(root.attribute("versionCode"), None);
+
+        assert_eq!(root.children.len(), 2);
+        let application = root.children_named("application").next().unwrap();
+        assert_eq!(
+            application.android_attribute(0x0101_000f, "debuggable"),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(application.children[0].name, "activity");
+        assert_eq!(root.children_named("uses-permission").count(), 1);
+    }
+
+    #[test]
+    fn test_obfuscated_attribute_names() {
+        // Attribute names were replaced, but their resource IDs remain
+        let data = Axml::new()
+            .rename_attributes()
+            .start("manifest", &[("versionCode", Attr::Int(7))])
+            .end()
+            .finish();
+        let root = parse(&data).unwrap();
+        assert_ne!(root.attributes[0].name, "versionCode");
+        assert_eq!(root.android_attribute(0x0101_021b, "versionCode"), Some(&Value::Int(7)));
+    }
+
+    #[test]
+    fn test_rejects_invalid_documents() {
+        assert!(parse(b"<manifest/>").is_err());
+        let data = Axml::new().start("manifest", &[]).finish();
+        assert!(parse(&data).is_err());
+    }
+}
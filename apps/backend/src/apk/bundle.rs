@@ -0,0 +1,482 @@
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Seek};
+
+use super::zip::ZipArchive;
+use super::{ApkError, MANIFEST_ENTRY};
+
+/// ABIs as they appear in split names, e.g. `config.arm64_v8a`
+const ABIS: &[&str] = &[
+    "armeabi",
+    "armeabi_v7a",
+    "arm64_v8a",
+    "x86",
+    "x86_64",
+    "mips",
+    "mips64",
+    "riscv64",
+];
+
+/// Density qualifiers and the densities they stand for, in dots per inch
+const DENSITIES: &[(&str, u32)] = &[
+    ("ldpi", 120),
+    ("mdpi", 160),
+    ("tvdpi", 213),
+    ("hdpi", 240),
+    ("xhdpi", 320),
+    ("xxhdpi", 480),
+    ("xxxhdpi", 640),
+];
+
+/// Where Android looks for the expansion files of `.xapk` bundles
+const OBB_DIR: &str = "Android/obb/";
+
+/// What the device offers that configuration splits are chosen for
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    /// Supported ABIs, most preferred first, e.g. `x86_64`, `arm64-v8a`
+    pub abis: Vec<String>,
+    /// Screen density in dots per inch
+    pub density: u32,
+}
+
+/// What a split APK adds to the app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitKind {
+    Base,
+    /// The code and resources of a feature module
+    Feature,
+    /// Native libraries for one ABI
+    Abi,
+    /// Resources for one screen density
+    Density,
+    /// Resources for one language
+    Language,
+}
+
+/// A split APK chosen for installation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChosenSplit {
+    /// `base`, or the split name from the split's manifest, e.g. `config.arm64_v8a`
+    pub name: String,
+    pub kind: SplitKind,
+}
+
+/// Which splits of an app to install on a device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitSelection {
+    pub chosen: Vec<ChosenSplit>,
+    /// Configuration splits for other ABIs and densities
+    pub skipped: Vec<String>,
+}
+
+/// The module and kind of a split, and its configuration qualifier if any
+fn classify(name: &str) -> (&str, SplitKind, Option<&str>) {
+    if name.is_empty() || name == "base" {
+        return ("", SplitKind::Base, None);
+    }
+    let (module, qualifier) = match name.strip_prefix("config.") {
+        Some(qualifier) => ("", qualifier),
+        None => match name.split_once(".config.") {
+            Some(split) => split,
+            None => return (name, SplitKind::Feature, None),
+        },
+    };
+    let kind = if ABIS.contains(&qualifier) {
+        SplitKind::Abi
+    } else if density(qualifier).is_some() {
+        SplitKind::Density
+    } else {
+        SplitKind::Language
+    };
+    (module, kind, Some(qualifier))
+}
+
+fn density(qualifier: &str) -> Option<u32> {
+    DENSITIES
+        .iter()
+        .find(|(name, _)| *name == qualifier)
+        .map(|(_, dpi)| *dpi)
+}
+
+/// Choose the splits of an app to install on a device.
+///
+/// Every base, feature and language split is installed. Of the ABI splits of each
+/// module, only the one for the device's most preferred ABI is; of the density
+/// splits, the one for the smallest density at least the device's, or the largest
+/// if all are smaller.
+pub fn select_splits<S: AsRef<str>>(names: &[S], device: &DeviceProfile) -> SplitSelection {
+    let splits: Vec<_> = names.iter().map(|name| (name.as_ref(), classify(name.as_ref()))).collect();
+    let candidates = |module: &str, kind: SplitKind| -> Vec<&str> {
+        splits
+            .iter()
+            .filter(|(_, (m, k, _))| *m == module && *k == kind)
+            .filter_map(|(_, (_, _, qualifier))| *qualifier)
+            .collect()
+    };
+
+    let best_abi = |module: &str| {
+        device
+            .abis
+            .iter()
+            .map(|abi| abi.replace('-', "_"))
+            .find(|abi| candidates(module, SplitKind::Abi).contains(&abi.as_str()))
+    };
+    let best_density = |module: &str| {
+        let densities: Vec<(&str, u32)> = candidates(module, SplitKind::Density)
+            .into_iter()
+            .filter_map(|qualifier| Some((qualifier, density(qualifier)?)))
+            .collect();
+        densities
+            .iter()
+            .filter(|(_, dpi)| *dpi >= device.density)
+            .min_by_key(|(_, dpi)| *dpi)
+            .or_else(|| densities.iter().max_by_key(|(_, dpi)| *dpi))
+            .map(|(qualifier, _)| qualifier.to_string())
+    };
+
+    let mut selection = SplitSelection::default();
+    for (name, (module, kind, qualifier)) in &splits {
+        let chosen = match kind {
+            SplitKind::Abi => best_abi(module).as_deref() == *qualifier,
+            SplitKind::Density => best_density(module).as_deref() == *qualifier,
+            _ => true,
+        };
+        if chosen {
+            selection.chosen.push(ChosenSplit {
+                name: name.to_string(),
+                kind: *kind,
+            });
+        } else {
+            selection.skipped.push(name.to_string());
+        }
+    }
+    selection
+}
+
+/// A split APK inside a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleSplit {
+    pub name: String,
+    /// Path of the APK in the bundle
+    pub entry: String,
+}
+
+/// An expansion file inside a bundle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleObb {
+    pub entry: String,
+    /// Name of the file in the app's OBB directory, e.g. `main.12.com.example.obb`
+    pub file_name: String,
+}
+
+/// The APKs and expansion files of an APK set (`.apks`, as built by bundletool)
+/// or an `.xapk`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bundle {
+    /// The package, when the bundle declares it outside the APKs
+    pub package: Option<String>,
+    pub splits: Vec<BundleSplit>,
+    pub obbs: Vec<BundleObb>,
+}
+
+/// `manifest.json` of an `.xapk`
+#[derive(Debug, Deserialize)]
+struct XapkManifest {
+    package_name: String,
+    #[serde(default)]
+    split_apks: Vec<XapkSplit>,
+    #[serde(default)]
+    expansions: Vec<XapkExpansion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct XapkSplit {
+    file: String,
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct XapkExpansion {
+    file: String,
+    install_path: Option<String>,
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+impl Bundle {
+    /// Describe the bundle in an archive, or return `None` if it is a plain APK
+    pub fn read<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<Option<Self>, ApkError> {
+        let names: Vec<String> = archive.names().map(str::to_string).collect();
+        if names.iter().any(|name| name == MANIFEST_ENTRY) {
+            return Ok(None);
+        }
+
+        let bundle = if names.iter().any(|name| name == "manifest.json") {
+            let manifest = archive
+                .read("manifest.json", 1024 * 1024)?
+                .unwrap_or_default();
+            let manifest: XapkManifest = serde_json::from_slice(&manifest)
+                .map_err(|e| ApkError::InvalidBundle(format!("invalid manifest.json: {}", e)))?;
+            Self::from_xapk(manifest, &names)
+        } else if names.iter().any(|name| name.starts_with("splits/")) {
+            Self::from_apk_set(&names)
+        } else {
+            return Err(ApkError::MissingEntry(MANIFEST_ENTRY.to_string()));
+        };
+
+        for split in &bundle.splits {
+            if !names.contains(&split.entry) {
+                return Err(ApkError::InvalidBundle(format!("{} is missing", split.entry)));
+            }
+        }
+        if !bundle.splits.iter().any(|split| split.name == "base") {
+            return Err(ApkError::InvalidBundle("there is no base APK".to_string()));
+        }
+        Ok(Some(bundle))
+    }
+
+    /// bundletool names the APKs of a set `splits/<module>-<config>.apk`, with
+    /// `master` for the module itself
+    fn from_apk_set(names: &[String]) -> Self {
+        let splits = names
+            .iter()
+            .filter(|entry| entry.starts_with("splits/") && entry.ends_with(".apk"))
+            .map(|entry| {
+                let stem = file_name(entry).trim_end_matches(".apk");
+                let name = match stem.split_once('-').unwrap_or((stem, "master")) {
+                    ("base", "master") => "base".to_string(),
+                    (module, "master") => module.to_string(),
+                    ("base", config) => format!("config.{}", config),
+                    (module, config) => format!("{}.config.{}", module, config),
+                };
+                BundleSplit {
+                    name,
+                    entry: entry.clone(),
+                }
+            })
+            .collect();
+        Self {
+            package: None,
+            splits,
+            obbs: Vec::new(),
+        }
+    }
+
+    fn from_xapk(manifest: XapkManifest, names: &[String]) -> Self {
+        let base_file = format!("{}.apk", manifest.package_name);
+        let splits = if manifest.split_apks.is_empty() {
+            // Single-APK bundles only list their expansion files
+            names
+                .iter()
+                .filter(|entry| !entry.contains('/') && entry.ends_with(".apk"))
+                .map(|entry| BundleSplit {
+                    name: if *entry == base_file {
+                        "base".to_string()
+                    } else {
+                        entry.trim_end_matches(".apk").to_string()
+                    },
+                    entry: entry.clone(),
+                })
+                .collect()
+        } else {
+            manifest
+                .split_apks
+                .into_iter()
+                .map(|split| BundleSplit {
+                    name: split.id,
+                    entry: split.file,
+                })
+                .collect()
+        };
+
+        let obbs = if manifest.expansions.is_empty() {
+            names
+                .iter()
+                .filter(|entry| entry.starts_with(OBB_DIR) && entry.ends_with(".obb"))
+                .map(|entry| BundleObb {
+                    entry: entry.clone(),
+                    file_name: file_name(entry).to_string(),
+                })
+                .collect()
+        } else {
+            manifest
+                .expansions
+                .into_iter()
+                .map(|expansion| BundleObb {
+                    file_name: file_name(expansion.install_path.as_deref().unwrap_or(&expansion.file))
+                        .to_string(),
+                    entry: expansion.file,
+                })
+                .collect()
+        };
+
+        Self {
+            package: Some(manifest.package_name),
+            splits,
+            obbs,
+        }
+    }
+
+    /// The base APK of the bundle
+    pub fn base(&self) -> Option<&BundleSplit> {
+        self.splits.iter().find(|split| split.name == "base")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::zip;
+    use std::io::Cursor;
+
+    fn device() -> DeviceProfile {
+        DeviceProfile {
+            abis: vec!["x86_64".to_string(), "x86".to_string(), "arm64-v8a".to_string()],
+            density: 420,
+        }
+    }
+
+    #[test]
+    fn test_select_splits() {
+        let names = [
+            "base",
+            "config.arm64_v8a",
+            "config.x86",
+            "config.x86_64",
+            "config.hdpi",
+            "config.xxhdpi",
+            "config.xxxhdpi",
+            "config.en",
+            "config.de",
+            "camera",
+            "camera.config.arm64_v8a",
+            "camera.config.x86",
+            "camera.config.mdpi",
+        ];
+        let selection = select_splits(&names, &device());
+        let chosen: Vec<(&str, SplitKind)> = selection
+            .chosen
+            .iter()
+            .map(|split| (split.name.as_str(), split.kind))
+            .collect();
+        assert_eq!(
+            chosen,
+            vec![
+                ("base", SplitKind::Base),
+                ("config.x86_64", SplitKind::Abi),
+                ("config.xxhdpi", SplitKind::Density),
+                ("config.en", SplitKind::Language),
+                ("config.de", SplitKind::Language),
+                ("camera", SplitKind::Feature),
+                // The module has no x86_64 libraries, so the next preferred ABI is used
+                ("camera.config.x86", SplitKind::Abi),
+                // A lower density is still better than none
+                ("camera.config.mdpi", SplitKind::Density),
+            ]
+        );
+        assert_eq!(
+            selection.skipped,
+            vec![
+                "config.arm64_v8a",
+                "config.x86",
+                "config.hdpi",
+                "config.xxxhdpi",
+                "camera.config.arm64_v8a",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_splits_without_matching_abi() {
+        let device = DeviceProfile {
+            abis: vec!["riscv64".to_string()],
+            density: 160,
+        };
+        let selection = select_splits(&["base", "config.arm64_v8a", "config.mdpi"], &device);
+        assert_eq!(selection.chosen.len(), 2);
+        assert_eq!(selection.skipped, vec!["config.arm64_v8a"]);
+    }
+
+    #[test]
+    fn test_read_apk_set() {
+        let data = zip(&[
+            ("toc.pb", b"".as_slice(), false),
+            ("splits/base-master.apk", b"PK".as_slice(), false),
+            ("splits/base-arm64_v8a.apk", b"PK".as_slice(), false),
+            ("splits/base-xxhdpi.apk", b"PK".as_slice(), false),
+            ("splits/camera-master.apk", b"PK".as_slice(), false),
+            ("splits/camera-x86.apk", b"PK".as_slice(), false),
+            ("standalones/standalone-x86.apk", b"PK".as_slice(), false),
+        ]);
+        let bundle = Bundle::read(&mut ZipArchive::new(Cursor::new(data)).unwrap())
+            .unwrap()
+            .unwrap();
+        let names: Vec<&str> = bundle.splits.iter().map(|split| split.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["base", "config.arm64_v8a", "config.xxhdpi", "camera", "camera.config.x86"]
+        );
+        assert_eq!(bundle.base().unwrap().entry, "splits/base-master.apk");
+        assert_eq!(bundle.package, None);
+    }
+
+    #[test]
+    fn test_read_xapk() {
+        let manifest = br#"{
+            "xapk_version": 2,
+            "package_name": "com.example.game",
+            "version_code": "12",
+            "split_apks": [
+                {"file": "com.example.game.apk", "id": "base"},
+                {"file": "config.arm64_v8a.apk", "id": "config.arm64_v8a"}
+            ],
+            "expansions": [
+                {
+                    "file": "Android/obb/com.example.game/main.12.com.example.game.obb",
+                    "install_location": "EXTERNAL_STORAGE",
+                    "install_path": "Android/obb/com.example.game/main.12.com.example.game.obb"
+                }
+            ]
+        }"#;
+        let data = zip(&[
+            ("manifest.json", manifest.as_slice(), true),
+            ("com.example.game.apk", b"PK".as_slice(), false),
+            ("config.arm64_v8a.apk", b"PK".as_slice(), false),
+            ("Android/obb/com.example.game/main.12.com.example.game.obb", b"obb".as_slice(), false),
+        ]);
+        let bundle = Bundle::read(&mut ZipArchive::new(Cursor::new(data)).unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(bundle.package.as_deref(), Some("com.example.game"));
+        assert_eq!(bundle.base().unwrap().entry, "com.example.game.apk");
+        assert_eq!(bundle.splits[1].name, "config.arm64_v8a");
+        assert_eq!(
+            bundle.obbs,
+            vec![BundleObb {
+                entry: "Android/obb/com.example.game/main.12.com.example.game.obb".to_string(),
+                file_name: "main.12.com.example.game.obb".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_plain_apk_and_invalid_bundles() {
+        let apk = zip(&[(MANIFEST_ENTRY, b"".as_slice(), false)]);
+        assert_eq!(Bundle::read(&mut ZipArchive::new(Cursor::new(apk)).unwrap()).unwrap(), None);
+
+        let no_base = zip(&[("splits/base-x86.apk", b"PK".as_slice(), false)]);
+        assert!(matches!(
+            Bundle::read(&mut ZipArchive::new(Cursor::new(no_base)).unwrap()),
+            Err(ApkError::InvalidBundle(_))
+        ));
+
+        let other = zip(&[("README.md", b"hi".as_slice(), false)]);
+        assert!(matches!(
+            Bundle::read(&mut ZipArchive::new(Cursor::new(other)).unwrap()),
+            Err(ApkError::MissingEntry(_))
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+//! Writers for the binary formats the parsers read, to build sample APKs in tests
+
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use std::collections::HashMap;
+use std::io::Write;
+
+use super::res::{Value, NO_INDEX};
+
+const ANDROID_NAMESPACE: &str = "http://schemas.android.com/apk/res/android";
+
+/// Resource IDs of the `android:` attributes used in sample manifests
+fn android_attribute_id(name: &str) -> Option<u32> {
+    Some(match name {
+        "label" => 0x0101_0001,
+        "name" => 0x0101_0003,
+        "enabled" => 0x0101_000e,
+        "debuggable" => 0x0101_000f,
+        "minSdkVersion" => 0x0101_020c,
+        "versionCode" => 0x0101_021b,
+        "versionName" => 0x0101_021c,
+        "targetSdkVersion" => 0x0101_0270,
+        "versionCodeMajor" => 0x0101_0576,
+        _ => return None,
+    })
+}
+
+/// A chunk with a header of `header` after the common type and size fields
+fn chunk(kind: u16, header: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(8 + header.len() + body.len());
+    data.extend_from_slice(&kind.to_le_bytes());
+    data.extend_from_slice(&(8 + header.len() as u16).to_le_bytes());
+    data.extend_from_slice(&(8 + header.len() as u32 + body.len() as u32).to_le_bytes());
+    data.extend_from_slice(header);
+    data.extend_from_slice(body);
+    data
+}
+
+fn words(values: &[u32]) -> Vec<u8> {
+    values.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+pub fn string_pool(strings: &[&str], utf8: bool) -> Vec<u8> {
+    let mut offsets = Vec::new();
+    let mut data = Vec::new();
+    for s in strings {
+        offsets.push(data.len() as u32);
+        if utf8 {
+            for len in [s.encode_utf16().count(), s.len()] {
+                if len > 0x7f {
+                    data.extend_from_slice(&[0x80 | (len >> 8) as u8, len as u8]);
+                } else {
+                    data.push(len as u8);
+                }
+            }
+            data.extend_from_slice(s.as_bytes());
+            data.push(0);
+        } else {
+            let units: Vec<u16> = s.encode_utf16().collect();
+            let len = units.len();
+            let mut prefix = vec![len as u16];
+            if len > 0x7fff {
+                prefix = vec![0x8000 | (len >> 16) as u16, len as u16];
+            }
+            for unit in prefix.into_iter().chain(units).chain([0]) {
+                data.extend_from_slice(&unit.to_le_bytes());
+            }
+        }
+    }
+    while data.len() % 4 != 0 {
+        data.push(0);
+    }
+
+    let header = words(&[
+        strings.len() as u32,
+        0,
+        if utf8 { 0x100 } else { 0 },
+        28 + 4 * strings.len() as u32,
+        0,
+    ]);
+    let mut body = words(&offsets);
+    body.extend_from_slice(&data);
+    chunk(0x0001, &header, &body)
+}
+
+/// A value of a sample attribute
+#[derive(Debug, Clone, Copy)]
+pub enum Attr {
+    Str(&'static str),
+    Int(i32),
+    Bool(bool),
+    Ref(u32),
+}
+
+enum Event {
+    Start(String, Vec<(String, Attr)>),
+    End(String),
+}
+
+/// Builds a binary XML document the way aapt2 lays it out
+#[derive(Default)]
+pub struct Axml {
+    events: Vec<Event>,
+    open: Vec<String>,
+    utf8: bool,
+    rename_attributes: bool,
+}
+
+impl Axml {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a UTF-8 string pool
+    pub fn utf8(mut self) -> Self {
+        self.utf8 = true;
+        self
+    }
+
+    /// Replace the names of `android:` attributes, like obfuscators do
+    pub fn rename_attributes(mut self) -> Self {
+        self.rename_attributes = true;
+        self
+    }
+
+    /// Open an element; attribute names known to `android_attribute_id` go into the
+    /// android namespace
+    pub fn start(mut self, name: &str, attributes: &[(&str, Attr)]) -> Self {
+        let attributes = attributes
+            .iter()
+            .map(|(name, value)| (name.to_string(), *value))
+            .collect();
+        self.events.push(Event::Start(name.to_string(), attributes));
+        self.open.push(name.to_string());
+        self
+    }
+
+    pub fn end(mut self) -> Self {
+        let name = self.open.pop().expect("an open element");
+        self.events.push(Event::End(name));
+        self
+    }
+
+    /// An intent filter that makes the open activity appear in the launcher
+    pub fn launcher_filter(self) -> Self {
+        self.start("intent-filter", &[])
+            .start("action", &[("name", Attr::Str("android.intent.action.MAIN"))])
+            .end()
+            .start("category", &[("name", Attr::Str("android.intent.category.LAUNCHER"))])
+            .end()
+            .end()
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        let mut strings: Vec<String> = Vec::new();
+        let mut indices: HashMap<String, u32> = HashMap::new();
+
+        // Attributes with resource IDs come first, in the order of the resource map
+        let mut resource_ids = Vec::new();
+        let mut attribute_indices = HashMap::new();
+        for event in &self.events {
+            if let Event::Start(_, attributes) = event {
+                for (name, _) in attributes {
+                    if let Some(id) = android_attribute_id(name) {
+                        attribute_indices.entry(id).or_insert_with(|| {
+                            strings.push(if self.rename_attributes {
+                                format!("a{}", resource_ids.len())
+                            } else {
+                                name.clone()
+                            });
+                            resource_ids.push(id);
+                            strings.len() as u32 - 1
+                        });
+                    }
+                }
+            }
+        }
+        let mut intern = |s: &str| -> u32 {
+            *indices.entry(s.to_string()).or_insert_with(|| {
+                strings.push(s.to_string());
+                strings.len() as u32 - 1
+            })
+        };
+
+        let prefix = intern("android");
+        let namespace = intern(ANDROID_NAMESPACE);
+        let node = words(&[1, NO_INDEX]);
+        let mut body = chunk(0x0100, &node, &words(&[prefix, namespace]));
+        for event in &self.events {
+            match event {
+                Event::Start(name, attributes) => {
+                    let mut element = words(&[NO_INDEX, intern(name)]);
+                    for field in [20u16, 20, attributes.len() as u16, 0, 0, 0] {
+                        element.extend_from_slice(&field.to_le_bytes());
+                    }
+                    for (name, value) in attributes {
+                        let (namespace, name) = match android_attribute_id(name) {
+                            Some(id) => (namespace, attribute_indices[&id]),
+                            None => (NO_INDEX, intern(name)),
+                        };
+                        let (raw, data_type, data) = match value {
+                            Attr::Str(s) => {
+                                let index = intern(s);
+                                (index, 0x03u8, index)
+                            }
+                            Attr::Int(i) => (NO_INDEX, 0x10, *i as u32),
+                            Attr::Bool(b) => (NO_INDEX, 0x12, if *b { NO_INDEX } else { 0 }),
+                            Attr::Ref(id) => (NO_INDEX, 0x01, *id),
+                        };
+                        element.extend_from_slice(&words(&[namespace, name, raw]));
+                        element.extend_from_slice(&[8, 0, 0, data_type]);
+                        element.extend_from_slice(&data.to_le_bytes());
+                    }
+                    body.extend(chunk(0x0102, &node, &element));
+                }
+                Event::End(name) => {
+                    body.extend(chunk(0x0103, &node, &words(&[NO_INDEX, intern(name)])));
+                }
+            }
+        }
+        body.extend(chunk(0x0101, &node, &words(&[prefix, namespace])));
+
+        let strings: Vec<&str> = strings.iter().map(String::as_str).collect();
+        let mut document = string_pool(&strings, self.utf8);
+        document.extend(chunk(0x0180, &[], &words(&resource_ids)));
+        document.extend(body);
+        chunk(0x0003, &[], &document)
+    }
+}
+
+/// A resource table with one package holding `entries` of resource ID, language
+/// of the configuration (`None` for the default) and value
+pub fn resource_table(entries: &[(u32, Option<&str>, Value)]) -> Vec<u8> {
+    let mut strings: Vec<&str> = Vec::new();
+    // Entries of each type and configuration, as entry index, data type and data
+    type Entries = Vec<(u16, u8, u32)>;
+    let mut types: Vec<((u8, Option<&str>), Entries)> = Vec::new();
+    for (id, language, value) in entries {
+        let (data_type, data) = match value {
+            Value::String(s) => {
+                strings.push(s);
+                (0x03, strings.len() as u32 - 1)
+            }
+            Value::Reference(id) => (0x01, *id),
+            Value::Int(i) => (0x10, *i as u32),
+            Value::Bool(b) => (0x12, *b as u32),
+            Value::Null => (0x00, 0),
+            Value::Other { data_type, data } => (*data_type, *data),
+        };
+        let key = ((id >> 16) as u8, *language);
+        match types.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, values)) => values.push((*id as u16, data_type, data)),
+            None => types.push((key, vec![(*id as u16, data_type, data)])),
+        }
+    }
+
+    let mut package = Vec::new();
+    for ((type_id, language), values) in types {
+        let count = values.iter().map(|(entry, _, _)| *entry as u32 + 1).max().unwrap_or(0);
+        let mut offsets = vec![NO_INDEX; count as usize];
+        let mut entries = Vec::new();
+        for (entry, data_type, data) in values {
+            offsets[entry as usize] = entries.len() as u32;
+            entries.extend_from_slice(&[8, 0, 0, 0, 0, 0, 0, 0, 8, 0, 0, data_type]);
+            entries.extend_from_slice(&data.to_le_bytes());
+        }
+
+        let mut config = vec![0; 64];
+        config[..4].copy_from_slice(&64u32.to_le_bytes());
+        if let Some(language) = language {
+            config[8..10].copy_from_slice(&language.as_bytes()[..2]);
+        }
+        let mut header = vec![type_id, 0, 0, 0];
+        header.extend(words(&[count, 8 + 12 + 64 + 4 * count]));
+        header.extend(config);
+        let mut body = words(&offsets);
+        body.extend(entries);
+        package.extend(chunk(0x0201, &header, &body));
+    }
+
+    let mut header = words(&[0x7f]);
+    header.extend(vec![0; 256]);
+    header.extend(words(&[0, 0, 0, 0, 0]));
+    let mut table = string_pool(&strings, true);
+    table.extend(chunk(0x0200, &header, &package));
+    chunk(0x0002, &words(&[1]), &table)
+}
+
+/// A ZIP archive of `entries` of name, content and whether to compress it
+pub fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut directory = Vec::new();
+    for (name, content, deflate) in entries {
+        let (method, stored) = if *deflate {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(content).unwrap();
+            (8u16, encoder.finish().unwrap())
+        } else {
+            (0u16, content.to_vec())
+        };
+        let local_header = data.len() as u32;
+        // version, flags, method, time, date, then CRC, sizes, name and extra length
+        let mut fields = Vec::new();
+        for field in [20u16, 0, method, 0, 0] {
+            fields.extend_from_slice(&field.to_le_bytes());
+        }
+        fields.extend(words(&[0, stored.len() as u32, content.len() as u32]));
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&[0, 0]);
+
+        data.extend(words(&[0x0403_4b50]));
+        data.extend_from_slice(&fields);
+        data.extend_from_slice(name.as_bytes());
+        data.extend(stored);
+
+        directory.extend(words(&[0x0201_4b50]));
+        directory.extend_from_slice(&20u16.to_le_bytes());
+        directory.extend_from_slice(&fields);
+        // comment length, disk, internal and external attributes, local header offset
+        directory.extend_from_slice(&[0; 6]);
+        directory.extend(words(&[0, local_header]));
+        directory.extend_from_slice(name.as_bytes());
+    }
+
+    let directory_offset = data.len() as u32;
+    data.extend_from_slice(&directory);
+    data.extend(words(&[0x0605_4b50, 0]));
+    for count in [entries.len() as u16; 2] {
+        data.extend_from_slice(&count.to_le_bytes());
+    }
+    data.extend(words(&[directory.len() as u32, directory_offset]));
+    data.extend_from_slice(&[0, 0]);
+    data
+}
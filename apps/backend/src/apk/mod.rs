@@ -0,0 +1,443 @@
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+use thiserror::Error;
+
+pub mod arsc;
+pub mod axml;
+pub mod bundle;
+#[cfg(test)]
+pub(crate) mod fixtures;
+pub mod res;
+pub mod zip;
+
+use arsc::ResourceTable;
+use axml::XmlElement;
+use bundle::Bundle;
+use res::Value;
+use zip::ZipArchive;
+
+const MANIFEST_ENTRY: &str = "AndroidManifest.xml";
+const RESOURCES_ENTRY: &str = "resources.arsc";
+/// Largest manifest and resource table read, so a crafted APK cannot exhaust memory
+const MAX_MANIFEST_SIZE: u64 = 16 * 1024 * 1024;
+const MAX_RESOURCES_SIZE: u64 = 256 * 1024 * 1024;
+
+const MAIN_ACTION: &str = "android.intent.action.MAIN";
+const LAUNCHER_CATEGORY: &str = "android.intent.category.LAUNCHER";
+
+/// Resource IDs of the `android:` attributes read from the manifest
+mod attr {
+    pub const NAME: u32 = 0x0101_0003;
+    pub const ENABLED: u32 = 0x0101_000e;
+    pub const MIN_SDK_VERSION: u32 = 0x0101_020c;
+    pub const VERSION_CODE: u32 = 0x0101_021b;
+    pub const VERSION_NAME: u32 = 0x0101_021c;
+    pub const TARGET_SDK_VERSION: u32 = 0x0101_0270;
+    pub const VERSION_CODE_MAJOR: u32 = 0x0101_0576;
+}
+
+#[derive(Error, Debug)]
+pub enum ApkError {
+    #[error("Failed to read APK: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid ZIP archive: {0}")]
+    InvalidZip(String),
+    #[error("The APK has no {0}")]
+    MissingEntry(String),
+    #[error("Malformed binary resource: {0}")]
+    Malformed(String),
+    #[error("The manifest does not declare a package")]
+    MissingPackage,
+    #[error("Invalid APK bundle: {0}")]
+    InvalidBundle(String),
+}
+
+/// What an APK's `AndroidManifest.xml` says about the app
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApkManifest {
+    pub package: String,
+    /// `versionCode`, including `versionCodeMajor` in the upper 32 bits
+    pub version_code: Option<i64>,
+    pub version_name: Option<String>,
+    pub min_sdk: Option<u32>,
+    pub target_sdk: Option<u32>,
+    #[serde(default)]
+    pub permissions: Vec<String>,
+    /// Fully qualified name of the activity started from the launcher
+    pub launcher_activity: Option<String>,
+    /// Name of the split for split APKs, e.g. `config.arm64_v8a`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<String>,
+}
+
+impl ApkManifest {
+    /// Read the manifest of a binary XML `AndroidManifest.xml`, resolving resource
+    /// references with the APK's resource table if there is one
+    pub fn parse(manifest: &[u8], resources: Option<&ResourceTable>) -> Result<Self, ApkError> {
+        let root = axml::parse(manifest)?;
+        if root.name != "manifest" {
+            return Err(ApkError::Malformed(format!(
+                "the root element is <{}>, not <manifest>",
+                root.name
+            )));
+        }
+        let resolve = |value: &Value| match resources {
+            Some(resources) => resources.resolve(value),
+            None => value.clone(),
+        };
+        let text = |value: Option<&Value>| match resolve(value?) {
+            Value::String(s) => Some(s),
+            Value::Int(i) => Some(i.to_string()),
+            _ => None,
+        };
+        let int = |value: Option<&Value>| match resolve(value?) {
+            Value::Int(i) => Some(i),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+
+        let package = text(root.attribute("package"))
+            .filter(|package| !package.is_empty())
+            .ok_or(ApkError::MissingPackage)?;
+
+        // The version code is unsigned; versionCodeMajor extends it to 64 bits
+        let version_code = int(root.android_attribute(attr::VERSION_CODE, "versionCode")).map(|code| {
+            let major = int(root.android_attribute(attr::VERSION_CODE_MAJOR, "versionCodeMajor"))
+                .unwrap_or(0);
+            ((major as u32 as i64) << 32) | code as u32 as i64
+        });
+        let version_name = text(root.android_attribute(attr::VERSION_NAME, "versionName"));
+
+        let uses_sdk = root.children_named("uses-sdk").next();
+        let sdk = |id, name| {
+            uses_sdk
+                .and_then(|uses_sdk| int(uses_sdk.android_attribute(id, name)))
+                .and_then(|sdk| u32::try_from(sdk).ok())
+        };
+        let min_sdk = sdk(attr::MIN_SDK_VERSION, "minSdkVersion");
+        // Apps target their minimum SDK unless they say otherwise
+        let target_sdk = sdk(attr::TARGET_SDK_VERSION, "targetSdkVersion").or(min_sdk);
+
+        let mut permissions: Vec<String> = Vec::new();
+        for element in &root.children {
+            if !element.name.starts_with("uses-permission") {
+                continue;
+            }
+            if let Some(permission) = text(element.android_attribute(attr::NAME, "name")) {
+                if !permissions.contains(&permission) {
+                    permissions.push(permission);
+                }
+            }
+        }
+
+        let launcher_activity = root
+            .children_named("application")
+            .flat_map(|application| application.children.iter())
+            .filter(|component| component.name == "activity" || component.name == "activity-alias")
+            .filter(|component| {
+                !matches!(
+                    component.android_attribute(attr::ENABLED, "enabled").map(&resolve),
+                    Some(Value::Bool(false))
+                )
+            })
+            .find(|component| is_launcher(component, &text))
+            .and_then(|component| text(component.android_attribute(attr::NAME, "name")))
+            .map(|name| qualify(&package, &name));
+        let split = text(root.attribute("split")).filter(|split| !split.is_empty());
+
+        Ok(Self {
+            package,
+            version_code,
+            version_name,
+            min_sdk,
+            target_sdk,
+            permissions,
+            launcher_activity,
+            split,
+        })
+    }
+}
+
+/// Whether an activity has an intent filter for the MAIN action in the LAUNCHER category
+fn is_launcher(component: &XmlElement, text: &impl Fn(Option<&Value>) -> Option<String>) -> bool {
+    let declares = |filter: &XmlElement, element: &str, name: &str| {
+        filter
+            .children_named(element)
+            .any(|child| text(child.android_attribute(attr::NAME, "name")).as_deref() == Some(name))
+    };
+    component
+        .children_named("intent-filter")
+        .any(|filter| declares(filter, "action", MAIN_ACTION) && declares(filter, "category", LAUNCHER_CATEGORY))
+}
+
+/// Turn a class name relative to the package, like `.MainActivity`, into a fully
+/// qualified one
+fn qualify(package: &str, class: &str) -> String {
+    if class.starts_with('.') {
+        format!("{}{}", package, class)
+    } else if !class.contains('.') {
+        format!("{}.{}", package, class)
+    } else {
+        class.to_string()
+    }
+}
+
+/// Read the manifest of an APK file, or of the base APK of an APK bundle.
+///
+/// The base APK of a bundle is extracted to a temporary file in `tmp_dir`.
+pub fn read_manifest<P: AsRef<Path>>(path: P, tmp_dir: &Path) -> Result<ApkManifest, ApkError> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    match Bundle::read(&mut archive)? {
+        Some(bundle) => read_base_manifest(&mut archive, &bundle, tmp_dir),
+        None => read_apk_manifest(&mut archive),
+    }
+}
+
+/// Read the manifest of a plain APK
+fn read_apk_manifest<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Result<ApkManifest, ApkError> {
+    let manifest = archive
+        .read(MANIFEST_ENTRY, MAX_MANIFEST_SIZE)?
+        .ok_or_else(|| ApkError::MissingEntry(MANIFEST_ENTRY.to_string()))?;
+
+    // Without the resource table, references simply stay unresolved
+    let resources = match archive.read(RESOURCES_ENTRY, MAX_RESOURCES_SIZE) {
+        Ok(Some(data)) => ResourceTable::parse(&data)
+            .map_err(|e| warn!("Ignoring unreadable {}: {}", RESOURCES_ENTRY, e))
+            .ok(),
+        Ok(None) => None,
+        Err(e) => {
+            warn!("Ignoring unreadable {}: {}", RESOURCES_ENTRY, e);
+            None
+        }
+    };
+
+    ApkManifest::parse(&manifest, resources.as_ref())
+}
+
+/// The base APK is a ZIP inside the bundle, so it is read from a temporary copy.
+/// A base APK that is a bundle itself is rejected rather than unpacked in turn.
+fn read_base_manifest<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    bundle: &Bundle,
+    tmp_dir: &Path,
+) -> Result<ApkManifest, ApkError> {
+    let base = bundle
+        .base()
+        .ok_or_else(|| ApkError::InvalidBundle("there is no base APK".to_string()))?;
+    std::fs::create_dir_all(tmp_dir)?;
+    let path = tmp_dir.join(format!("base-{}.apk", uuid::Uuid::new_v4()));
+    let result = File::create(&path)
+        .map_err(ApkError::from)
+        .and_then(|mut file| archive.copy_to(&base.entry, &mut file))
+        .and_then(|_| {
+            let mut apk = ZipArchive::new(BufReader::new(File::open(&path)?))?;
+            if Bundle::read(&mut apk)?.is_some() {
+                return Err(ApkError::InvalidBundle(format!(
+                    "the base APK {} is itself a bundle",
+                    base.entry
+                )));
+            }
+            read_apk_manifest(&mut apk)
+        });
+    let _ = std::fs::remove_file(&path);
+
+    let manifest = result?;
+    match &bundle.package {
+        Some(package) if *package != manifest.package => Err(ApkError::InvalidBundle(format!(
+            "the bundle is for {} but its base APK for {}",
+            package, manifest.package
+        ))),
+        _ => Ok(manifest),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fixtures::{resource_table, zip, Attr, Axml};
+
+    /// A manifest like the one aapt2 compiles for a typical app
+    fn sample_manifest() -> Axml {
+        Axml::new()
+            .start(
+                "manifest",
+                &[
+                    ("versionCode", Attr::Int(10203)),
+                    ("versionName", Attr::Str("1.2.3")),
+                    ("package", Attr::Str("com.example.app")),
+                ],
+            )
+            .start("uses-sdk", &[("minSdkVersion", Attr::Int(24)), ("targetSdkVersion", Attr::Int(34))])
+            .end()
+            .start("uses-permission", &[("name", Attr::Str("android.permission.INTERNET"))])
+            .end()
+            .start("uses-permission-sdk-23", &[("name", Attr::Str("android.permission.CAMERA"))])
+            .end()
+            .start("uses-permission", &[("name", Attr::Str("android.permission.INTERNET"))])
+            .end()
+            .start("application", &[("label", Attr::Str("Example"))])
+            // A service and a disabled launcher activity come first
+            .start("service", &[("name", Attr::Str(".SyncService"))])
+            .end()
+            .start("activity", &[("name", Attr::Str(".OldMain")), ("enabled", Attr::Bool(false))])
+            .launcher_filter()
+            .end()
+            .start("activity", &[("name", Attr::Str(".SettingsActivity"))])
+            .start("intent-filter", &[])
+            .start("action", &[("name", Attr::Str(MAIN_ACTION))])
+            .end()
+            .end()
+            .end()
+            .start("activity", &[("name", Attr::Str(".ui.MainActivity"))])
+            .launcher_filter()
+            .end()
+            .end()
+            .end()
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = ApkManifest::parse(&sample_manifest().finish(), None).unwrap();
+        assert_eq!(
+            manifest,
+            ApkManifest {
+                package: "com.example.app".to_string(),
+                version_code: Some(10203),
+                version_name: Some("1.2.3".to_string()),
+                min_sdk: Some(24),
+                target_sdk: Some(34),
+                permissions: vec![
+                    "android.permission.INTERNET".to_string(),
+                    "android.permission.CAMERA".to_string(),
+                ],
+                launcher_activity: Some("com.example.app.ui.MainActivity".to_string()),
+                split: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_minimal_manifest() {
+        // UTF-8 string pool, as written by aapt2, and no optional attributes
+        let data = Axml::new()
+            .utf8()
+            .start("manifest", &[("package", Attr::Str("org.minimal"))])
+            .start("uses-sdk", &[("minSdkVersion", Attr::Str("21"))])
+            .end()
+            .start("application", &[])
+            .start("activity-alias", &[("name", Attr::Str("Launcher"))])
+            .launcher_filter()
+            .end()
+            .end()
+            .end()
+            .finish();
+        let manifest = ApkManifest::parse(&data, None).unwrap();
+        assert_eq!(manifest.package, "org.minimal");
+        assert_eq!(manifest.version_code, None);
+        assert_eq!(manifest.version_name, None);
+        assert_eq!(manifest.min_sdk, Some(21));
+        assert_eq!(manifest.target_sdk, Some(21));
+        assert!(manifest.permissions.is_empty());
+        assert_eq!(manifest.launcher_activity.as_deref(), Some("org.minimal.Launcher"));
+    }
+
+    #[test]
+    fn test_parse_manifest_with_resources() {
+        let data = Axml::new()
+            .start(
+                "manifest",
+                &[
+                    ("package", Attr::Str("com.example.app")),
+                    ("versionCode", Attr::Int(-1)),
+                    ("versionCodeMajor", Attr::Ref(0x7f03_0000)),
+                    ("versionName", Attr::Ref(0x7f02_0001)),
+                ],
+            )
+            .end()
+            .finish();
+        let resources = ResourceTable::parse(&resource_table(&[
+            (0x7f02_0001, None, Value::String("2.0-beta".to_string())),
+            (0x7f03_0000, None, Value::Int(1)),
+        ]))
+        .unwrap();
+
+        let manifest = ApkManifest::parse(&data, Some(&resources)).unwrap();
+        assert_eq!(manifest.version_name.as_deref(), Some("2.0-beta"));
+        assert_eq!(manifest.version_code, Some((1 << 32) | 0xffff_ffff));
+
+        // Unresolved references are left out
+        let manifest = ApkManifest::parse(&data, None).unwrap();
+        assert_eq!(manifest.version_name, None);
+        assert_eq!(manifest.version_code, Some(0xffff_ffff));
+    }
+
+    #[test]
+    fn test_manifest_requires_package() {
+        let data = Axml::new().start("manifest", &[]).end().finish();
+        assert!(matches!(ApkManifest::parse(&data, None), Err(ApkError::MissingPackage)));
+        let data = Axml::new().start("resources", &[]).end().finish();
+        assert!(matches!(ApkManifest::parse(&data, None), Err(ApkError::Malformed(_))));
+    }
+
+    #[test]
+    fn test_read_manifest() {
+        let dir = std::env::temp_dir().join(format!("apk-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let apk = dir.join("app.apk");
+        let manifest = sample_manifest().finish();
+        let resources = resource_table(&[]);
+        std::fs::write(
+            &apk,
+            zip(&[
+                (MANIFEST_ENTRY, manifest.as_slice(), true),
+                (RESOURCES_ENTRY, resources.as_slice(), false),
+                ("classes.dex", b"dex\n035\0".as_slice(), true),
+            ]),
+        )
+        .unwrap();
+        let parsed = read_manifest(&apk, &dir).unwrap();
+        assert_eq!(parsed.package, "com.example.app");
+        assert_eq!(parsed.launcher_activity.as_deref(), Some("com.example.app.ui.MainActivity"));
+
+        // The manifest of a bundle is the one of its base APK
+        let base = std::fs::read(&apk).unwrap();
+        let split = Axml::new()
+            .start("manifest", &[("package", Attr::Str("com.example.app")), ("split", Attr::Str("config.x86_64"))])
+            .end()
+            .finish();
+        let split = zip(&[(MANIFEST_ENTRY, split.as_slice(), true)]);
+        let bundle = dir.join("app.apks");
+        std::fs::write(
+            &bundle,
+            zip(&[
+                ("splits/base-master.apk", base.as_slice(), false),
+                ("splits/base-x86_64.apk", split.as_slice(), false),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(read_manifest(&bundle, &dir).unwrap(), parsed);
+        let split_apk = dir.join("split.apk");
+        std::fs::write(&split_apk, &split).unwrap();
+        assert_eq!(read_manifest(&split_apk, &dir).unwrap().split.as_deref(), Some("config.x86_64"));
+
+        let empty = dir.join("empty.apk");
+        std::fs::write(&empty, zip(&[("classes.dex", b"dex\n035\0".as_slice(), false)])).unwrap();
+        assert!(matches!(read_manifest(&empty, &dir), Err(ApkError::MissingEntry(_))));
+
+        // A bundle whose base APK is another bundle is not unpacked any further
+        let nested = dir.join("nested.apks");
+        let inner = std::fs::read(&bundle).unwrap();
+        std::fs::write(&nested, zip(&[("splits/base-master.apk", inner.as_slice(), false)])).unwrap();
+        assert!(matches!(read_manifest(&nested, &dir), Err(ApkError::InvalidBundle(_))));
+        // The extracted base APKs are removed again
+        assert!(std::fs::read_dir(&dir)
+            .unwrap()
+            .all(|entry| !entry.unwrap().file_name().to_string_lossy().starts_with("base-")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
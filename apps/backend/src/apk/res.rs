@@ -0,0 +1,209 @@
+use super::ApkError;
+
+pub const RES_STRING_POOL_TYPE: u16 = 0x0001;
+pub const RES_TABLE_TYPE: u16 = 0x0002;
+pub const RES_XML_TYPE: u16 = 0x0003;
+pub const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+pub const RES_XML_END_ELEMENT_TYPE: u16 = 0x0103;
+pub const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+pub const RES_TABLE_PACKAGE_TYPE: u16 = 0x0200;
+pub const RES_TABLE_TYPE_TYPE: u16 = 0x0201;
+
+/// Index used for "no string" and "no entry"
+pub const NO_INDEX: u32 = 0xffff_ffff;
+
+/// Flag of string pools whose strings are UTF-8 rather than UTF-16
+const UTF8_FLAG: u32 = 0x100;
+
+fn bytes_at(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ApkError> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| ApkError::Malformed(format!("unexpected end of data at offset {}", offset)))
+}
+
+pub fn u8_at(data: &[u8], offset: usize) -> Result<u8, ApkError> {
+    Ok(bytes_at(data, offset, 1)?[0])
+}
+
+pub fn u16_at(data: &[u8], offset: usize) -> Result<u16, ApkError> {
+    let bytes = bytes_at(data, offset, 2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn u32_at(data: &[u8], offset: usize) -> Result<u32, ApkError> {
+    let bytes = bytes_at(data, offset, 4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The header every chunk of compiled resources starts with
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkHeader {
+    pub kind: u16,
+    pub offset: usize,
+    pub header_size: usize,
+    pub size: usize,
+}
+
+impl ChunkHeader {
+    pub fn read(data: &[u8], offset: usize) -> Result<Self, ApkError> {
+        let header = Self {
+            kind: u16_at(data, offset)?,
+            offset,
+            header_size: u16_at(data, offset + 2)? as usize,
+            size: u32_at(data, offset + 4)? as usize,
+        };
+        if header.header_size < 8 || header.size < header.header_size || header.end() > data.len() {
+            return Err(ApkError::Malformed(format!(
+                "invalid chunk of type {:#06x} at offset {}",
+                header.kind, offset
+            )));
+        }
+        Ok(header)
+    }
+
+    /// Offset of the chunk's content, after its header
+    pub fn body(&self) -> usize {
+        self.offset + self.header_size
+    }
+
+    pub fn end(&self) -> usize {
+        self.offset + self.size
+    }
+
+    /// The chunks contained in this chunk's content
+    pub fn children(&self, data: &[u8]) -> Result<Vec<ChunkHeader>, ApkError> {
+        let mut chunks = Vec::new();
+        let mut offset = self.body();
+        while offset + 8 <= self.end() {
+            let chunk = ChunkHeader::read(data, offset)?;
+            offset = chunk.end();
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+}
+
+/// The strings of an XML document or resource table, which everything else refers
+/// to by index
+#[derive(Debug, Clone, Default)]
+pub struct StringPool {
+    strings: Vec<String>,
+}
+
+impl StringPool {
+    pub fn parse(data: &[u8], chunk: &ChunkHeader) -> Result<Self, ApkError> {
+        let count = u32_at(data, chunk.offset + 8)? as usize;
+        let flags = u32_at(data, chunk.offset + 16)?;
+        let strings_start = chunk.offset + u32_at(data, chunk.offset + 20)? as usize;
+        // Only look inside the chunk
+        let data = &data[..chunk.end()];
+
+        let strings = (0..count)
+            .map(|i| {
+                let offset = strings_start + u32_at(data, chunk.body() + i * 4)? as usize;
+                if flags & UTF8_FLAG != 0 {
+                    read_utf8(data, offset)
+                } else {
+                    read_utf16(data, offset)
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self { strings })
+    }
+
+    pub fn get(&self, index: u32) -> Option<&str> {
+        self.strings.get(index as usize).map(String::as_str)
+    }
+}
+
+/// Read a UTF-8 pool string: its length in UTF-16 units, its length in bytes, the bytes
+fn read_utf8(data: &[u8], offset: usize) -> Result<String, ApkError> {
+    let length = |offset: usize| -> Result<(usize, usize), ApkError> {
+        let first = u8_at(data, offset)? as usize;
+        if first & 0x80 != 0 {
+            Ok((((first & 0x7f) << 8) | u8_at(data, offset + 1)? as usize, 2))
+        } else {
+            Ok((first, 1))
+        }
+    };
+    let (_, skip) = length(offset)?;
+    let (len, skip_len) = length(offset + skip)?;
+    let bytes = bytes_at(data, offset + skip + skip_len, len)?;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Read a UTF-16 pool string: its length in units, the units
+fn read_utf16(data: &[u8], offset: usize) -> Result<String, ApkError> {
+    let first = u16_at(data, offset)? as usize;
+    let (len, skip) = if first & 0x8000 != 0 {
+        (((first & 0x7fff) << 16) | u16_at(data, offset + 2)? as usize, 4)
+    } else {
+        (first, 2)
+    };
+    let bytes = bytes_at(data, offset + skip, len * 2)?;
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+/// A typed value of an XML attribute or a resource
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    /// Reference to another resource by ID, like `@string/app_name`
+    Reference(u32),
+    String(String),
+    Int(i32),
+    Bool(bool),
+    /// A value of a type the manifest never needs, like a dimension or color
+    Other { data_type: u8, data: u32 },
+}
+
+impl Value {
+    pub fn parse(data_type: u8, data: u32, strings: &StringPool) -> Self {
+        match data_type {
+            0x00 => Value::Null,
+            // Plain and dynamic references
+            0x01 | 0x07 => Value::Reference(data),
+            0x03 => strings
+                .get(data)
+                .map(|s| Value::String(s.to_string()))
+                .unwrap_or(Value::Null),
+            0x10 | 0x11 => Value::Int(data as i32),
+            0x12 => Value::Bool(data != 0),
+            _ => Value::Other { data_type, data },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::string_pool;
+
+    #[test]
+    fn test_string_pool() {
+        let long = "x".repeat(200);
+        for utf8 in [false, true] {
+            let strings = ["package", "", "ünïcødé", long.as_str()];
+            let data = string_pool(&strings, utf8);
+            let chunk = ChunkHeader::read(&data, 0).unwrap();
+            assert_eq!(chunk.kind, RES_STRING_POOL_TYPE);
+            let pool = StringPool::parse(&data, &chunk).unwrap();
+            for (i, s) in strings.iter().enumerate() {
+                assert_eq!(pool.get(i as u32), Some(*s));
+            }
+            assert_eq!(pool.get(4), None);
+        }
+    }
+
+    #[test]
+    fn test_truncated_data() {
+        let data = string_pool(&["package"], false);
+        assert!(ChunkHeader::read(&data[..data.len() - 1], 0).is_err());
+        assert!(u32_at(&data, data.len() - 3).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use flate2::read::DeflateDecoder;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use super::res::{u16_at, u32_at};
+use super::ApkError;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x0403_4b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x0605_4b50;
+/// Size of the end of central directory record without its trailing comment
+const END_OF_CENTRAL_DIRECTORY_SIZE: u64 = 22;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+#[derive(Debug, Clone)]
+struct ZipEntry {
+    name: String,
+    method: u16,
+    compressed_size: u64,
+    size: u64,
+    local_header: u64,
+}
+
+/// Just enough of a ZIP reader to extract single files from an APK
+#[derive(Debug)]
+pub struct ZipArchive<R> {
+    reader: R,
+    entries: Vec<ZipEntry>,
+}
+
+impl<R: Read + Seek> ZipArchive<R> {
+    /// Read the archive's central directory
+    pub fn new(mut reader: R) -> Result<Self, ApkError> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        if len < END_OF_CENTRAL_DIRECTORY_SIZE {
+            return Err(ApkError::InvalidZip("the file is too short".to_string()));
+        }
+
+        // The end of central directory record is followed by a comment of at most 64 KiB
+        let tail_len = len.min(END_OF_CENTRAL_DIRECTORY_SIZE + u16::MAX as u64);
+        reader.seek(SeekFrom::Start(len - tail_len))?;
+        let mut tail = vec![0; tail_len as usize];
+        reader.read_exact(&mut tail)?;
+        let end = (0..=tail.len() - END_OF_CENTRAL_DIRECTORY_SIZE as usize)
+            .rev()
+            .find(|&offset| u32_at(&tail, offset).ok() == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE))
+            .ok_or_else(|| ApkError::InvalidZip("no end of central directory record".to_string()))?;
+
+        let count = u16_at(&tail, end + 10)?;
+        let directory_size = u32_at(&tail, end + 12)?;
+        let directory_offset = u32_at(&tail, end + 16)?;
+        if count == u16::MAX || directory_offset == u32::MAX {
+            return Err(ApkError::InvalidZip("ZIP64 archives are not supported".to_string()));
+        }
+        if directory_offset as u64 + directory_size as u64 > len {
+            return Err(ApkError::InvalidZip("the central directory is out of bounds".to_string()));
+        }
+
+        reader.seek(SeekFrom::Start(directory_offset as u64))?;
+        let mut directory = vec![0; directory_size as usize];
+        reader.read_exact(&mut directory)?;
+
+        let mut entries = Vec::with_capacity(count as usize);
+        let mut offset = 0;
+        for _ in 0..count {
+            if u32_at(&directory, offset)? != CENTRAL_HEADER_SIGNATURE {
+                return Err(ApkError::InvalidZip("corrupt central directory".to_string()));
+            }
+            let name_len = u16_at(&directory, offset + 28)? as usize;
+            let extra_len = u16_at(&directory, offset + 30)? as usize;
+            let comment_len = u16_at(&directory, offset + 32)? as usize;
+            let name = directory
+                .get(offset + 46..offset + 46 + name_len)
+                .ok_or_else(|| ApkError::InvalidZip("corrupt central directory".to_string()))?;
+            entries.push(ZipEntry {
+                name: String::from_utf8_lossy(name).into_owned(),
+                method: u16_at(&directory, offset + 10)?,
+                compressed_size: u32_at(&directory, offset + 20)? as u64,
+                size: u32_at(&directory, offset + 24)? as u64,
+                local_header: u32_at(&directory, offset + 42)? as u64,
+            });
+            offset += 46 + name_len + extra_len + comment_len;
+        }
+
+        Ok(Self { reader, entries })
+    }
+
+    /// Names of the files in the archive
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    fn entry(&self, name: &str) -> Option<ZipEntry> {
+        self.entries.iter().find(|entry| entry.name == name).cloned()
+    }
+
+    /// A reader of the uncompressed content of an entry
+    fn open(&mut self, entry: &ZipEntry) -> Result<Box<dyn Read + '_>, ApkError> {
+        // The local header repeats the name and may have a different extra field
+        let mut header = [0; 30];
+        self.reader.seek(SeekFrom::Start(entry.local_header))?;
+        self.reader.read_exact(&mut header)?;
+        if u32_at(&header, 0)? != LOCAL_HEADER_SIGNATURE {
+            return Err(ApkError::InvalidZip(format!("corrupt local header of {}", entry.name)));
+        }
+        let skip = u16_at(&header, 26)? as i64 + u16_at(&header, 28)? as i64;
+        self.reader.seek(SeekFrom::Current(skip))?;
+
+        let compressed = (&mut self.reader).take(entry.compressed_size);
+        match entry.method {
+            STORED => Ok(Box::new(compressed)),
+            DEFLATED => Ok(Box::new(DeflateDecoder::new(compressed))),
+            method => Err(ApkError::InvalidZip(format!(
+                "{} uses unsupported compression method {}",
+                entry.name, method
+            ))),
+        }
+    }
+
+    /// Extract a file, refusing files larger than `limit` bytes.
+    ///
+    /// Returns `None` when the archive has no file of that name.
+    pub fn read(&mut self, name: &str, limit: u64) -> Result<Option<Vec<u8>>, ApkError> {
+        let Some(entry) = self.entry(name) else {
+            return Ok(None);
+        };
+        if entry.size > limit {
+            return Err(ApkError::InvalidZip(format!(
+                "{} is larger than {} bytes",
+                name, limit
+            )));
+        }
+
+        let mut content = Vec::with_capacity(entry.size as usize);
+        // Never trust the declared size when inflating
+        self.open(&entry)?.take(limit + 1).read_to_end(&mut content)?;
+        if content.len() as u64 != entry.size {
+            return Err(ApkError::InvalidZip(format!("{} has the wrong size", name)));
+        }
+        Ok(Some(content))
+    }
+
+    /// Extract a file into `writer` without holding it in memory, for entries like
+    /// the APKs of a bundle.
+    ///
+    /// Returns `false` when the archive has no file of that name.
+    pub fn copy_to<W: Write>(&mut self, name: &str, writer: &mut W) -> Result<bool, ApkError> {
+        let Some(entry) = self.entry(name) else {
+            return Ok(false);
+        };
+        let copied = io::copy(&mut self.open(&entry)?.take(entry.size + 1), writer)?;
+        if copied != entry.size {
+            return Err(ApkError::InvalidZip(format!("{} has the wrong size", name)));
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::zip;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_entries() {
+        let manifest = b"<manifest/>".repeat(100);
+        let data = zip(&[
+            ("AndroidManifest.xml", manifest.as_slice(), true),
+            ("classes.dex", b"dex\n035".as_slice(), false),
+        ]);
+        let mut archive = ZipArchive::new(Cursor::new(data)).unwrap();
+        assert_eq!(
+            archive.names().collect::<Vec<_>>(),
+            vec!["AndroidManifest.xml", "classes.dex"]
+        );
+        assert_eq!(archive.read("AndroidManifest.xml", 1 << 20).unwrap(), Some(manifest.clone()));
+        assert_eq!(archive.read("classes.dex", 1 << 20).unwrap(), Some(b"dex\n035".to_vec()));
+        assert_eq!(archive.read("resources.arsc", 1 << 20).unwrap(), None);
+        assert!(archive.read("AndroidManifest.xml", 100).is_err());
+
+        let mut extracted = Vec::new();
+        assert!(archive.copy_to("AndroidManifest.xml", &mut extracted).unwrap());
+        assert_eq!(extracted, manifest);
+        assert!(!archive.copy_to("resources.arsc", &mut extracted).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_non_zip_files() {
+        assert!(ZipArchive::new(Cursor::new(b"not a zip".to_vec())).is_err());
+        assert!(ZipArchive::new(Cursor::new(vec![0; 1000])).is_err());
+    }
+}
@@ -0,0 +1,359 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+use uuid::Uuid;
+
+use super::{ArtifactError, ArtifactStore};
+use crate::apk::bundle::{select_splits, Bundle, BundleObb, DeviceProfile, SplitSelection};
+use crate::apk::zip::ZipArchive;
+use crate::apk::ApkError;
+use crate::db::artifact::ArtifactRecord;
+
+/// Where the APK of a split is found
+#[derive(Debug, Clone)]
+enum SplitSource {
+    /// A stored artifact
+    Artifact(PathBuf),
+    /// An entry of the bundle being installed
+    Entry(String),
+}
+
+#[derive(Debug, Clone)]
+struct PlannedSplit {
+    name: String,
+    source: SplitSource,
+}
+
+/// The APKs and expansion files an installation of an artifact is made of, before
+/// the splits for the device are chosen
+#[derive(Debug, Clone)]
+pub struct InstallPlan {
+    /// The artifact the installation was requested for
+    pub artifact: ArtifactRecord,
+    /// Package of the app, when the artifact's manifest or bundle names it
+    pub package: Option<String>,
+    /// IDs of all artifacts taking part, the requested one first
+    pub artifact_ids: Vec<String>,
+    bundle: Option<PathBuf>,
+    splits: Vec<PlannedSplit>,
+    obbs: Vec<BundleObb>,
+}
+
+impl InstallPlan {
+    /// Whether there are splits to choose from, which needs the device's profile
+    pub fn has_splits(&self) -> bool {
+        self.splits.len() > 1
+    }
+
+    /// Choose the splits to install on a device. Without a profile, configuration
+    /// splits cannot be matched, so only the base, feature and language splits are.
+    pub fn select(&self, device: Option<&DeviceProfile>) -> SplitSelection {
+        let names: Vec<&str> = self.splits.iter().map(|split| split.name.as_str()).collect();
+        select_splits(&names, device.unwrap_or(&DeviceProfile::default()))
+    }
+}
+
+/// Files ready to be handed to `adb`, extracted from a bundle if needed
+#[derive(Debug, Default)]
+pub struct PreparedInstall {
+    /// The APKs to install together, base first
+    pub apks: Vec<PathBuf>,
+    /// Expansion files with their names in the app's OBB directory
+    pub obbs: Vec<(String, PathBuf)>,
+    /// Directory holding the extracted files
+    scratch: Option<PathBuf>,
+}
+
+impl PreparedInstall {
+    /// Remove the files extracted for the installation
+    pub async fn cleanup(self) {
+        if let Some(scratch) = self.scratch {
+            let _ = fs::remove_dir_all(scratch).await;
+        }
+    }
+}
+
+/// Read the bundle layout of a stored artifact off the async runtime.
+///
+/// Anything that is not a readable bundle is installed as a plain APK and left for
+/// the package manager to judge; only bundles with missing APKs are rejected.
+async fn read_bundle(path: PathBuf) -> Result<Option<Bundle>, ArtifactError> {
+    let result = tokio::task::spawn_blocking(move || {
+        let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+        Bundle::read(&mut archive)
+    })
+    .await
+    .map_err(|e| ArtifactError::Io(std::io::Error::other(e)))?;
+    match result {
+        Ok(bundle) => Ok(bundle),
+        Err(e @ ApkError::InvalidBundle(_)) => Err(ArtifactError::InvalidUpload(e.to_string())),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Extract entries of a bundle to files, as pairs of entry and destination
+fn extract(bundle: &Path, entries: &[(String, PathBuf)]) -> Result<(), ApkError> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(bundle)?))?;
+    for (entry, dest) in entries {
+        let mut file = File::create(dest)?;
+        if !archive.copy_to(entry, &mut file)? {
+            return Err(ApkError::MissingEntry(entry.clone()));
+        }
+    }
+    Ok(())
+}
+
+impl ArtifactStore {
+    /// Work out what installing an artifact takes: the APKs of a bundle, or a base
+    /// APK with separately uploaded split APKs of the same app
+    pub async fn plan_install(&self, id: &str, split_ids: &[String]) -> Result<InstallPlan, ArtifactError> {
+        let (artifact, path) = self.resolve(id).await?;
+        let manifest = artifact.manifest.as_ref().map(|manifest| &manifest.0);
+        if let Some(split) = manifest.and_then(|manifest| manifest.split.as_ref()) {
+            return Err(ArtifactError::InvalidUpload(format!(
+                "artifact {} is the split APK {}; install it along with its base APK",
+                id, split
+            )));
+        }
+        let mut package = manifest.map(|manifest| manifest.package.clone());
+        let mut artifact_ids = vec![artifact.id.clone()];
+
+        let bundle = read_bundle(path.clone()).await?;
+        let plan = match bundle {
+            Some(bundle) => {
+                if !split_ids.is_empty() {
+                    return Err(ArtifactError::InvalidUpload(
+                        "split APKs cannot be added to an APK bundle".to_string(),
+                    ));
+                }
+                package = bundle.package.or(package);
+                if !bundle.obbs.is_empty() && package.is_none() {
+                    return Err(ArtifactError::InvalidUpload(
+                        "the bundle has expansion files but its package is unknown".to_string(),
+                    ));
+                }
+                let splits = bundle
+                    .splits
+                    .into_iter()
+                    .map(|split| PlannedSplit {
+                        name: split.name,
+                        source: SplitSource::Entry(split.entry),
+                    })
+                    .collect();
+                InstallPlan {
+                    artifact,
+                    package,
+                    artifact_ids,
+                    bundle: Some(path),
+                    splits,
+                    obbs: bundle.obbs,
+                }
+            }
+            None => {
+                let mut splits = vec![PlannedSplit {
+                    name: "base".to_string(),
+                    source: SplitSource::Artifact(path),
+                }];
+                for split_id in split_ids {
+                    if artifact_ids.contains(split_id) {
+                        continue;
+                    }
+                    let (record, split_path) = self.resolve(split_id).await?;
+                    let split_manifest = record.manifest.as_ref().map(|manifest| &manifest.0);
+                    let name = split_manifest
+                        .and_then(|manifest| manifest.split.clone())
+                        .ok_or_else(|| {
+                            ArtifactError::InvalidUpload(format!("artifact {} is not a split APK", split_id))
+                        })?;
+                    let split_package = split_manifest.map(|manifest| &manifest.package);
+                    if package.is_some() && split_package != package.as_ref() {
+                        return Err(ArtifactError::InvalidUpload(format!(
+                            "split APK {} is not for the package {}",
+                            split_id,
+                            package.as_deref().unwrap_or_default()
+                        )));
+                    }
+                    splits.push(PlannedSplit {
+                        name,
+                        source: SplitSource::Artifact(split_path),
+                    });
+                    artifact_ids.push(record.id);
+                }
+                InstallPlan {
+                    artifact,
+                    package,
+                    artifact_ids,
+                    bundle: None,
+                    splits,
+                    obbs: Vec::new(),
+                }
+            }
+        };
+        Ok(plan)
+    }
+
+    /// Gather the files for the chosen splits, extracting them and the expansion
+    /// files of a bundle into a scratch directory under the store
+    pub async fn prepare_install(
+        &self,
+        plan: &InstallPlan,
+        selection: &SplitSelection,
+    ) -> Result<PreparedInstall, ArtifactError> {
+        let chosen = plan
+            .splits
+            .iter()
+            .filter(|split| selection.chosen.iter().any(|chosen| chosen.name == split.name));
+        let Some(bundle) = plan.bundle.clone() else {
+            let apks = chosen
+                .filter_map(|split| match &split.source {
+                    SplitSource::Artifact(path) => Some(path.clone()),
+                    SplitSource::Entry(_) => None,
+                })
+                .collect();
+            return Ok(PreparedInstall {
+                apks,
+                ..Default::default()
+            });
+        };
+
+        let scratch = self.dir.join("tmp").join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&scratch).await?;
+        let mut prepared = PreparedInstall {
+            scratch: Some(scratch.clone()),
+            ..Default::default()
+        };
+        let mut entries = Vec::new();
+        for (index, split) in chosen.enumerate() {
+            if let SplitSource::Entry(entry) = &split.source {
+                // adb requires the .apk extension; split names are not safe file names
+                let dest = scratch.join(format!("{}.apk", index));
+                entries.push((entry.clone(), dest.clone()));
+                prepared.apks.push(dest);
+            }
+        }
+        for (index, obb) in plan.obbs.iter().enumerate() {
+            let dest = scratch.join(format!("{}.obb", index));
+            entries.push((obb.entry.clone(), dest.clone()));
+            prepared.obbs.push((obb.file_name.clone(), dest));
+        }
+
+        let result = tokio::task::spawn_blocking(move || extract(&bundle, &entries)).await;
+        match result {
+            Ok(Ok(())) => Ok(prepared),
+            Ok(Err(e)) => {
+                prepared.cleanup().await;
+                Err(ArtifactError::InvalidUpload(e.to_string()))
+            }
+            Err(e) => {
+                prepared.cleanup().await;
+                Err(ArtifactError::Io(std::io::Error::other(e)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::{zip, Attr, Axml};
+    use crate::db;
+    use actix_web::web::Bytes;
+    use futures_util::stream;
+    use std::convert::Infallible;
+    use tokio::test;
+
+    async fn setup_store() -> ArtifactStore {
+        let pool = db::create_pool("sqlite::memory:").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("artifacts-{}", Uuid::new_v4()));
+        ArtifactStore::new(dir, pool)
+    }
+
+    async fn save(store: &ArtifactStore, data: Vec<u8>) -> ArtifactRecord {
+        let content = stream::iter([Ok::<_, Infallible>(Bytes::from(data))]);
+        store.save("app.apk", content).await.unwrap().0
+    }
+
+    fn apk(split: Option<&'static str>) -> Vec<u8> {
+        let mut attributes = vec![("package", Attr::Str("com.example.game"))];
+        if let Some(split) = split {
+            attributes.push(("split", Attr::Str(split)));
+        }
+        let manifest = Axml::new().start("manifest", &attributes).end().finish();
+        zip(&[("AndroidManifest.xml", manifest.as_slice(), true)])
+    }
+
+    fn device() -> DeviceProfile {
+        DeviceProfile {
+            abis: vec!["x86_64".to_string()],
+            density: 420,
+        }
+    }
+
+    #[test]
+    async fn test_install_bundle() {
+        let store = setup_store().await;
+        let base = apk(None);
+        let abi = apk(Some("config.x86_64"));
+        let bundle = zip(&[
+            ("manifest.json", br#"{"package_name": "com.example.game", "split_apks": [
+                {"file": "com.example.game.apk", "id": "base"},
+                {"file": "config.arm64_v8a.apk", "id": "config.arm64_v8a"},
+                {"file": "config.x86_64.apk", "id": "config.x86_64"}
+            ]}"#.as_slice(), true),
+            ("com.example.game.apk", base.as_slice(), false),
+            ("config.arm64_v8a.apk", b"PK".as_slice(), false),
+            ("config.x86_64.apk", abi.as_slice(), false),
+            ("Android/obb/com.example.game/main.1.com.example.game.obb", b"obb".as_slice(), true),
+        ]);
+        let record = save(&store, bundle).await;
+        // The artifact describes the app of the base APK
+        assert_eq!(record.manifest.as_ref().unwrap().0.package, "com.example.game");
+
+        let plan = store.plan_install(&record.id, &[]).await.unwrap();
+        assert!(plan.has_splits());
+        assert_eq!(plan.package.as_deref(), Some("com.example.game"));
+        let selection = plan.select(Some(&device()));
+        assert_eq!(selection.skipped, vec!["config.arm64_v8a"]);
+
+        let prepared = store.prepare_install(&plan, &selection).await.unwrap();
+        assert_eq!(prepared.apks.len(), 2);
+        assert_eq!(std::fs::read(&prepared.apks[0]).unwrap(), base);
+        assert_eq!(std::fs::read(&prepared.apks[1]).unwrap(), abi);
+        assert_eq!(prepared.obbs[0].0, "main.1.com.example.game.obb");
+        assert_eq!(std::fs::read(&prepared.obbs[0].1).unwrap(), b"obb");
+
+        let extracted = prepared.apks[0].clone();
+        prepared.cleanup().await;
+        assert!(!extracted.exists());
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+
+    #[test]
+    async fn test_install_separate_splits() {
+        let store = setup_store().await;
+        let base = save(&store, apk(None)).await;
+        let split = save(&store, apk(Some("config.x86_64"))).await;
+
+        let plan = store.plan_install(&base.id, std::slice::from_ref(&split.id)).await.unwrap();
+        assert_eq!(plan.artifact_ids, vec![base.id.clone(), split.id.clone()]);
+        let selection = plan.select(Some(&device()));
+        let prepared = store.prepare_install(&plan, &selection).await.unwrap();
+        assert_eq!(
+            prepared.apks,
+            vec![store.path(&base.id).unwrap(), store.path(&split.id).unwrap()]
+        );
+
+        // A split is no base, and a base no split
+        assert!(matches!(
+            store.plan_install(&split.id, &[]).await,
+            Err(ArtifactError::InvalidUpload(_))
+        ));
+        let other = save(&store, apk(None).into_iter().chain([0]).collect()).await;
+        assert!(matches!(
+            store.plan_install(&base.id, &[other.id]).await,
+            Err(ArtifactError::InvalidUpload(_))
+        ));
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+}
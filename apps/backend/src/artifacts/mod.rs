@@ -0,0 +1,449 @@
+use actix_web::web::Bytes;
+use chrono::Utc;
+use futures_util::{Stream, StreamExt};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use sqlx::sqlite::SqlitePool;
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::apk::{self, ApkManifest};
+use crate::config::ArtifactSettings;
+use crate::db::artifact::ArtifactRecord;
+use crate::db::ArtifactDb;
+
+mod install;
+
+pub use install::{InstallPlan, PreparedInstall};
+
+/// Every APK is a ZIP archive, which starts with a local file header
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+#[derive(Error, Debug)]
+pub enum ArtifactError {
+    #[error("Artifact storage error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Database error: {0}")]
+    DbError(#[from] sqlx::Error),
+    #[error("Artifact {0} not found")]
+    NotFound(String),
+    #[error("Invalid artifact ID {0:?}: expected a lowercase hex SHA-256")]
+    InvalidId(String),
+    #[error("Upload exceeds the limit of {limit} bytes")]
+    TooLarge { limit: u64 },
+    #[error("Invalid upload: {0}")]
+    InvalidUpload(String),
+    #[error("No activity given, {emulator} resolves no launcher activity for {package} and no APK of it installed there declares one")]
+    NoLauncherActivity { package: String, emulator: String },
+}
+
+impl ArtifactError {
+    /// Stable, machine-readable identifier of the error for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            ArtifactError::Io(_) => "artifact_storage_error",
+            ArtifactError::DbError(_) => "database_error",
+            ArtifactError::NotFound(_) => "artifact_not_found",
+            ArtifactError::InvalidId(_) => "invalid_artifact_id",
+            ArtifactError::TooLarge { .. } => "artifact_too_large",
+            ArtifactError::InvalidUpload(_) => "invalid_upload",
+            ArtifactError::NoLauncherActivity { .. } => "launcher_activity_unknown",
+        }
+    }
+}
+
+/// Check that an artifact ID is a SHA-256 digest, so it is safe to use in a path
+pub fn validate_id(id: &str) -> Result<(), ArtifactError> {
+    if id.len() == 64 && id.chars().all(|c| matches!(c, '0'..='9' | 'a'..='f')) {
+        Ok(())
+    } else {
+        Err(ArtifactError::InvalidId(id.to_string()))
+    }
+}
+
+/// Uploaded APKs, stored on disk by the SHA-256 of their content so that the same
+/// file uploaded twice is kept once
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    dir: PathBuf,
+    db: ArtifactDb,
+    max_size: u64,
+    /// Held while an upload puts its content in place and while the cleanup removes
+    /// content, so neither sees the other half done
+    content_lock: Arc<Mutex<()>>,
+}
+
+impl ArtifactStore {
+    pub fn new<P: Into<PathBuf>>(dir: P, pool: SqlitePool) -> Self {
+        Self {
+            dir: dir.into(),
+            db: ArtifactDb::new(pool),
+            max_size: ArtifactSettings::default().max_upload_mb * 1024 * 1024,
+            content_lock: Arc::default(),
+        }
+    }
+
+    /// A store configured from the application settings
+    pub fn from_settings(settings: &ArtifactSettings, pool: SqlitePool) -> Self {
+        Self::new(&settings.dir, pool).with_max_size(settings.max_upload_mb * 1024 * 1024)
+    }
+
+    /// Set the size of the largest accepted upload in bytes
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where the content of an artifact is stored
+    pub fn path(&self, id: &str) -> Result<PathBuf, ArtifactError> {
+        validate_id(id)?;
+        Ok(self.dir.join(&id[..2]).join(format!("{}.apk", id)))
+    }
+
+    /// Store an upload, streaming it to disk while it is hashed, and read its manifest.
+    ///
+    /// Returns the artifact and whether it is new; uploading content that is already
+    /// stored only marks the existing artifact as used. APKs whose manifest cannot be
+    /// read are stored without one.
+    pub async fn save<S, E>(&self, file_name: &str, mut content: S) -> Result<(ArtifactRecord, bool), ArtifactError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Display,
+    {
+        let tmp_dir = self.dir.join("tmp");
+        fs::create_dir_all(&tmp_dir).await?;
+        let tmp_path = tmp_dir.join(format!("{}.part", Uuid::new_v4()));
+
+        let result = self.receive(&tmp_path, &mut content).await;
+        let (digest, size) = match result {
+            Ok(received) => received,
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path).await;
+                return Err(e);
+            }
+        };
+
+        let id = hex::encode(digest);
+        let path = self.path(&id)?;
+        let _content = self.content_lock.lock().await;
+        // Marking a known artifact as used first keeps the cleanup from deleting it
+        // once its content is found to be in place
+        if self.db.touch(&id).await? && fs::try_exists(&path).await? {
+            fs::remove_file(&tmp_path).await?;
+        } else {
+            fs::create_dir_all(path.parent().unwrap_or(&self.dir)).await?;
+            fs::rename(&tmp_path, &path).await?;
+        }
+
+        let manifest = read_manifest(path.clone(), tmp_dir).await;
+        let record = ArtifactRecord::new(id, file_name.to_string(), size as i64, manifest);
+        Ok(self.db.save_artifact(&record).await?)
+    }
+
+    /// Write an upload to `path`, returning its digest and size
+    async fn receive<S, E>(&self, path: &Path, content: &mut S) -> Result<(Vec<u8>, u64), ArtifactError>
+    where
+        S: Stream<Item = Result<Bytes, E>> + Unpin,
+        E: Display,
+    {
+        let mut file = fs::File::create(path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        let mut head = Vec::with_capacity(ZIP_MAGIC.len());
+
+        while let Some(chunk) = content.next().await {
+            let chunk = chunk.map_err(|e| ArtifactError::InvalidUpload(e.to_string()))?;
+            size += chunk.len() as u64;
+            if size > self.max_size {
+                return Err(ArtifactError::TooLarge { limit: self.max_size });
+            }
+            if head.len() < ZIP_MAGIC.len() {
+                let missing = (ZIP_MAGIC.len() - head.len()).min(chunk.len());
+                head.extend_from_slice(&chunk[..missing]);
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+
+        if head != ZIP_MAGIC {
+            return Err(ArtifactError::InvalidUpload(
+                "the file is not an APK".to_string(),
+            ));
+        }
+        Ok((hasher.finalize().to_vec(), size))
+    }
+
+    pub async fn get(&self, id: &str) -> Result<ArtifactRecord, ArtifactError> {
+        validate_id(id)?;
+        self.db
+            .get_artifact(id)
+            .await?
+            .ok_or_else(|| ArtifactError::NotFound(id.to_string()))
+    }
+
+    pub async fn list(&self) -> Result<Vec<ArtifactRecord>, ArtifactError> {
+        Ok(self.db.list_artifacts().await?)
+    }
+
+    /// A stored artifact and its path, for installing it. Marks the artifact as used
+    /// so the retention cleanup does not remove it during the installation.
+    pub async fn resolve(&self, id: &str) -> Result<(ArtifactRecord, PathBuf), ArtifactError> {
+        let path = self.path(id)?;
+        if !self.db.touch(id).await? || !fs::try_exists(&path).await? {
+            return Err(ArtifactError::NotFound(id.to_string()));
+        }
+        Ok((self.get(id).await?, path))
+    }
+
+    /// The launcher activity of the APK of `package` last installed on an emulator
+    pub async fn launcher_activity(&self, emulator_name: &str, package: &str) -> Result<String, ArtifactError> {
+        self.db
+            .latest_install(emulator_name, package)
+            .await?
+            .and_then(|record| record.manifest?.0.launcher_activity)
+            .ok_or_else(|| ArtifactError::NoLauncherActivity {
+                package: package.to_string(),
+                emulator: emulator_name.to_string(),
+            })
+    }
+
+    /// Remember that an artifact was installed on an emulator, which keeps it from
+    /// being cleaned up while the emulator exists
+    pub async fn record_install(&self, id: &str, emulator_name: &str) -> Result<(), ArtifactError> {
+        Ok(self.db.record_install(id, emulator_name).await?)
+    }
+
+    /// Delete an artifact and its content
+    pub async fn delete(&self, id: &str) -> Result<(), ArtifactError> {
+        let path = self.path(id)?;
+        if !self.db.delete_artifact(id).await? {
+            return Err(ArtifactError::NotFound(id.to_string()));
+        }
+        remove_content(&path).await?;
+        Ok(())
+    }
+
+    /// Delete the artifacts that are not installed on any existing emulator and
+    /// were last used longer than `retention` ago. Returns the deleted IDs.
+    pub async fn cleanup(&self, retention: Duration) -> Result<Vec<String>, ArtifactError> {
+        let retention = chrono::Duration::from_std(retention).unwrap_or(chrono::Duration::MAX);
+        let before = Utc::now()
+            .checked_sub_signed(retention)
+            .unwrap_or(chrono::DateTime::<Utc>::MIN_UTC)
+            .to_rfc3339();
+
+        let mut deleted = Vec::new();
+        for record in self.db.unreferenced_before(&before).await? {
+            let _content = self.content_lock.lock().await;
+            // The artifact may have been uploaded or used again since it was listed
+            if !self.db.delete_unreferenced(&record.id, &before).await? {
+                continue;
+            }
+            remove_content(&self.path(&record.id)?).await?;
+            deleted.push(record.id);
+        }
+        Ok(deleted)
+    }
+
+    /// Run the retention cleanup every `interval` for as long as the process lives
+    pub fn spawn_cleanup(self, interval: Duration, retention: Duration) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.cleanup(retention).await {
+                    Ok(deleted) if !deleted.is_empty() => {
+                        info!("Cleaned up {} unreferenced artifacts", deleted.len())
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Artifact cleanup failed: {}", e),
+                }
+            }
+        });
+    }
+}
+
+/// Read the manifest of a stored APK off the async runtime, extracting the base
+/// APK of a bundle to `tmp_dir`
+async fn read_manifest(path: PathBuf, tmp_dir: PathBuf) -> Option<ApkManifest> {
+    let display = path.display().to_string();
+    match tokio::task::spawn_blocking(move || apk::read_manifest(path, &tmp_dir)).await {
+        Ok(Ok(manifest)) => Some(manifest),
+        Ok(Err(e)) => {
+            warn!("Cannot read the manifest of {}: {}", display, e);
+            None
+        }
+        Err(e) => {
+            warn!("Reading the manifest of {} failed: {}", display, e);
+            None
+        }
+    }
+}
+
+/// Remove an artifact's file, which may already be gone
+async fn remove_content(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::apk::fixtures::{zip, Attr, Axml};
+    use crate::db;
+    use crate::emulator::EmulatorManager;
+    use futures_util::stream;
+    use std::convert::Infallible;
+    use tokio::test;
+
+    async fn setup_store() -> (ArtifactStore, SqlitePool) {
+        let pool = db::create_pool("sqlite::memory:").await.unwrap();
+        let dir = std::env::temp_dir().join(format!("artifacts-{}", Uuid::new_v4()));
+        (ArtifactStore::new(dir, pool.clone()).with_max_size(64), pool)
+    }
+
+    fn upload(chunks: &[&'static [u8]]) -> impl Stream<Item = Result<Bytes, Infallible>> + Unpin {
+        stream::iter(chunks.iter().map(|chunk| Ok(Bytes::from_static(chunk))).collect::<Vec<_>>())
+    }
+
+    #[test]
+    async fn test_save_deduplicates_content() {
+        let (store, _pool) = setup_store().await;
+        let (first, new) = store.save("app.apk", upload(&[b"PK\x03", b"\x04apk"])).await.unwrap();
+        assert!(new);
+        assert_eq!(first.size_bytes, 7);
+        assert_eq!(first.id, hex::encode(Sha256::digest(b"PK\x03\x04apk")));
+        assert!(store.resolve(&first.id).await.unwrap().1.exists());
+        // Not a real APK, so there is no manifest to read
+        assert!(first.manifest.is_none());
+
+        let (second, new) = store.save("copy.apk", upload(&[b"PK\x03\x04apk"])).await.unwrap();
+        assert!(!new);
+        assert_eq!(second.id, first.id);
+        assert_eq!(second.file_name, "app.apk");
+        assert_eq!(store.list().await.unwrap().len(), 1);
+
+        store.delete(&first.id).await.unwrap();
+        assert!(matches!(store.resolve(&first.id).await, Err(ArtifactError::NotFound(_))));
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+
+    #[test]
+    async fn test_save_reads_manifest() {
+        let (store, pool) = setup_store().await;
+        let manifest = Axml::new()
+            .start("manifest", &[("package", Attr::Str("com.example"))])
+            .start("application", &[])
+            .start("activity", &[("name", Attr::Str(".MainActivity"))])
+            .launcher_filter()
+            .end()
+            .end()
+            .end()
+            .finish();
+        let apk = Bytes::from(zip(&[("AndroidManifest.xml", manifest.as_slice(), true)]));
+        let store = store.with_max_size(1 << 20);
+        let (record, _) = store.save("app.apk", stream::iter([Ok::<_, Infallible>(apk)])).await.unwrap();
+        let manifest = record.manifest.unwrap().0;
+        assert_eq!(manifest.package, "com.example");
+        assert_eq!(manifest.launcher_activity.as_deref(), Some("com.example.MainActivity"));
+
+        // The launcher activity is known once the APK is installed on the emulator
+        EmulatorManager::new(pool).create_emulator("pixel".to_string()).await.unwrap();
+        assert!(matches!(
+            store.launcher_activity("pixel", "com.example").await,
+            Err(ArtifactError::NoLauncherActivity { .. })
+        ));
+        store.record_install(&record.id, "pixel").await.unwrap();
+        assert_eq!(
+            store.launcher_activity("pixel", "com.example").await.unwrap(),
+            "com.example.MainActivity"
+        );
+        assert!(store.launcher_activity("other", "com.example").await.is_err());
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+
+    #[test]
+    async fn test_save_rejects_invalid_uploads() {
+        let (store, _pool) = setup_store().await;
+        assert!(matches!(
+            store.save("app.apk", upload(&[b"not a zip"])).await,
+            Err(ArtifactError::InvalidUpload(_))
+        ));
+        assert!(matches!(
+            store.save("app.apk", upload(&[b"PK\x03\x04", &[0; 64]])).await,
+            Err(ArtifactError::TooLarge { limit: 64 })
+        ));
+        // Rejected uploads leave nothing behind
+        assert_eq!(std::fs::read_dir(store.dir().join("tmp")).unwrap().count(), 0);
+        assert!(store.list().await.unwrap().is_empty());
+        assert!(matches!(store.path("../../etc/passwd"), Err(ArtifactError::InvalidId(_))));
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+
+    #[test]
+    async fn test_reupload_races_with_cleanup() {
+        let (store, _pool) = setup_store().await;
+        let uploads = {
+            let store = store.clone();
+            tokio::spawn(async move {
+                for _ in 0..50 {
+                    let (record, _) = store.save("a.apk", upload(&[b"PK\x03\x04a"])).await.unwrap();
+                    // Whatever the cleanup does, a stored artifact has its content
+                    if let Ok((_, path)) = store.resolve(&record.id).await {
+                        assert!(path.exists());
+                    }
+                }
+            })
+        };
+        for _ in 0..50 {
+            store.cleanup(Duration::ZERO).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        uploads.await.unwrap();
+
+        for record in store.list().await.unwrap() {
+            assert!(store.path(&record.id).unwrap().exists());
+        }
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+
+    #[test]
+    async fn test_cleanup_keeps_installed_artifacts() {
+        let (store, pool) = setup_store().await;
+        let (installed, _) = store.save("a.apk", upload(&[b"PK\x03\x04a"])).await.unwrap();
+        let (unused, _) = store.save("b.apk", upload(&[b"PK\x03\x04b"])).await.unwrap();
+        store.record_install(&installed.id, "pixel").await.unwrap();
+        EmulatorManager::new(pool).create_emulator("pixel".to_string()).await.unwrap();
+
+        // Recently used artifacts are kept
+        assert!(store.cleanup(Duration::from_secs(3600)).await.unwrap().is_empty());
+
+        let deleted = store.cleanup(Duration::ZERO).await.unwrap();
+        assert_eq!(deleted, vec![unused.id.clone()]);
+        assert!(store.resolve(&installed.id).await.is_ok());
+        assert!(!store.path(&unused.id).unwrap().exists());
+
+        // An artifact used after it was picked for cleanup is kept
+        let (reused, _) = store.save("c.apk", upload(&[b"PK\x03\x04c"])).await.unwrap();
+        let before = Utc::now().to_rfc3339();
+        store.resolve(&reused.id).await.unwrap();
+        assert!(!store.db.delete_unreferenced(&reused.id, &before).await.unwrap());
+        assert!(store.resolve(&reused.id).await.unwrap().1.exists());
+        let _ = std::fs::remove_dir_all(store.dir());
+    }
+}
@@ -1,27 +1,98 @@
 use anyhow::Result;
 use serde::Deserialize;
-use std::env;
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    #[serde(default)]
+    pub emulator: EmulatorSettings,
+    #[serde(default)]
+    pub artifacts: ArtifactSettings,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
     pub host: String,
     pub port: u16,
-    pub database_url: String,
-}
-
-impl Config {
-    pub async fn load() -> Result<Self> {
-        // Load from environment variables
-        dotenv::dotenv().ok();
-
-        Ok(Config {
-            host: env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
-            port: env::var("PORT")
-                .unwrap_or_else(|_| "8080".to_string())
-                .parse()
-                .unwrap_or(8080),
-            database_url: env::var("DATABASE_URL")
-                .unwrap_or_else(|_| "sqlite:data/emulators.db".to_string()),
-        })
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DatabaseConfig {
+    pub url: String,
+}
+
+/// Tunables for emulator lifecycle management
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct EmulatorSettings {
+    /// How long to wait for an emulator to finish booting before giving up
+    pub boot_timeout_secs: u64,
+    /// Delay between boot progress probes
+    pub boot_poll_interval_ms: u64,
+    /// Number of emulator output lines kept in memory per emulator
+    pub log_buffer_lines: usize,
+    /// How long a stopping emulator may take to exit before it is killed
+    pub stop_grace_secs: u64,
+    /// How long a single device command may run before it is killed
+    pub command_timeout_secs: u64,
+    /// How long an app installation may run before it is killed
+    pub install_timeout_secs: u64,
+    /// How often a command failing with a transient adb error is retried
+    pub command_retries: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub command_retry_backoff_ms: u64,
+}
+
+impl Default for EmulatorSettings {
+    fn default() -> Self {
+        Self {
+            boot_timeout_secs: 300,
+            boot_poll_interval_ms: 2000,
+            log_buffer_lines: 500,
+            stop_grace_secs: 15,
+            command_timeout_secs: 60,
+            install_timeout_secs: 600,
+            command_retries: 2,
+            command_retry_backoff_ms: 500,
+        }
+    }
+}
+
+/// Storage of uploaded APKs
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ArtifactSettings {
+    /// Directory the uploaded files are stored in
+    pub dir: String,
+    /// Largest accepted upload
+    pub max_upload_mb: u64,
+    /// How long an artifact that no emulator has installed is kept after its last use
+    pub retention_hours: u64,
+    /// Delay between runs of the retention cleanup
+    pub cleanup_interval_secs: u64,
+}
+
+impl Default for ArtifactSettings {
+    fn default() -> Self {
+        Self {
+            dir: "data/artifacts".to_string(),
+            max_upload_mb: 1024,
+            retention_hours: 168,
+            cleanup_interval_secs: 3600,
+        }
     }
 }
+
+/// Load configuration from `config/default.toml` and `APP__`-prefixed environment variables
+pub fn load() -> Result<Config> {
+    let config = ::config::Config::builder()
+        .set_default("server.host", "127.0.0.1")?
+        .set_default("server.port", 8080)?
+        .set_default("database.url", "sqlite:data/emulators.db")?
+        .add_source(::config::File::with_name("config/default").required(false))
+        .add_source(::config::Environment::with_prefix("APP").separator("__"))
+        .build()?;
+
+    Ok(config.try_deserialize()?)
+}
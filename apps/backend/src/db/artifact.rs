@@ -0,0 +1,287 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::types::Json;
+use sqlx::{FromRow, Result};
+
+use crate::apk::ApkManifest;
+
+/// Metadata of an uploaded APK, identified by the SHA-256 of its content
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ArtifactRecord {
+    /// Lowercase hex SHA-256 of the file
+    pub id: String,
+    /// File name the artifact was first uploaded with
+    pub file_name: String,
+    pub size_bytes: i64,
+    pub created_at: String,
+    /// Last time the artifact was uploaded again or installed
+    pub last_used_at: String,
+    /// What the APK's manifest declares; missing if it could not be read
+    pub manifest: Option<Json<ApkManifest>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ArtifactDb {
+    pool: SqlitePool,
+}
+
+impl ArtifactDb {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS artifacts (
+                id TEXT PRIMARY KEY,
+                file_name TEXT NOT NULL,
+                size_bytes INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                last_used_at TEXT NOT NULL,
+                manifest TEXT
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Bring tables created by older versions up to date
+        let has_manifest: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('artifacts') WHERE name = 'manifest'",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        if !has_manifest {
+            sqlx::query("ALTER TABLE artifacts ADD COLUMN manifest TEXT")
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Which emulators an artifact was installed on; these keep it from being cleaned up
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS artifact_installs (
+                artifact_id TEXT NOT NULL,
+                emulator_name TEXT NOT NULL,
+                installed_at TEXT NOT NULL,
+                PRIMARY KEY (artifact_id, emulator_name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record an artifact, or mark an existing one with the same content as used.
+    ///
+    /// Returns the stored record and whether it is new.
+    pub async fn save_artifact(&self, record: &ArtifactRecord) -> Result<(ArtifactRecord, bool)> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO artifacts (id, file_name, size_bytes, created_at, last_used_at, manifest)
+            VALUES (?, ?, ?, ?, ?, ?)
+            ON CONFLICT(id) DO NOTHING
+            "#,
+        )
+        .bind(&record.id)
+        .bind(&record.file_name)
+        .bind(record.size_bytes)
+        .bind(&record.created_at)
+        .bind(&record.last_used_at)
+        .bind(&record.manifest)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+        if !inserted {
+            // Artifacts uploaded before manifests were read get one now
+            sqlx::query(
+                "UPDATE artifacts SET last_used_at = ?, manifest = COALESCE(manifest, ?) WHERE id = ?",
+            )
+            .bind(&record.last_used_at)
+            .bind(&record.manifest)
+            .bind(&record.id)
+            .execute(&self.pool)
+            .await?;
+        }
+
+        let stored = self
+            .get_artifact(&record.id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)?;
+        Ok((stored, inserted))
+    }
+
+    pub async fn get_artifact(&self, id: &str) -> Result<Option<ArtifactRecord>> {
+        let record = sqlx::query_as::<_, ArtifactRecord>(
+            r#"
+            SELECT id, file_name, size_bytes, created_at, last_used_at, manifest
+            FROM artifacts
+            WHERE id = ?
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    pub async fn list_artifacts(&self) -> Result<Vec<ArtifactRecord>> {
+        let records = sqlx::query_as::<_, ArtifactRecord>(
+            r#"
+            SELECT id, file_name, size_bytes, created_at, last_used_at, manifest
+            FROM artifacts
+            ORDER BY created_at DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    /// Mark an artifact as used now
+    pub async fn touch(&self, id: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE artifacts SET last_used_at = ? WHERE id = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forget an artifact and the emulators it was installed on
+    pub async fn delete_artifact(&self, id: &str) -> Result<bool> {
+        sqlx::query("DELETE FROM artifact_installs WHERE artifact_id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        let result = sqlx::query("DELETE FROM artifacts WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forget an artifact if it is still unused since `before` and not installed on
+    /// any existing emulator. Returns whether it was deleted, so that an artifact
+    /// used again after it was picked for cleanup is kept.
+    pub async fn delete_unreferenced(&self, id: &str, before: &str) -> Result<bool> {
+        let mut tx = self.pool.begin().await?;
+
+        let result = sqlx::query(
+            r#"
+            DELETE FROM artifacts
+            WHERE id = ?
+              AND last_used_at < ?
+              AND NOT EXISTS (
+                  SELECT 1
+                  FROM artifact_installs i
+                  JOIN emulators e ON e.name = i.emulator_name
+                  WHERE i.artifact_id = artifacts.id
+              )
+            "#,
+        )
+        .bind(id)
+        .bind(before)
+        .execute(&mut *tx)
+        .await?;
+        let deleted = result.rows_affected() > 0;
+        if deleted {
+            sqlx::query("DELETE FROM artifact_installs WHERE artifact_id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    /// Remember that an artifact was installed on an emulator
+    pub async fn record_install(&self, id: &str, emulator_name: &str) -> Result<()> {
+        let now = Utc::now().to_rfc3339();
+        sqlx::query(
+            r#"
+            INSERT INTO artifact_installs (artifact_id, emulator_name, installed_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(artifact_id, emulator_name) DO UPDATE SET installed_at = excluded.installed_at
+            "#,
+        )
+        .bind(id)
+        .bind(emulator_name)
+        .bind(&now)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("UPDATE artifacts SET last_used_at = ? WHERE id = ?")
+            .bind(&now)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// The artifact of `package` most recently installed on an emulator
+    pub async fn latest_install(&self, emulator_name: &str, package: &str) -> Result<Option<ArtifactRecord>> {
+        let record = sqlx::query_as::<_, ArtifactRecord>(
+            r#"
+            SELECT a.id, a.file_name, a.size_bytes, a.created_at, a.last_used_at, a.manifest
+            FROM artifacts a
+            JOIN artifact_installs i ON i.artifact_id = a.id
+            WHERE i.emulator_name = ? AND json_extract(a.manifest, '$.package') = ?
+            ORDER BY i.installed_at DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(emulator_name)
+        .bind(package)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    /// Artifacts last used before `before` that are not installed on any existing emulator
+    pub async fn unreferenced_before(&self, before: &str) -> Result<Vec<ArtifactRecord>> {
+        let records = sqlx::query_as::<_, ArtifactRecord>(
+            r#"
+            SELECT id, file_name, size_bytes, created_at, last_used_at, manifest
+            FROM artifacts a
+            WHERE last_used_at < ?
+              AND NOT EXISTS (
+                  SELECT 1
+                  FROM artifact_installs i
+                  JOIN emulators e ON e.name = i.emulator_name
+                  WHERE i.artifact_id = a.id
+              )
+            "#,
+        )
+        .bind(before)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+}
+
+impl ArtifactRecord {
+    pub fn new(id: String, file_name: String, size_bytes: i64, manifest: Option<ApkManifest>) -> Self {
+        let now = Utc::now().to_rfc3339();
+        Self {
+            id,
+            file_name,
+            size_bytes,
+            created_at: now.clone(),
+            last_used_at: now,
+            manifest: manifest.map(Json),
+        }
+    }
+}
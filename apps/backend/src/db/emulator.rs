@@ -1,17 +1,27 @@
-use anyhow::Result;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use sqlx::sqlite::SqlitePool;
+use sqlx::types::Json;
+use sqlx::{FromRow, Result};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::emulator::launch::LaunchProfile;
+use crate::emulator::supervisor::RestartPolicy;
+use crate::emulator::EmulatorState;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct EmulatorConfig {
     pub name: String,
     pub console_port: u16,
     pub adb_port: u16,
+    pub state: EmulatorState,
+    pub last_error: Option<String>,
+    pub restart_policy: Json<RestartPolicy>,
+    pub launch_profile: Json<LaunchProfile>,
     pub created_at: String,
     pub updated_at: String,
 }
 
+#[derive(Debug, Clone)]
 pub struct EmulatorDb {
     pool: SqlitePool,
 }
@@ -28,6 +38,10 @@ impl EmulatorDb {
                 name TEXT PRIMARY KEY,
                 console_port INTEGER NOT NULL,
                 adb_port INTEGER NOT NULL,
+                state TEXT NOT NULL DEFAULT 'created',
+                last_error TEXT,
+                restart_policy TEXT NOT NULL DEFAULT '{"policy":"never"}',
+                launch_profile TEXT NOT NULL DEFAULT '{}',
                 created_at TEXT NOT NULL,
                 updated_at TEXT NOT NULL
             )
@@ -36,6 +50,31 @@ impl EmulatorDb {
         .execute(&self.pool)
         .await?;
 
+        // Bring tables created by older versions up to date
+        self.ensure_column("state", "TEXT NOT NULL DEFAULT 'created'").await?;
+        self.ensure_column("last_error", "TEXT").await?;
+        self.ensure_column("restart_policy", r#"TEXT NOT NULL DEFAULT '{"policy":"never"}'"#)
+            .await?;
+        self.ensure_column("launch_profile", "TEXT NOT NULL DEFAULT '{}'").await?;
+
+        Ok(())
+    }
+
+    /// Add a column to the `emulators` table if it does not exist yet
+    async fn ensure_column(&self, column: &str, definition: &str) -> Result<()> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('emulators') WHERE name = ?",
+        )
+        .bind(column)
+        .fetch_one(&self.pool)
+        .await?;
+
+        if !exists {
+            sqlx::query(&format!("ALTER TABLE emulators ADD COLUMN {} {}", column, definition))
+                .execute(&self.pool)
+                .await?;
+        }
+
         Ok(())
     }
 
@@ -44,17 +83,22 @@ impl EmulatorDb {
         
         sqlx::query(
             r#"
-            INSERT INTO emulators (name, console_port, adb_port, created_at, updated_at)
-            VALUES (?, ?, ?, ?, ?)
+            INSERT INTO emulators
+                (name, console_port, adb_port, restart_policy, launch_profile, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
             ON CONFLICT(name) DO UPDATE SET
                 console_port = excluded.console_port,
                 adb_port = excluded.adb_port,
+                restart_policy = excluded.restart_policy,
+                launch_profile = excluded.launch_profile,
                 updated_at = excluded.updated_at
             "#,
         )
         .bind(&config.name)
         .bind(config.console_port)
         .bind(config.adb_port)
+        .bind(config.restart_policy)
+        .bind(&config.launch_profile)
         .bind(&now)
         .bind(&now)
         .execute(&self.pool)
@@ -64,15 +108,15 @@ impl EmulatorDb {
     }
 
     pub async fn get_emulator(&self, name: &str) -> Result<Option<EmulatorConfig>> {
-        let config = sqlx::query_as!(
-            EmulatorConfig,
+        let config = sqlx::query_as::<_, EmulatorConfig>(
             r#"
-            SELECT name, console_port, adb_port, created_at, updated_at
+            SELECT name, console_port, adb_port, state, last_error, restart_policy,
+                   launch_profile, created_at, updated_at
             FROM emulators
             WHERE name = ?
             "#,
-            name
         )
+        .bind(name)
         .fetch_optional(&self.pool)
         .await?;
 
@@ -80,10 +124,10 @@ impl EmulatorDb {
     }
 
     pub async fn list_emulators(&self) -> Result<Vec<EmulatorConfig>> {
-        let configs = sqlx::query_as!(
-            EmulatorConfig,
+        let configs = sqlx::query_as::<_, EmulatorConfig>(
             r#"
-            SELECT name, console_port, adb_port, created_at, updated_at
+            SELECT name, console_port, adb_port, state, last_error, restart_policy,
+                   launch_profile, created_at, updated_at
             FROM emulators
             ORDER BY created_at DESC
             "#,
@@ -94,14 +138,88 @@ impl EmulatorDb {
         Ok(configs)
     }
 
+    /// Move an emulator to `to`, but only if it is currently in state `from`.
+    ///
+    /// A failure reason replaces the stored one; `None` keeps the previous reason.
+    /// Returns `false` if the emulator does not exist or its state has changed.
+    pub async fn update_state(
+        &self,
+        name: &str,
+        from: EmulatorState,
+        to: EmulatorState,
+        last_error: Option<&str>,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE emulators
+            SET state = ?, last_error = COALESCE(?, last_error), updated_at = ?
+            WHERE name = ? AND state = ?
+            "#,
+        )
+        .bind(to)
+        .bind(last_error)
+        .bind(Utc::now().to_rfc3339())
+        .bind(name)
+        .bind(from)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forget the failure reason of an emulator that turned out to be healthy
+    pub async fn clear_last_error(&self, name: &str) -> Result<bool> {
+        let result = sqlx::query("UPDATE emulators SET last_error = NULL, updated_at = ? WHERE name = ?")
+            .bind(Utc::now().to_rfc3339())
+            .bind(name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn set_restart_policy(&self, name: &str, policy: RestartPolicy) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE emulators
+            SET restart_policy = ?, updated_at = ?
+            WHERE name = ?
+            "#,
+        )
+        .bind(Json(policy))
+        .bind(Utc::now().to_rfc3339())
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn set_launch_profile(&self, name: &str, profile: &LaunchProfile) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE emulators
+            SET launch_profile = ?, updated_at = ?
+            WHERE name = ?
+            "#,
+        )
+        .bind(Json(profile))
+        .bind(Utc::now().to_rfc3339())
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn delete_emulator(&self, name: &str) -> Result<bool> {
-        let result = sqlx::query!(
+        let result = sqlx::query(
             r#"
             DELETE FROM emulators
             WHERE name = ?
             "#,
-            name
         )
+        .bind(name)
         .execute(&self.pool)
         .await?;
 
@@ -1,31 +1,52 @@
 use anyhow::Result;
-use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions};
 use std::path::Path;
 use std::fs;
+use std::str::FromStr;
 
+pub mod artifact;
 pub mod emulator;
+pub mod package;
+pub mod snapshot;
+pub use artifact::ArtifactDb;
 pub use emulator::EmulatorDb;
+pub use package::PackageDb;
+pub use snapshot::SnapshotDb;
 
 pub async fn create_pool(database_url: &str) -> Result<SqlitePool> {
     // Extract the path from the SQLite URL
     let path = database_url.strip_prefix("sqlite:").unwrap_or(database_url);
+    let in_memory = path.starts_with(":memory:");
     
     // Ensure the directory exists
-    if let Some(parent) = Path::new(path).parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent)?;
+    if !in_memory {
+        if let Some(parent) = Path::new(path).parent() {
+            if !parent.as_os_str().is_empty() && !parent.exists() {
+                fs::create_dir_all(parent)?;
+            }
         }
     }
     
-    // Create the pool with connection options that ensure the database file is created
-    let pool = SqlitePoolOptions::new()
-        .max_connections(5)
-        .connect(database_url)
-        .await?;
+    // Create the pool with connection options that ensure the database file is created.
+    // Every connection to an in-memory database gets its own empty database, so those
+    // pools are limited to a single connection that is never recycled.
+    let options = SqliteConnectOptions::from_str(database_url)?.create_if_missing(true);
+    let pool_options = if in_memory {
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    } else {
+        SqlitePoolOptions::new().max_connections(5)
+    };
+    let pool = pool_options.connect_with(options).await?;
     
     // Initialize the database schema if needed
     let emulator_db = EmulatorDb::new(pool.clone());
     emulator_db.init().await?;
+    SnapshotDb::new(pool.clone()).init().await?;
+    ArtifactDb::new(pool.clone()).init().await?;
+    PackageDb::new(pool.clone()).init().await?;
     
     Ok(pool)
 }
@@ -0,0 +1,145 @@
+use chrono::Utc;
+use sqlx::sqlite::SqlitePool;
+use sqlx::Result;
+
+use crate::emulator::packages::InstalledPackage;
+
+/// The packages last read from each emulator, so inventories of stopped emulators
+/// can still be shown
+#[derive(Debug, Clone)]
+pub struct PackageDb {
+    pool: SqlitePool,
+}
+
+impl PackageDb {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS package_inventories (
+                emulator_name TEXT PRIMARY KEY,
+                refreshed_at TEXT NOT NULL
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS installed_packages (
+                emulator_name TEXT NOT NULL,
+                package TEXT NOT NULL,
+                apk_path TEXT NOT NULL,
+                version_code INTEGER,
+                version_name TEXT,
+                system INTEGER NOT NULL,
+                installer TEXT,
+                first_install_time TEXT,
+                last_update_time TEXT,
+                PRIMARY KEY (emulator_name, package)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Replace the cached packages of an emulator, returning when they were read
+    pub async fn replace_packages(&self, emulator_name: &str, packages: &[InstalledPackage]) -> Result<String> {
+        let refreshed_at = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM installed_packages WHERE emulator_name = ?")
+            .bind(emulator_name)
+            .execute(&mut *tx)
+            .await?;
+        for package in packages {
+            sqlx::query(
+                r#"
+                INSERT INTO installed_packages (
+                    emulator_name, package, apk_path, version_code, version_name, system,
+                    installer, first_install_time, last_update_time
+                )
+                VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+                "#,
+            )
+            .bind(emulator_name)
+            .bind(&package.package)
+            .bind(&package.apk_path)
+            .bind(package.version_code)
+            .bind(&package.version_name)
+            .bind(package.system)
+            .bind(&package.installer)
+            .bind(&package.first_install_time)
+            .bind(&package.last_update_time)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query(
+            r#"
+            INSERT INTO package_inventories (emulator_name, refreshed_at)
+            VALUES (?, ?)
+            ON CONFLICT(emulator_name) DO UPDATE SET refreshed_at = excluded.refreshed_at
+            "#,
+        )
+        .bind(emulator_name)
+        .bind(&refreshed_at)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(refreshed_at)
+    }
+
+    /// The cached packages of an emulator and when they were read, if they ever were
+    pub async fn list_packages(&self, emulator_name: &str) -> Result<Option<(String, Vec<InstalledPackage>)>> {
+        let refreshed_at: Option<String> = sqlx::query_scalar(
+            r#"
+            SELECT refreshed_at
+            FROM package_inventories
+            WHERE emulator_name = ?
+            "#,
+        )
+        .bind(emulator_name)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(refreshed_at) = refreshed_at else {
+            return Ok(None);
+        };
+
+        let packages = sqlx::query_as::<_, InstalledPackage>(
+            r#"
+            SELECT package, apk_path, version_code, version_name, system, installer,
+                first_install_time, last_update_time
+            FROM installed_packages
+            WHERE emulator_name = ?
+            ORDER BY package
+            "#,
+        )
+        .bind(emulator_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(Some((refreshed_at, packages)))
+    }
+
+    /// Forget the packages of an emulator
+    pub async fn delete_packages(&self, emulator_name: &str) -> Result<()> {
+        sqlx::query("DELETE FROM installed_packages WHERE emulator_name = ?")
+            .bind(emulator_name)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM package_inventories WHERE emulator_name = ?")
+            .bind(emulator_name)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
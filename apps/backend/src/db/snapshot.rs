@@ -0,0 +1,143 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::SqlitePool;
+use sqlx::{FromRow, Result};
+
+/// Metadata of a saved emulator snapshot
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SnapshotRecord {
+    pub emulator_name: String,
+    pub name: String,
+    /// Size of the snapshot on disk, if it could be measured
+    pub size_bytes: Option<i64>,
+    /// `ro.build.fingerprint` of the system the snapshot was taken from
+    pub android_build: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct SnapshotDb {
+    pool: SqlitePool,
+}
+
+impl SnapshotDb {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS snapshots (
+                emulator_name TEXT NOT NULL,
+                name TEXT NOT NULL,
+                size_bytes INTEGER,
+                android_build TEXT,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (emulator_name, name)
+            )
+            "#,
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a snapshot, replacing the metadata of an older one with the same name
+    pub async fn save_snapshot(&self, record: &SnapshotRecord) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO snapshots (emulator_name, name, size_bytes, android_build, created_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(emulator_name, name) DO UPDATE SET
+                size_bytes = excluded.size_bytes,
+                android_build = excluded.android_build,
+                created_at = excluded.created_at
+            "#,
+        )
+        .bind(&record.emulator_name)
+        .bind(&record.name)
+        .bind(record.size_bytes)
+        .bind(&record.android_build)
+        .bind(&record.created_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_snapshot(&self, emulator_name: &str, name: &str) -> Result<Option<SnapshotRecord>> {
+        let record = sqlx::query_as::<_, SnapshotRecord>(
+            r#"
+            SELECT emulator_name, name, size_bytes, android_build, created_at
+            FROM snapshots
+            WHERE emulator_name = ? AND name = ?
+            "#,
+        )
+        .bind(emulator_name)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(record)
+    }
+
+    pub async fn list_snapshots(&self, emulator_name: &str) -> Result<Vec<SnapshotRecord>> {
+        let records = sqlx::query_as::<_, SnapshotRecord>(
+            r#"
+            SELECT emulator_name, name, size_bytes, android_build, created_at
+            FROM snapshots
+            WHERE emulator_name = ?
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(emulator_name)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(records)
+    }
+
+    pub async fn delete_snapshot(&self, emulator_name: &str, name: &str) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM snapshots
+            WHERE emulator_name = ? AND name = ?
+            "#,
+        )
+        .bind(emulator_name)
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Forget every snapshot of an emulator
+    pub async fn delete_snapshots(&self, emulator_name: &str) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM snapshots
+            WHERE emulator_name = ?
+            "#,
+        )
+        .bind(emulator_name)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+impl SnapshotRecord {
+    pub fn new(emulator_name: &str, name: &str) -> Self {
+        Self {
+            emulator_name: emulator_name.to_string(),
+            name: name.to_string(),
+            size_bytes: None,
+            android_build: None,
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use serde::{Deserialize, Serialize};
+
+/// What `am start` reported about starting an activity
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LaunchResult {
+    /// The component that was started, e.g. `com.example/.MainActivity`
+    pub component: String,
+    /// Result of waiting for the launch with `-W`, e.g. `ok` or `timeout`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// `COLD`, `WARM` or `HOT`, on Android 10 and later
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_state: Option<String>,
+    /// Time until the activity drew its first frame, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_time_ms: Option<u64>,
+    /// Time `am` waited for the launch, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wait_time_ms: Option<u64>,
+    /// Set when the intent went to an activity that was already running on top
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
+}
+
+/// Parse the output of `am start [-W] -n <component>`.
+///
+/// `am` exits successfully on most failures on older Android versions, so errors
+/// are recognized from the output and returned as the error message.
+pub fn parse_am_start(output: &str, component: &str) -> Result<LaunchResult, String> {
+    let mut result = LaunchResult {
+        component: component.to_string(),
+        ..Default::default()
+    };
+    let mut errors = Vec::new();
+
+    for line in output.lines().map(str::trim) {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        match key {
+            "Error" | "Exception" => errors.push(value.to_string()),
+            "Warning" => result.warning = Some(value.to_string()),
+            "Status" => result.status = Some(value.to_string()),
+            "LaunchState" => result.launch_state = Some(value.to_string()),
+            "Activity" => result.component = value.to_string(),
+            "TotalTime" => result.total_time_ms = value.parse().ok(),
+            "WaitTime" => result.wait_time_ms = value.parse().ok(),
+            _ => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+/// Parse the output of `cmd package resolve-activity --brief` into the activity of
+/// the resolved component, e.g. `.MainActivity` for `com.example/.MainActivity`.
+///
+/// The component is the last line; `No activity found` means nothing matched.
+pub fn parse_resolved_activity(output: &str, package: &str) -> Option<String> {
+    let last = output.lines().map(str::trim).rfind(|line| !line.is_empty())?;
+    let (resolved_package, activity) = last.split_once('/')?;
+    if resolved_package != package || activity.is_empty() || activity.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(activity.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_am_start() {
+        let output = "\
+Starting: Intent { cmp=com.example/.MainActivity }
+Status: ok
+LaunchState: COLD
+Activity: com.example/.MainActivity
+TotalTime: 512
+WaitTime: 530
+Complete
+";
+        assert_eq!(
+            parse_am_start(output, "com.example/.MainActivity").unwrap(),
+            LaunchResult {
+                component: "com.example/.MainActivity".to_string(),
+                status: Some("ok".to_string()),
+                launch_state: Some("COLD".to_string()),
+                total_time_ms: Some(512),
+                wait_time_ms: Some(530),
+                warning: None,
+            }
+        );
+
+        // Without -W only the intent is echoed
+        let result = parse_am_start("Starting: Intent { cmp=com.example/.Main }\n", "com.example/.Main").unwrap();
+        assert_eq!(result.component, "com.example/.Main");
+        assert_eq!(result.status, None);
+
+        let output = "\
+Starting: Intent { cmp=com.example/.Main }
+Warning: Activity not started, intent has been delivered to currently running top-most instance.
+Status: ok
+LaunchState: UNKNOWN (0)
+Activity: com.example/.Main
+WaitTime: 3
+Complete
+";
+        let result = parse_am_start(output, "com.example/.Main").unwrap();
+        assert!(result.warning.unwrap().starts_with("Activity not started"));
+        assert_eq!(result.total_time_ms, None);
+    }
+
+    #[test]
+    fn test_parse_am_start_errors() {
+        let output = "\
+Starting: Intent { cmp=com.example/.Missing }
+Error type 3
+Error: Activity class {com.example/com.example.Missing} does not exist.
+";
+        assert_eq!(
+            parse_am_start(output, "com.example/.Missing").unwrap_err(),
+            "Activity class {com.example/com.example.Missing} does not exist."
+        );
+    }
+
+    #[test]
+    fn test_parse_resolved_activity() {
+        let output = "\
+priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=false
+com.example/.ui.MainActivity
+";
+        assert_eq!(
+            parse_resolved_activity(output, "com.example").as_deref(),
+            Some(".ui.MainActivity")
+        );
+        assert_eq!(parse_resolved_activity("No activity found\n", "com.example"), None);
+        assert_eq!(parse_resolved_activity("com.other/.Main\n", "com.example"), None);
+        assert_eq!(parse_resolved_activity("", "com.example"), None);
+    }
+}
@@ -0,0 +1,747 @@
+use log::{debug, warn};
+use std::env;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use super::devices::{parse_devices, AdbDevice};
+use super::runner::{self, CommandOutput, CommandPolicy, CommandRunner};
+
+/// Port of the adb server unless `ANDROID_ADB_SERVER_PORT` says otherwise
+pub const DEFAULT_SERVER_PORT: u16 = 5037;
+
+/// Largest chunk of file data in a single sync `DATA` packet
+const SYNC_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Packet ids of the `shell,v2` protocol sent by the device
+pub(super) const SHELL_STDOUT: u8 = 1;
+pub(super) const SHELL_STDERR: u8 = 2;
+pub(super) const SHELL_EXIT: u8 = 3;
+
+#[derive(Error, Debug)]
+pub enum AdbClientError {
+    #[error("adb server is not reachable: {0}")]
+    ServerUnavailable(io::Error),
+    #[error("adb I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("adb request timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("Device {0} not found")]
+    DeviceNotFound(String),
+    #[error("Device {0} is offline")]
+    DeviceOffline(String),
+    #[error("Device {0} is unauthorized")]
+    Unauthorized(String),
+    #[error("adb request failed: {0}")]
+    Failed(String),
+    #[error("adb protocol error: {0}")]
+    Protocol(String),
+}
+
+impl AdbClientError {
+    /// Classify a `FAIL` message of the adb server
+    fn from_failure(serial: Option<&str>, message: String) -> Self {
+        let device = || serial.unwrap_or_default().to_string();
+        if message.contains("not found") {
+            AdbClientError::DeviceNotFound(device())
+        } else if message.contains("offline") {
+            AdbClientError::DeviceOffline(device())
+        } else if message.contains("unauthorized") {
+            AdbClientError::Unauthorized(device())
+        } else {
+            AdbClientError::Failed(message)
+        }
+    }
+
+    /// Stable, machine-readable identifier of the error for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            AdbClientError::ServerUnavailable(_) => "adb_server_unavailable",
+            AdbClientError::DeviceNotFound(_) => "device_not_found",
+            AdbClientError::DeviceOffline(_) => "device_offline",
+            AdbClientError::Unauthorized(_) => "device_unauthorized",
+            _ => "adb_failed",
+        }
+    }
+
+    /// Whether the request may succeed when it is repeated
+    pub fn is_transient(&self) -> bool {
+        match self {
+            AdbClientError::DeviceOffline(_) => true,
+            AdbClientError::Failed(message) => runner::is_transient_error(message),
+            _ => false,
+        }
+    }
+}
+
+/// Metadata of a file on the device, as returned by a sync `STAT` request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileStat {
+    pub mode: u32,
+    pub size: u32,
+    pub mtime: u32,
+}
+
+impl FileStat {
+    /// A `STAT` of a missing path returns all zeroes
+    pub fn exists(&self) -> bool {
+        self.mode != 0
+    }
+}
+
+/// Client for the adb server's host protocol.
+///
+/// Every request opens a new connection to the server, sends a length-prefixed
+/// service name and expects `OKAY` or `FAIL` followed by a length-prefixed message.
+/// Device services are reached by first switching the connection to the device with
+/// `host:transport:<serial>`.
+///
+/// Connecting and opening a service is retried on transient adb errors under a
+/// [`CommandPolicy`], whose timeout also bounds every attempt and the rest of the request.
+#[derive(Debug, Clone, Copy)]
+pub struct AdbClient {
+    addr: SocketAddr,
+    policy: CommandPolicy,
+}
+
+/// Address of the local adb server, on `ANDROID_ADB_SERVER_PORT` if it is set
+pub fn default_server_addr() -> SocketAddr {
+    let port = env::var("ANDROID_ADB_SERVER_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(DEFAULT_SERVER_PORT);
+    SocketAddr::from(([127, 0, 0, 1], port))
+}
+
+/// Run `request` to completion within `after`; dropping the request closes its connection
+async fn deadline<T>(
+    after: Duration,
+    request: impl std::future::Future<Output = Result<T, AdbClientError>>,
+) -> Result<T, AdbClientError> {
+    timeout(after, request)
+        .await
+        .map_err(|_| AdbClientError::Timeout(after))?
+}
+
+async fn read_exact<R: AsyncRead + Unpin, const N: usize>(
+    reader: &mut R,
+) -> Result<[u8; N], AdbClientError> {
+    let mut buf = [0; N];
+    reader.read_exact(&mut buf).await?;
+    Ok(buf)
+}
+
+/// Read a message prefixed with its length as four hex digits
+async fn read_hex_prefixed<R: AsyncRead + Unpin>(reader: &mut R) -> Result<String, AdbClientError> {
+    let len = read_exact::<_, 4>(reader).await?;
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| AdbClientError::Protocol(format!("invalid length {:?}", len)))?;
+    let mut message = vec![0; len];
+    reader.read_exact(&mut message).await?;
+    Ok(String::from_utf8_lossy(&message).into_owned())
+}
+
+/// Read `OKAY`, or turn `FAIL` and its message into an error
+async fn read_status<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    serial: Option<&str>,
+) -> Result<(), AdbClientError> {
+    match &read_exact::<_, 4>(reader).await? {
+        b"OKAY" => Ok(()),
+        b"FAIL" => {
+            let message = read_hex_prefixed(reader).await?;
+            Err(AdbClientError::from_failure(serial, message))
+        }
+        other => Err(AdbClientError::Protocol(format!(
+            "unexpected status {:?}",
+            String::from_utf8_lossy(other)
+        ))),
+    }
+}
+
+impl AdbClient {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            policy: CommandPolicy::default(),
+        }
+    }
+
+    /// A client for the adb server that `runner` runs its adb commands against
+    pub fn for_runner(runner: &dyn CommandRunner, policy: CommandPolicy) -> Self {
+        Self::new(runner.adb_server()).with_policy(policy)
+    }
+
+    /// Apply the timeout and retries of `policy` to requests
+    pub fn with_policy(mut self, policy: CommandPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Limit how long a request may take before it is abandoned
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.policy = self.policy.with_timeout(timeout);
+        self
+    }
+
+    async fn connect(&self) -> Result<TcpStream, AdbClientError> {
+        TcpStream::connect(self.addr)
+            .await
+            .map_err(AdbClientError::ServerUnavailable)
+    }
+
+    /// Run a request to completion within the client's timeout
+    async fn with_deadline<T>(
+        &self,
+        request: impl std::future::Future<Output = Result<T, AdbClientError>>,
+    ) -> Result<T, AdbClientError> {
+        deadline(self.policy.timeout, request).await
+    }
+
+    /// Open a connection with `open`, retrying with exponential backoff while it
+    /// fails with a transient error. Nothing has been sent to the service yet at
+    /// that point, so every request can be retried.
+    async fn open<F, Fut>(&self, open: F) -> Result<TcpStream, AdbClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<TcpStream, AdbClientError>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match self.with_deadline(open()).await {
+                Err(e) if e.is_transient() && attempt < self.policy.retries => {
+                    let delay = self.policy.backoff_for(attempt);
+                    attempt += 1;
+                    warn!("adb request failed ({}), retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Send a service request over a connection and wait for it to be accepted
+    async fn send_request(
+        stream: &mut TcpStream,
+        request: &str,
+        serial: Option<&str>,
+    ) -> Result<(), AdbClientError> {
+        debug!("adb request {}", request);
+        stream
+            .write_all(format!("{:04x}{}", request.len(), request).as_bytes())
+            .await?;
+        read_status(stream, serial).await
+    }
+
+    /// Run a `host:` service and return the open connection
+    async fn host_request(&self, request: &str) -> Result<TcpStream, AdbClientError> {
+        self.open(|| async {
+            let mut stream = self.connect().await?;
+            Self::send_request(&mut stream, request, None).await?;
+            Ok(stream)
+        })
+        .await
+    }
+
+    /// List the devices known to the adb server
+    pub async fn devices(&self) -> Result<Vec<AdbDevice>, AdbClientError> {
+        let mut stream = self.host_request("host:devices").await?;
+        self.with_deadline(async {
+            let listing = read_hex_prefixed(&mut stream).await?;
+            Ok(parse_devices(&listing))
+        })
+        .await
+    }
+
+    /// Version of the adb server's protocol
+    pub async fn server_version(&self) -> Result<u32, AdbClientError> {
+        let mut stream = self.host_request("host:version").await?;
+        self.with_deadline(async {
+            let version = read_hex_prefixed(&mut stream).await?;
+            u32::from_str_radix(&version, 16)
+                .map_err(|_| AdbClientError::Protocol(format!("invalid version {:?}", version)))
+        })
+        .await
+    }
+
+    /// Open a connection to a device service such as `shell:` or `sync:`
+    async fn device_request(&self, serial: &str, service: &str) -> Result<TcpStream, AdbClientError> {
+        self.open(|| async {
+            let mut stream = self.connect().await?;
+            let transport = format!("host:transport:{}", serial);
+            Self::send_request(&mut stream, &transport, Some(serial)).await?;
+            Self::send_request(&mut stream, service, Some(serial)).await?;
+            Ok(stream)
+        })
+        .await
+    }
+
+    /// Run a command with `exec:` and return its raw stdout.
+    ///
+    /// Unlike `shell:` the output is not passed through a terminal, so it is binary safe.
+    pub async fn exec(&self, serial: &str, command: &str) -> Result<Vec<u8>, AdbClientError> {
+        self.exec_with_input(serial, command, &mut tokio::io::empty()).await
+    }
+
+    /// Run a command with `exec:`, write `input` to its stdin and return its raw stdout.
+    ///
+    /// The connection stays open for the output, so the command must know how much
+    /// input to read, e.g. `cmd package install -S <size>`.
+    pub async fn exec_with_input<R: AsyncRead + Unpin>(
+        &self,
+        serial: &str,
+        command: &str,
+        input: &mut R,
+    ) -> Result<Vec<u8>, AdbClientError> {
+        let mut stream = self.device_request(serial, &format!("exec:{}", command)).await?;
+        self.with_deadline(async {
+            tokio::io::copy(input, &mut stream).await?;
+            let mut output = Vec::new();
+            stream.read_to_end(&mut output).await?;
+            Ok(output)
+        })
+        .await
+    }
+
+    /// Run a command with the `shell,v2` protocol and capture its stdout, stderr
+    /// and exit code
+    pub async fn shell(&self, serial: &str, command: &str) -> Result<CommandOutput, AdbClientError> {
+        let service = format!("shell,v2,raw:{}", command);
+        let mut stream = self.device_request(serial, &service).await?;
+        self.with_deadline(async {
+            let mut output = CommandOutput::default();
+            loop {
+                let [id] = read_exact::<_, 1>(&mut stream).await?;
+                let len = u32::from_le_bytes(read_exact::<_, 4>(&mut stream).await?) as u64;
+                let mut data = Vec::new();
+                if (&mut stream).take(len).read_to_end(&mut data).await? as u64 != len {
+                    return Err(AdbClientError::Protocol("truncated shell packet".to_string()));
+                }
+                match id {
+                    SHELL_STDOUT => output.stdout.extend_from_slice(&data),
+                    SHELL_STDERR => output.stderr.extend_from_slice(&data),
+                    SHELL_EXIT => {
+                        output.code = data.first().map(|&code| i32::from(code));
+                        return Ok(output);
+                    }
+                    // Other packets, such as window size changes, carry no output
+                    _ => {}
+                }
+            }
+        })
+        .await
+    }
+
+    /// Start a file transfer session with `sync:`; every operation of the session
+    /// has the client's timeout
+    pub async fn sync(&self, serial: &str) -> Result<SyncSession, AdbClientError> {
+        let stream = self.device_request(serial, "sync:").await?;
+        Ok(SyncSession {
+            stream,
+            timeout: self.policy.timeout,
+        })
+    }
+}
+
+/// An open `sync:` connection for transferring files to and from a device
+pub struct SyncSession {
+    stream: TcpStream,
+    timeout: Duration,
+}
+
+impl SyncSession {
+    /// Send a sync packet: a four letter id, a little-endian length and the payload
+    async fn send_packet(&mut self, id: &[u8; 4], payload: &[u8]) -> Result<(), AdbClientError> {
+        let mut packet = Vec::with_capacity(8 + payload.len());
+        packet.extend_from_slice(id);
+        packet.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        packet.extend_from_slice(payload);
+        self.stream.write_all(&packet).await?;
+        Ok(())
+    }
+
+    async fn read_u32(&mut self) -> Result<u32, AdbClientError> {
+        Ok(u32::from_le_bytes(read_exact::<_, 4>(&mut self.stream).await?))
+    }
+
+    /// Read the message of a `FAIL` reply; it describes the file operation that
+    /// failed, not the device
+    async fn read_failure(&mut self) -> AdbClientError {
+        let len = match self.read_u32().await {
+            Ok(len) => len as usize,
+            Err(e) => return e,
+        };
+        if len > SYNC_CHUNK_SIZE {
+            return AdbClientError::Protocol(format!("sync failure message of {} bytes", len));
+        }
+        let mut message = vec![0; len];
+        match self.stream.read_exact(&mut message).await {
+            Ok(_) => AdbClientError::Failed(String::from_utf8_lossy(&message).into_owned()),
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Get the metadata of a path on the device
+    pub async fn stat(&mut self, path: &str) -> Result<FileStat, AdbClientError> {
+        deadline(self.timeout, async {
+            self.send_packet(b"STAT", path.as_bytes()).await?;
+            let id = read_exact::<_, 4>(&mut self.stream).await?;
+            if &id != b"STAT" {
+                return Err(AdbClientError::Protocol("expected STAT reply".to_string()));
+            }
+            Ok(FileStat {
+                mode: self.read_u32().await?,
+                size: self.read_u32().await?,
+                mtime: self.read_u32().await?,
+            })
+        })
+        .await
+    }
+
+    /// Write everything read from `source` to a file on the device with the given
+    /// permission bits
+    pub async fn push<R: AsyncRead + Unpin>(
+        &mut self,
+        source: &mut R,
+        remote_path: &str,
+        mode: u32,
+    ) -> Result<(), AdbClientError> {
+        deadline(self.timeout, async {
+            let target = format!("{},{}", remote_path, 0o100000 | (mode & 0o7777));
+            self.send_packet(b"SEND", target.as_bytes()).await?;
+            let mut chunk = vec![0; SYNC_CHUNK_SIZE];
+            loop {
+                let read = source.read(&mut chunk).await?;
+                if read == 0 {
+                    break;
+                }
+                self.send_packet(b"DATA", &chunk[..read]).await?;
+            }
+            let mtime = chrono::Utc::now().timestamp() as u32;
+            self.stream.write_all(b"DONE").await?;
+            self.stream.write_all(&mtime.to_le_bytes()).await?;
+
+            match &read_exact::<_, 4>(&mut self.stream).await? {
+                b"OKAY" => {
+                    self.read_u32().await?;
+                    Ok(())
+                }
+                b"FAIL" => Err(self.read_failure().await),
+                _ => Err(AdbClientError::Protocol("expected OKAY or FAIL".to_string())),
+            }
+        })
+        .await
+    }
+
+    /// Read a file from the device
+    pub async fn pull(&mut self, remote_path: &str) -> Result<Vec<u8>, AdbClientError> {
+        deadline(self.timeout, async {
+            self.send_packet(b"RECV", remote_path.as_bytes()).await?;
+            let mut data = Vec::new();
+            loop {
+                match &read_exact::<_, 4>(&mut self.stream).await? {
+                    b"DATA" => {
+                        let len = self.read_u32().await? as usize;
+                        if len > SYNC_CHUNK_SIZE {
+                            return Err(AdbClientError::Protocol(format!(
+                                "DATA packet of {} bytes exceeds {} bytes",
+                                len, SYNC_CHUNK_SIZE
+                            )));
+                        }
+                        let start = data.len();
+                        data.resize(start + len, 0);
+                        self.stream.read_exact(&mut data[start..]).await?;
+                    }
+                    b"DONE" => {
+                        self.read_u32().await?;
+                        return Ok(data);
+                    }
+                    b"FAIL" => return Err(self.read_failure().await),
+                    _ => return Err(AdbClientError::Protocol("expected DATA or DONE".to_string())),
+                }
+            }
+        })
+        .await
+    }
+
+    /// End the session
+    pub async fn quit(mut self) -> Result<(), AdbClientError> {
+        deadline(self.timeout, self.send_packet(b"QUIT", &[])).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tokio::net::TcpListener;
+    use tokio::test;
+
+    type Files = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+    async fn read_request(stream: &mut TcpStream) -> Option<String> {
+        read_hex_prefixed(stream).await.ok()
+    }
+
+    async fn fail(stream: &mut TcpStream, message: &str) {
+        let reply = format!("FAIL{:04x}{}", message.len(), message);
+        stream.write_all(reply.as_bytes()).await.unwrap();
+    }
+
+    async fn serve_sync(stream: &mut TcpStream, files: Files) {
+        loop {
+            let Ok(id) = read_exact::<_, 4>(stream).await else { return };
+            let len = u32::from_le_bytes(read_exact::<_, 4>(stream).await.unwrap()) as usize;
+            let mut payload = vec![0; len];
+            stream.read_exact(&mut payload).await.unwrap();
+            let path = String::from_utf8(payload).unwrap();
+
+            match &id {
+                b"STAT" | b"RECV" if path == "/data/hang" => std::future::pending().await,
+                b"STAT" => {
+                    let size = files.lock().unwrap().get(&path).map(|data| data.len() as u32);
+                    let mut reply = b"STAT".to_vec();
+                    for value in [size.map_or(0, |_| 0o100644), size.unwrap_or(0), 0] {
+                        reply.extend_from_slice(&u32::to_le_bytes(value));
+                    }
+                    stream.write_all(&reply).await.unwrap();
+                }
+                b"SEND" => {
+                    let (path, _mode) = path.rsplit_once(',').unwrap();
+                    let mut data = Vec::new();
+                    loop {
+                        let id = read_exact::<_, 4>(stream).await.unwrap();
+                        let len = u32::from_le_bytes(read_exact::<_, 4>(stream).await.unwrap());
+                        if &id == b"DONE" {
+                            break;
+                        }
+                        let mut chunk = vec![0; len as usize];
+                        stream.read_exact(&mut chunk).await.unwrap();
+                        data.extend_from_slice(&chunk);
+                    }
+                    files.lock().unwrap().insert(path.to_string(), data);
+                    stream.write_all(b"OKAY\0\0\0\0").await.unwrap();
+                }
+                b"RECV" if path == "/data/huge" => {
+                    let mut reply = b"DATA".to_vec();
+                    reply.extend_from_slice(&(SYNC_CHUNK_SIZE as u32 + 1).to_le_bytes());
+                    stream.write_all(&reply).await.unwrap();
+                }
+                b"RECV" => {
+                    let data = files.lock().unwrap().get(&path).cloned();
+                    match data {
+                        Some(data) => {
+                            let mut reply = Vec::new();
+                            for chunk in data.chunks(SYNC_CHUNK_SIZE) {
+                                reply.extend_from_slice(b"DATA");
+                                reply.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+                                reply.extend_from_slice(chunk);
+                            }
+                            reply.extend_from_slice(b"DONE\0\0\0\0");
+                            stream.write_all(&reply).await.unwrap();
+                        }
+                        None => {
+                            let message = format!("remote object '{}' not found", path);
+                            let message = message.as_bytes();
+                            let mut reply = b"FAIL".to_vec();
+                            reply.extend_from_slice(&(message.len() as u32).to_le_bytes());
+                            reply.extend_from_slice(message);
+                            stream.write_all(&reply).await.unwrap();
+                        }
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    /// Serve one adb connection with a device `emulator-5554` that is online and an
+    /// `emulator-5556` that is offline
+    async fn serve(mut stream: TcpStream, files: Files) {
+        let mut serial = None;
+        while let Some(request) = read_request(&mut stream).await {
+            if request == "host:devices" {
+                let listing = "emulator-5554\tdevice\nemulator-5556\toffline\n";
+                let reply = format!("OKAY{:04x}{}", listing.len(), listing);
+                stream.write_all(reply.as_bytes()).await.unwrap();
+                return;
+            } else if let Some(target) = request.strip_prefix("host:transport:") {
+                match target {
+                    "emulator-5554" => {
+                        serial = Some(target.to_string());
+                        stream.write_all(b"OKAY").await.unwrap();
+                    }
+                    "emulator-5556" => return fail(&mut stream, "device offline").await,
+                    _ => return fail(&mut stream, &format!("device '{}' not found", target)).await,
+                }
+            } else if serial.is_none() {
+                return fail(&mut stream, "no device selected").await;
+            } else if let Some(command) = request.strip_prefix("shell,v2,raw:") {
+                let (id, output, code) = match command {
+                    "getprop ro.build.version.sdk" => (SHELL_STDOUT, "34\n".to_string(), 0),
+                    other => (SHELL_STDERR, format!("/system/bin/sh: {}: not found\n", other), 127),
+                };
+                let mut reply = b"OKAY".to_vec();
+                reply.push(id);
+                reply.extend_from_slice(&(output.len() as u32).to_le_bytes());
+                reply.extend_from_slice(output.as_bytes());
+                reply.extend_from_slice(&[SHELL_EXIT, 1, 0, 0, 0, code]);
+                stream.write_all(&reply).await.unwrap();
+                return;
+            } else if let Some(command) = request.strip_prefix("exec:") {
+                stream.write_all(b"OKAY").await.unwrap();
+                if command == "cat /data/blob" {
+                    stream.write_all(&[0, 159, 146, 150, b'\n']).await.unwrap();
+                } else if let Some(size) = command.strip_prefix("cmd package install -S ") {
+                    // Like the package manager, read exactly the announced size
+                    let mut apk = vec![0; size.parse().unwrap()];
+                    stream.read_exact(&mut apk).await.unwrap();
+                    let reply = if apk.starts_with(b"PK") {
+                        "Success\n"
+                    } else {
+                        "Failure [INSTALL_PARSE_FAILED_NOT_APK: not a zip archive]\n"
+                    };
+                    stream.write_all(reply.as_bytes()).await.unwrap();
+                }
+                return;
+            } else if request == "sync:" {
+                stream.write_all(b"OKAY").await.unwrap();
+                return serve_sync(&mut stream, files).await;
+            } else {
+                return fail(&mut stream, "unknown service").await;
+            }
+        }
+    }
+
+    async fn fake_server() -> AdbClient {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = AdbClient::new(listener.local_addr().unwrap());
+        let files = Files::default();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, files.clone()));
+            }
+        });
+        client
+    }
+
+    #[test]
+    async fn test_devices() {
+        let client = fake_server().await;
+        let devices = client.devices().await.unwrap();
+        assert_eq!(devices.len(), 2);
+        assert!(devices[0].is_online());
+        assert_eq!(devices[1].state, "offline");
+    }
+
+    #[test]
+    async fn test_shell_and_exec() {
+        let client = fake_server().await;
+        let sdk = client.shell("emulator-5554", "getprop ro.build.version.sdk").await.unwrap();
+        assert!(sdk.success());
+        assert_eq!(sdk.stdout_lossy().trim(), "34");
+
+        let missing = client.shell("emulator-5554", "frobnicate").await.unwrap();
+        assert_eq!(missing.code, Some(127));
+        assert!(missing.stdout.is_empty());
+        assert!(missing.stderr_lossy().contains("frobnicate: not found"));
+
+        let blob = client.exec("emulator-5554", "cat /data/blob").await.unwrap();
+        assert_eq!(blob, vec![0, 159, 146, 150, b'\n']);
+    }
+
+    #[test]
+    async fn test_exec_with_input() {
+        let client = fake_server().await;
+        let apk = b"PK\x03\x04 not really an apk";
+        let command = format!("cmd package install -S {}", apk.len());
+        let output = client
+            .exec_with_input("emulator-5554", &command, &mut &apk[..])
+            .await
+            .unwrap();
+        assert_eq!(output, b"Success\n");
+    }
+
+    #[test]
+    async fn test_typed_device_errors() {
+        let client = fake_server().await;
+        let err = client.shell("emulator-5556", "true").await.unwrap_err();
+        assert!(matches!(err, AdbClientError::DeviceOffline(ref serial) if serial == "emulator-5556"));
+
+        let err = client.shell("emulator-5999", "true").await.unwrap_err();
+        assert!(matches!(err, AdbClientError::DeviceNotFound(_)));
+    }
+
+    #[test]
+    async fn test_sync_push_pull() {
+        let client = fake_server().await;
+        let mut sync = client.sync("emulator-5554").await.unwrap();
+
+        assert!(!sync.stat("/data/local/tmp/app.apk").await.unwrap().exists());
+        let data = vec![7u8; SYNC_CHUNK_SIZE * 2 + 10];
+        sync.push(&mut &data[..], "/data/local/tmp/app.apk", 0o644).await.unwrap();
+
+        let stat = sync.stat("/data/local/tmp/app.apk").await.unwrap();
+        assert_eq!(stat.size as usize, data.len());
+        assert_eq!(sync.pull("/data/local/tmp/app.apk").await.unwrap(), data);
+
+        // A missing file is not a missing device
+        let err = sync.pull("/data/local/tmp/missing").await.unwrap_err();
+        assert!(matches!(err, AdbClientError::Failed(ref message) if message.contains("not found")));
+        sync.quit().await.unwrap();
+    }
+
+    #[test]
+    async fn test_sync_rejects_oversized_data() {
+        let client = fake_server().await;
+        let mut sync = client.sync("emulator-5554").await.unwrap();
+        let err = sync.pull("/data/huge").await.unwrap_err();
+        assert!(matches!(err, AdbClientError::Protocol(_)));
+    }
+
+    #[test]
+    async fn test_sync_timeout() {
+        let client = fake_server().await.with_timeout(Duration::from_millis(100));
+        let mut sync = client.sync("emulator-5554").await.unwrap();
+        let err = sync.stat("/data/hang").await.unwrap_err();
+        assert!(matches!(err, AdbClientError::Timeout(_)));
+    }
+
+    #[test]
+    async fn test_transient_failures_are_retried() {
+        // The device is offline for the first request only
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            read_request(&mut stream).await;
+            fail(&mut stream, "device offline").await;
+            let (stream, _) = listener.accept().await.unwrap();
+            serve(stream, Files::default()).await;
+        });
+        let policy = CommandPolicy {
+            retries: 1,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        };
+
+        let client = AdbClient::new(addr).with_policy(policy);
+        let sdk = client.shell("emulator-5554", "getprop ro.build.version.sdk").await.unwrap();
+        assert_eq!(sdk.stdout_lossy().trim(), "34");
+    }
+
+    #[test]
+    async fn test_server_unavailable() {
+        // Bind and drop a listener to get a port nothing listens on
+        let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+        let err = AdbClient::new(addr).devices().await.unwrap_err();
+        assert!(matches!(err, AdbClientError::ServerUnavailable(_)));
+    }
+}
@@ -1,12 +1,26 @@
+use serde::{Deserialize, Serialize};
 use std::path::Path;
-use tokio::process::Command as TokioCommand;
+use std::time::Duration;
 use thiserror::Error;
 use log::{info, error};
 
+use super::activity::{self, LaunchResult};
+use super::adb_client::{AdbClient, AdbClientError};
+use super::intent::{self, Intent, IntentResult};
+use super::packages::{self, InstalledPackage};
+use super::process::{self, AppProcess};
+use crate::apk::bundle::DeviceProfile;
+use super::runner::{CommandOutput, CommandPolicy, SharedRunner, SystemRunner};
+use super::shell;
+
 #[derive(Error, Debug)]
 pub enum AppError {
-    #[error("Failed to install app: {0}")]
-    InstallError(String),
+    #[error("Failed to install app: {message}")]
+    InstallError {
+        message: String,
+        /// The package manager's reason, e.g. `INSTALL_FAILED_VERSION_DOWNGRADE`
+        failure: Option<String>,
+    },
     #[error("Failed to uninstall app: {0}")]
     UninstallError(String),
     #[error("Failed to start app: {0}")]
@@ -17,175 +31,1171 @@ pub enum AppError {
     StatusError(String),
     #[error("Invalid APK path: {0}")]
     InvalidApkPath(String),
+    #[error("{command} timed out after {after:?}")]
+    Timeout { command: String, after: Duration },
+    /// The adb server or the device cannot be reached
+    #[error(transparent)]
+    Adb(AdbClientError),
+    #[error("Invalid package name {0:?}")]
+    InvalidPackageName(String),
+    #[error("Invalid activity name {0:?}")]
+    InvalidActivity(String),
+    #[error("Invalid file name {0:?}")]
+    InvalidFileName(String),
+    #[error("Invalid install option: {0}")]
+    InvalidInstallOption(String),
+    #[error("Invalid intent: {0}")]
+    InvalidIntent(String),
+    #[error("Failed to send intent: {0}")]
+    IntentError(String),
+}
+
+/// Package manager failure codes reported to clients as they are, in lowercase
+const INSTALL_FAILURES: &[&str] = &[
+    "install_failed_already_exists",
+    "install_failed_invalid_apk",
+    "install_failed_insufficient_storage",
+    "install_failed_duplicate_package",
+    "install_failed_update_incompatible",
+    "install_failed_shared_user_incompatible",
+    "install_failed_missing_shared_library",
+    "install_failed_older_sdk",
+    "install_failed_newer_sdk",
+    "install_failed_conflicting_provider",
+    "install_failed_test_only",
+    "install_failed_cpu_abi_incompatible",
+    "install_failed_no_matching_abis",
+    "install_failed_missing_feature",
+    "install_failed_missing_split",
+    "install_failed_invalid_install_location",
+    "install_failed_verification_failure",
+    "install_failed_version_downgrade",
+    "install_failed_permission_model_downgrade",
+    "install_failed_deprecated_sdk_version",
+    "install_failed_user_restricted",
+    "install_failed_instant_app_invalid",
+    "install_failed_duplicate_permission",
+    "install_failed_aborted",
+    "install_failed_internal_error",
+    "install_parse_failed_not_apk",
+    "install_parse_failed_bad_manifest",
+    "install_parse_failed_no_certificates",
+    "install_parse_failed_inconsistent_certificates",
+    "install_parse_failed_bad_package_name",
+    "install_parse_failed_manifest_malformed",
+];
+
+const MAIN_ACTION: &str = "android.intent.action.MAIN";
+const LAUNCHER_CATEGORY: &str = "android.intent.category.LAUNCHER";
+
+/// ABIs `--abi` accepts
+const ABIS: &[&str] = &[
+    "armeabi",
+    "armeabi-v7a",
+    "arm64-v8a",
+    "x86",
+    "x86_64",
+    "mips",
+    "mips64",
+    "riscv64",
+];
+
+impl AppError {
+    /// An installation failure, with the package manager's reason if the output has one
+    pub fn install(message: String) -> Self {
+        let failure = parse_install_failure(&message);
+        AppError::InstallError { message, failure }
+    }
+
+    /// Stable, machine-readable identifier of the error for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::InstallError { failure, .. } => failure
+                .as_deref()
+                .and_then(|failure| {
+                    INSTALL_FAILURES
+                        .iter()
+                        .find(|code| code.eq_ignore_ascii_case(failure))
+                        .copied()
+                })
+                .unwrap_or("install_failed"),
+            AppError::UninstallError(_) => "uninstall_failed",
+            AppError::StartError(_) => "app_start_failed",
+            AppError::StopError(_) => "app_stop_failed",
+            AppError::StatusError(_) => "app_status_failed",
+            AppError::InvalidApkPath(_) => "invalid_apk_path",
+            AppError::Timeout { .. } => "timeout",
+            AppError::Adb(e) => e.code(),
+            AppError::InvalidPackageName(_) => "invalid_package_name",
+            AppError::InvalidActivity(_) => "invalid_activity",
+            AppError::InvalidFileName(_) => "invalid_file_name",
+            AppError::InvalidInstallOption(_) => "invalid_install_option",
+            AppError::InvalidIntent(_) => "invalid_intent",
+            AppError::IntentError(_) => "intent_failed",
+        }
+    }
+}
+
+/// Whether `name` is a dot-separated list of identifiers made of `extra` characters,
+/// ASCII letters, digits and underscores, none starting with a digit
+pub(super) fn is_dotted_name(name: &str, extra: &[char]) -> bool {
+    name.split('.').all(|segment| {
+        segment
+            .chars()
+            .next()
+            .is_some_and(|c| !c.is_ascii_digit())
+            && segment
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || extra.contains(&c))
+    })
+}
+
+/// Check a package name against the Android grammar, e.g. `com.example.app`
+pub fn validate_package_name(package: &str) -> Result<(), AppError> {
+    if package.len() <= 255 && is_dotted_name(package, &[]) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidPackageName(package.to_string()))
+    }
+}
+
+/// Check an activity class name, either fully qualified (`com.example.MainActivity`)
+/// or relative to the package (`.MainActivity`); nested classes use `$`
+pub fn validate_activity(activity: &str) -> Result<(), AppError> {
+    let class = activity.strip_prefix('.').unwrap_or(activity);
+    if activity.len() <= 255 && is_dotted_name(class, &['$']) {
+        Ok(())
+    } else {
+        Err(AppError::InvalidActivity(activity.to_string()))
+    }
+}
+
+/// Check the name a file gets on the device, e.g. `main.12.com.example.obb`
+pub fn validate_file_name(name: &str) -> Result<(), AppError> {
+    let valid = name.len() <= 255
+        && !name.starts_with('.')
+        && !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::InvalidFileName(name.to_string()))
+    }
+}
+
+/// Find the `INSTALL_FAILED_*` or `INSTALL_PARSE_FAILED_*` code in install output,
+/// e.g. `adb: failed to install app.apk: Failure [INSTALL_FAILED_OLDER_SDK: ...]`
+fn parse_install_failure(output: &str) -> Option<String> {
+    let start = output
+        .find("INSTALL_FAILED_")
+        .or_else(|| output.find("INSTALL_PARSE_FAILED_"))?;
+    let code: String = output[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || *c == '_')
+        .collect();
+    Some(code)
+}
+
+/// Check the output of a package manager install command, which reports
+/// `Success` or `Failure [<reason>]`
+fn check_install(output: String) -> Result<(), AppError> {
+    if output.contains("Success") && !output.contains("Failure") {
+        Ok(())
+    } else {
+        Err(AppError::install(output))
+    }
+}
+
+/// The session id in `Success: created install session [1234]`
+fn parse_session_id(output: &str) -> Option<String> {
+    let start = output.find('[')? + 1;
+    let end = start + output[start..].find(']')?;
+    let session = &output[start..end];
+    session.chars().all(|c| c.is_ascii_digit()).then(|| session.to_string())
+}
+
+/// How the package manager installs an app, beyond replacing an existing installation
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallOptions {
+    /// Grant all runtime permissions the app requests (`-g`)
+    #[serde(default)]
+    pub grant_permissions: bool,
+    /// Allow replacing the app with a lower version code (`-d`)
+    #[serde(default)]
+    pub allow_downgrade: bool,
+    /// Allow APKs marked `android:testOnly` (`-t`)
+    #[serde(default)]
+    pub allow_test_packages: bool,
+    /// Install for one user only (`--user`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<u32>,
+    /// Install as an instant app (`--instant`)
+    #[serde(default)]
+    pub instant: bool,
+    /// Install the native libraries for this ABI instead of the primary one (`--abi`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub abi: Option<String>,
+}
+
+impl InstallOptions {
+    /// The `pm install` flags for the options
+    pub fn to_args(&self) -> Result<Vec<String>, AppError> {
+        let mut args = Vec::new();
+        for (enabled, flag) in [
+            (self.grant_permissions, "-g"),
+            (self.allow_downgrade, "-d"),
+            (self.allow_test_packages, "-t"),
+            (self.instant, "--instant"),
+        ] {
+            if enabled {
+                args.push(flag.to_string());
+            }
+        }
+        if let Some(user) = self.user {
+            args.extend(["--user".to_string(), user.to_string()]);
+        }
+        if let Some(abi) = &self.abi {
+            if !ABIS.contains(&abi.as_str()) {
+                return Err(AppError::InvalidInstallOption(format!(
+                    "unknown ABI {:?}, expected one of {}",
+                    abi,
+                    ABIS.join(", ")
+                )));
+            }
+            args.extend(["--abi".to_string(), abi.clone()]);
+        }
+        Ok(args)
+    }
 }
 
 /// Manages application installation and control on an emulator
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AppManager {
     device_id: String,
+    runner: SharedRunner,
+    policy: CommandPolicy,
 }
 
 impl AppManager {
     pub fn new(device_id: String) -> Self {
-        Self { device_id }
+        Self {
+            device_id,
+            runner: SystemRunner::shared(),
+            policy: CommandPolicy::default(),
+        }
     }
 
-    /// Install an APK file on the emulator
-    pub async fn install_app<P: AsRef<Path>>(&self, apk_path: P) -> Result<(), AppError> {
-        let apk_path = apk_path.as_ref().to_str().ok_or_else(|| {
-            AppError::InvalidApkPath("APK path contains invalid characters".to_string())
-        })?;
+    /// Run adb through `runner` instead of spawning it directly
+    pub fn with_runner(mut self, runner: SharedRunner) -> Self {
+        self.runner = runner;
+        self
+    }
 
-        info!("Installing app from {}", apk_path);
-        
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(&self.device_id)
-            .arg("install")
-            .arg("-r")  // Replace existing application
-            .arg(apk_path)
-            .output()
+    /// Apply timeouts and retries to adb commands
+    pub fn with_policy(mut self, policy: CommandPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Client for the adb server of the runner, with the timeout of `policy`
+    fn client(&self, policy: CommandPolicy) -> AdbClient {
+        AdbClient::for_runner(self.runner.as_ref(), policy)
+    }
+
+    /// Turn a failed adb request for `command` into an error of the operation,
+    /// keeping timeouts and unreachable devices apart
+    fn request_error(&self, command: &str, e: AdbClientError, error: fn(String) -> AppError) -> AppError {
+        match e {
+            AdbClientError::Timeout(after) => AppError::Timeout {
+                command: format!("{} on {}", command, self.device_id),
+                after,
+            },
+            e @ (AdbClientError::ServerUnavailable(_)
+            | AdbClientError::DeviceNotFound(_)
+            | AdbClientError::DeviceOffline(_)
+            | AdbClientError::Unauthorized(_)) => AppError::Adb(e),
+            e => error(e.to_string()),
+        }
+    }
+
+    /// Run a command in the device shell with every argument quoted, so values
+    /// cannot be interpreted by the shell; a non-zero exit status is mapped with `error`
+    async fn shell(
+        &self,
+        command: &[&str],
+        error: fn(String) -> AppError,
+    ) -> Result<CommandOutput, AppError> {
+        let command = shell::join(command);
+        let output = self
+            .client(self.policy)
+            .shell(&self.device_id, &command)
             .await
-            .map_err(|e| AppError::InstallError(e.to_string()))?;
+            .map_err(|e| self.request_error(&command, e, error))?;
+        if !output.success() {
+            // Tools such as pm report their failures on stdout
+            let stderr = output.stderr_lossy();
+            let message = if stderr.trim().is_empty() { output.stdout_lossy() } else { stderr };
+            return Err(error(message));
+        }
+        Ok(output)
+    }
+
+    /// Run a package manager command with `exec:`, streaming the file at `input`
+    /// to it if given, and return its output
+    async fn exec_install(&self, command: &[&str], input: Option<&Path>) -> Result<String, AppError> {
+        let command = shell::join(command);
+        let client = self.client(self.policy.for_install());
+        let output = match input {
+            Some(path) => {
+                let mut file = tokio::fs::File::open(path)
+                    .await
+                    .map_err(|e| AppError::install(format!("Cannot read {}: {}", path.display(), e)))?;
+                client.exec_with_input(&self.device_id, &command, &mut file).await
+            }
+            None => client.exec(&self.device_id, &command).await,
+        };
+        let output = output.map_err(|e| self.request_error(&command, e, AppError::install))?;
+        Ok(String::from_utf8_lossy(&output).into_owned())
+    }
+
+    /// Install an APK file on the emulator
+    pub async fn install_app<P: AsRef<Path>>(
+        &self,
+        apk_path: P,
+        options: &InstallOptions,
+    ) -> Result<(), AppError> {
+        self.install(&[apk_path.as_ref()], options).await
+    }
+
+    /// Install the split APKs of one app in a single session
+    pub async fn install_multiple<P: AsRef<Path>>(
+        &self,
+        apk_paths: &[P],
+        options: &InstallOptions,
+    ) -> Result<(), AppError> {
+        let apk_paths: Vec<&Path> = apk_paths.iter().map(AsRef::as_ref).collect();
+        self.install(&apk_paths, options).await
+    }
+
+    /// Install the APKs with the package manager, streaming them the way
+    /// `adb install` does: a single APK directly, splits through an install session
+    async fn install(
+        &self,
+        apk_paths: &[&Path],
+        options: &InstallOptions,
+    ) -> Result<(), AppError> {
+        let flags = options.to_args()?;
+        let mut apks = Vec::with_capacity(apk_paths.len());
+        for path in apk_paths {
+            if path.to_str().is_none() {
+                return Err(AppError::InvalidApkPath("APK path contains invalid characters".to_string()));
+            }
+            let size = tokio::fs::metadata(path)
+                .await
+                .map_err(|e| AppError::install(format!("Cannot read {}: {}", path.display(), e)))?
+                .len();
+            apks.push((*path, size.to_string()));
+        }
+        let description = apk_paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to install app: {}", error);
-            return Err(AppError::InstallError(error.to_string()));
+        info!("Installing app from {}", description);
+
+        let result = match apks.as_slice() {
+            [(path, size)] => {
+                // Replace an existing installation
+                let mut command = vec!["cmd", "package", "install", "-r"];
+                command.extend(flags.iter().map(String::as_str));
+                command.extend(["-S", size]);
+                self.exec_install(&command, Some(path)).await.and_then(check_install)
+            }
+            _ => self.install_session(&apks, &flags).await,
+        };
+        if let Err(e) = result {
+            error!("Failed to install app: {}", e);
+            return Err(e);
         }
 
-        info!("Successfully installed app from {}", apk_path);
+        info!("Successfully installed app from {}", description);
         Ok(())
     }
 
+    /// Install split APKs in one package manager session, abandoning the session
+    /// if any step fails
+    async fn install_session(&self, apks: &[(&Path, String)], flags: &[String]) -> Result<(), AppError> {
+        let mut command = vec!["cmd", "package", "install-create", "-r"];
+        command.extend(flags.iter().map(String::as_str));
+        let created = self.exec_install(&command, None).await?;
+        let session = parse_session_id(&created).ok_or_else(|| AppError::install(created.clone()))?;
+
+        let result = async {
+            for (index, (path, size)) in apks.iter().enumerate() {
+                let name = format!("{}.apk", index);
+                let command = ["cmd", "package", "install-write", "-S", size, &session, &name, "-"];
+                check_install(self.exec_install(&command, Some(path)).await?)?;
+            }
+            check_install(self.exec_install(&["cmd", "package", "install-commit", &session], None).await?)
+        }
+        .await;
+        if result.is_err() {
+            let _ = self.exec_install(&["cmd", "package", "install-abandon", &session], None).await;
+        }
+        result
+    }
+
+    /// Copy an expansion file into the app's OBB directory,
+    /// `/sdcard/Android/obb/<package>/<file_name>`
+    pub async fn push_obb<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        obb_path: P,
+        file_name: &str,
+    ) -> Result<(), AppError> {
+        validate_package_name(package_name)?;
+        validate_file_name(file_name)?;
+        let obb_path = obb_path.as_ref();
+        let remote = format!("/sdcard/Android/obb/{}/{}", package_name, file_name);
+        info!("Pushing {} to {}", obb_path.display(), remote);
+
+        let mut file = tokio::fs::File::open(obb_path)
+            .await
+            .map_err(|e| AppError::install(format!("Cannot read {}: {}", obb_path.display(), e)))?;
+        // OBBs run to gigabytes, so they get the install timeout
+        let push = async {
+            let mut sync = self.client(self.policy.for_install()).sync(&self.device_id).await?;
+            sync.push(&mut file, &remote, 0o644).await?;
+            sync.quit().await
+        };
+        if let Err(e) = push.await {
+            error!("Failed to push {}: {}", remote, e);
+            return Err(self.request_error(&format!("push {}", remote), e, AppError::install));
+        }
+        Ok(())
+    }
+
+    /// The ABIs and screen density that split APKs are chosen for
+    pub async fn device_profile(&self) -> Result<DeviceProfile, AppError> {
+        let getprop = |name| async move {
+            self.shell(&["getprop", name], AppError::StatusError)
+                .await
+                .map(|output| output.stdout_lossy().trim().to_string())
+        };
+
+        // Devices before Android 5 only report a single ABI
+        let mut abis = getprop("ro.product.cpu.abilist").await?;
+        if abis.is_empty() {
+            abis = getprop("ro.product.cpu.abi").await?;
+        }
+        let density = getprop("ro.sf.lcd_density").await?;
+        let density = density.parse().map_err(|_| {
+            AppError::StatusError(format!("Failed to parse screen density {:?}", density))
+        })?;
+
+        Ok(DeviceProfile {
+            abis: abis
+                .split(',')
+                .map(str::trim)
+                .filter(|abi| !abi.is_empty())
+                .map(str::to_string)
+                .collect(),
+            density,
+        })
+    }
+
     /// Uninstall an app from the emulator
     pub async fn uninstall_app(&self, package_name: &str) -> Result<(), AppError> {
+        validate_package_name(package_name)?;
         info!("Uninstalling app {}", package_name);
-        
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(&self.device_id)
-            .arg("uninstall")
-            .arg(package_name)
-            .output()
-            .await
-            .map_err(|e| AppError::UninstallError(e.to_string()))?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to uninstall app: {}", error);
-            return Err(AppError::UninstallError(error.to_string()));
+        let result = self
+            .shell(&["pm", "uninstall", package_name], AppError::UninstallError)
+            .await
+            .and_then(|output| {
+                // Older package managers report failures on stdout and still exit with 0
+                let stdout = output.stdout_lossy();
+                if stdout.contains("Failure") {
+                    Err(AppError::UninstallError(stdout))
+                } else {
+                    Ok(())
+                }
+            });
+        if let Err(e) = result {
+            error!("Failed to uninstall app: {}", e);
+            return Err(e);
         }
 
         info!("Successfully uninstalled app {}", package_name);
         Ok(())
     }
 
-    /// Start an app on the emulator
-    pub async fn start_app(&self, package_name: &str, activity: &str) -> Result<(), AppError> {
+    /// The activity the launcher starts for an app, e.g. `.MainActivity`, as the
+    /// device's package manager resolves it; `None` if the app has none
+    pub async fn resolve_launcher_activity(&self, package_name: &str) -> Result<Option<String>, AppError> {
+        validate_package_name(package_name)?;
+        let output = self
+            .shell(
+                &[
+                    "cmd",
+                    "package",
+                    "resolve-activity",
+                    "--brief",
+                    "-a",
+                    MAIN_ACTION,
+                    "-c",
+                    LAUNCHER_CATEGORY,
+                    package_name,
+                ],
+                AppError::StatusError,
+            )
+            .await?;
+
+        Ok(activity::parse_resolved_activity(&output.stdout_lossy(), package_name))
+    }
+
+    /// Start an app on the emulator. With `wait`, `am start -W` waits until the
+    /// activity has drawn and the launch timings are reported.
+    pub async fn start_app(
+        &self,
+        package_name: &str,
+        activity: &str,
+        wait: bool,
+    ) -> Result<LaunchResult, AppError> {
+        validate_package_name(package_name)?;
+        validate_activity(activity)?;
         info!("Starting app {}/{}", package_name, activity);
-        
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(&self.device_id)
-            .arg("shell")
-            .arg("am")
-            .arg("start")
-            .arg("-n")
-            .arg(format!("{}/{}", package_name, activity))
-            .output()
-            .await
-            .map_err(|e| AppError::StartError(e.to_string()))?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to start app: {}", error);
-            return Err(AppError::StartError(error.to_string()));
+        let component = format!("{}/{}", package_name, activity);
+        let mut args = vec!["am", "start"];
+        if wait {
+            args.push("-W");
         }
+        args.extend(["-n", &component]);
+        let result = self
+            .shell(&args, AppError::StartError)
+            .await
+            .and_then(|output| {
+                activity::parse_am_start(&output.stdout_lossy(), &component).map_err(AppError::StartError)
+            });
+        match result {
+            Ok(launch) => {
+                info!("Successfully started app {}", package_name);
+                Ok(launch)
+            }
+            Err(e) => {
+                error!("Failed to start app: {}", e);
+                Err(e)
+            }
+        }
+    }
 
-        info!("Successfully started app {}", package_name);
-        Ok(())
+    /// Send an intent to an activity, service or broadcast receivers with `am`
+    pub async fn send_intent(&self, intent: &Intent) -> Result<IntentResult, AppError> {
+        let args = intent.to_args()?;
+        info!("Sending intent: {}", args.join(" "));
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let output = self.shell(&args, AppError::IntentError).await?;
+        intent::parse_intent_result(intent, &output.stdout_lossy()).map_err(AppError::IntentError)
     }
 
     /// Stop an app on the emulator
     pub async fn stop_app(&self, package_name: &str) -> Result<(), AppError> {
+        validate_package_name(package_name)?;
         info!("Stopping app {}", package_name);
-        
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(&self.device_id)
-            .arg("shell")
-            .arg("am")
-            .arg("force-stop")
-            .arg(package_name)
-            .output()
-            .await
-            .map_err(|e| AppError::StopError(e.to_string()))?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to stop app: {}", error);
-            return Err(AppError::StopError(error.to_string()));
+        if let Err(e) = self
+            .shell(&["am", "force-stop", package_name], AppError::StopError)
+            .await
+        {
+            error!("Failed to stop app: {}", e);
+            return Err(e);
         }
 
         info!("Successfully stopped app {}", package_name);
         Ok(())
     }
 
-    /// Check if an app is currently running
-    pub async fn is_app_running(&self, package_name: &str) -> Result<bool, AppError> {
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(&self.device_id)
-            .arg("shell")
-            .arg("ps")
-            .arg("|")
-            .arg("grep")
-            .arg(package_name)
-            .output()
-            .await
-            .map_err(|e| AppError::StatusError(e.to_string()))?;
+    /// List installed package names, optionally only third-party packages
+    pub async fn list_packages(&self, third_party_only: bool) -> Result<Vec<String>, AppError> {
+        let mut args = vec!["pm", "list", "packages"];
+        if third_party_only {
+            args.push("-3");
+        }
+        let output = self.shell(&args, AppError::StatusError).await?;
 
-        Ok(!String::from_utf8_lossy(&output.stdout).trim().is_empty())
+        Ok(packages::parse_package_names(&output.stdout_lossy()))
+    }
+
+    /// List every installed package, system packages included, with its APK path,
+    /// version, installer and install times
+    pub async fn installed_packages(&self) -> Result<Vec<InstalledPackage>, AppError> {
+        let listed = self
+            .shell(&["pm", "list", "packages", "-f", "--show-versioncode"], AppError::StatusError)
+            .await?;
+        let listed = packages::parse_package_list(&listed.stdout_lossy()).map_err(AppError::StatusError)?;
+        let system = self
+            .shell(&["pm", "list", "packages", "-s"], AppError::StatusError)
+            .await?;
+        let system = packages::parse_package_names(&system.stdout_lossy());
+        let dumpsys = self
+            .shell(&["dumpsys", "package"], AppError::StatusError)
+            .await?;
+        let mut details = packages::parse_dumpsys_packages(&dumpsys.stdout_lossy());
+
+        let mut installed: Vec<InstalledPackage> = listed
+            .into_iter()
+            .map(|(package, apk_path, version_code)| {
+                let details = details.remove(&package).unwrap_or_default();
+                InstalledPackage {
+                    system: system.contains(&package),
+                    version_code: version_code.or(details.version_code),
+                    version_name: details.version_name,
+                    installer: details.installer,
+                    first_install_time: details.first_install_time,
+                    last_update_time: details.last_update_time,
+                    package,
+                    apk_path,
+                }
+            })
+            .collect();
+        installed.sort_by(|a, b| a.package.cmp(&b.package));
+        Ok(installed)
+    }
+
+    /// List the running processes of an app, including its `:name` subprocesses;
+    /// the list is empty when the app is not running
+    pub async fn processes(&self, package_name: &str) -> Result<Vec<AppProcess>, AppError> {
+        validate_package_name(package_name)?;
+        let output = self
+            .shell(&["ps", "-A", "-o", process::PS_COLUMNS], AppError::StatusError)
+            .await?;
+
+        let processes = process::parse_ps(&output.stdout_lossy()).map_err(AppError::StatusError)?;
+        Ok(processes
+            .into_iter()
+            .filter(|process| process.belongs_to(package_name))
+            .collect())
     }
 
     /// Get the version of an installed app
     pub async fn get_app_version(&self, package_name: &str) -> Result<String, AppError> {
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(&self.device_id)
-            .arg("shell")
-            .arg("dumpsys")
-            .arg("package")
-            .arg(package_name)
-            .arg("|")
-            .arg("grep")
-            .arg("versionName")
-            .output()
-            .await
-            .map_err(|e| AppError::StatusError(e.to_string()))?;
-
-        let version = String::from_utf8_lossy(&output.stdout)
-            .lines()
-            .next()
-            .and_then(|line| line.split('=').nth(1))
-            .map(|v| v.trim().to_string())
-            .ok_or_else(|| AppError::StatusError("Failed to parse app version".to_string()))?;
+        validate_package_name(package_name)?;
+        let output = self
+            .shell(&["dumpsys", "package", package_name], AppError::StatusError)
+            .await?;
 
-        Ok(version)
+        packages::parse_dumpsys_packages(&output.stdout_lossy())
+            .remove(package_name)
+            .and_then(|details| details.version_name)
+            .ok_or_else(|| AppError::StatusError("Failed to parse app version".to_string()))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::emulator::runner::{FakeResponse, FakeRunner};
+    use std::path::PathBuf;
+    use std::sync::Arc;
     use tokio::test;
 
+    fn fake_manager() -> (AppManager, FakeRunner) {
+        let runner = FakeRunner::new();
+        let manager = AppManager::new("emulator-5554".to_string())
+            .with_runner(Arc::new(runner.clone()))
+            .with_policy(CommandPolicy {
+                backoff: Duration::from_millis(1),
+                ..Default::default()
+            });
+        (manager, runner)
+    }
+
+    /// Write a file of the test to a fresh temporary directory
+    fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("app-manager-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Let every package manager command on the fake device succeed
+    fn package_manager(runner: &FakeRunner) {
+        runner.on("adb", &["-s", "emulator-5554", "exec-out"], FakeResponse::ok("Success\n"));
+        runner.on(
+            "adb",
+            &["-s", "emulator-5554", "exec-out", "cmd package install-create -r"],
+            FakeResponse::ok("Success: created install session [1234]\n"),
+        );
+    }
+
     #[test]
     async fn test_app_manager_creation() {
         let manager = AppManager::new("emulator-5554".to_string());
         assert_eq!(manager.device_id, "emulator-5554");
     }
 
-    // Note: Additional integration tests would require actual APK files and running emulator
+    #[test]
+    async fn test_install_app() {
+        let (manager, runner) = fake_manager();
+        let apk = temp_file("app.apk", b"PK\x03\x04app");
+        package_manager(&runner);
+        manager.install_app(&apk, &InstallOptions::default()).await.unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            vec!["adb -s emulator-5554 exec-out cmd package install -r -S 7"]
+        );
+
+        runner.on("adb", &["-s", "emulator-5554", "exec-out"], FakeResponse::fail(1, "Failure [INSTALL_FAILED_OLDER_SDK]"));
+        let err = manager.install_app(&apk, &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(err, AppError::InstallError { ref message, .. } if message.contains("INSTALL_FAILED_OLDER_SDK")));
+
+        // Nothing is sent to the device for a file that cannot be read
+        runner.clear_calls();
+        let err = manager.install_app("/nonexistent/app.apk", &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(err, AppError::InstallError { .. }));
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    async fn test_install_options() {
+        let (manager, runner) = fake_manager();
+        let apk = temp_file("app.apk", b"PK\x03\x04app");
+        package_manager(&runner);
+        let options = InstallOptions {
+            grant_permissions: true,
+            allow_downgrade: true,
+            allow_test_packages: true,
+            user: Some(10),
+            instant: true,
+            abi: Some("arm64-v8a".to_string()),
+        };
+        manager.install_app(&apk, &options).await.unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            vec!["adb -s emulator-5554 exec-out cmd package install -r -g -d -t --instant --user 10 --abi arm64-v8a -S 7"]
+        );
+
+        let options = InstallOptions {
+            abi: Some("x86_64 -g".to_string()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            manager.install_app(&apk, &options).await,
+            Err(AppError::InvalidInstallOption(_))
+        ));
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[test]
+    async fn test_install_failure_codes() {
+        let (manager, runner) = fake_manager();
+        let apk = temp_file("app.apk", b"PK\x03\x04app");
+        runner.on(
+            "adb",
+            &[],
+            FakeResponse::fail(
+                1,
+                "Failure [INSTALL_FAILED_VERSION_DOWNGRADE: Downgrade detected: Update version code 1 is older than current 2]",
+            ),
+        );
+        let err = manager.install_app(&apk, &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::InstallError { failure: Some(ref failure), .. } if failure == "INSTALL_FAILED_VERSION_DOWNGRADE"
+        ));
+        assert_eq!(err.code(), "install_failed_version_downgrade");
+
+        // The package manager of old images prints the failure on stdout
+        runner.on("adb", &[], FakeResponse::ok("\tpkg: /data/local/tmp/app.apk\nFailure [INSTALL_FAILED_INSUFFICIENT_STORAGE]\n"));
+        let err = manager.install_app(&apk, &InstallOptions::default()).await.unwrap_err();
+        assert_eq!(err.code(), "install_failed_insufficient_storage");
+
+        // Unknown codes are kept but reported as generic failures
+        runner.on("adb", &[], FakeResponse::fail(1, "Failure [INSTALL_FAILED_SOMETHING_NEW]"));
+        let err = manager.install_app(&apk, &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(err, AppError::InstallError { failure: Some(ref failure), .. } if failure == "INSTALL_FAILED_SOMETHING_NEW"));
+        assert_eq!(err.code(), "install_failed");
+
+        runner.on("adb", &[], FakeResponse::fail(1, "cmd: Can't find service: package"));
+        let err = manager.install_app(&apk, &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(err, AppError::InstallError { failure: None, .. }));
+    }
+
+    #[test]
+    async fn test_install_multiple_and_push_obb() {
+        let (manager, runner) = fake_manager();
+        let base = temp_file("base.apk", b"PK\x03\x04base");
+        let split = temp_file("config.x86_64.apk", b"PK\x03\x04x86_64");
+        let obb = temp_file("main.obb", b"expansion");
+        package_manager(&runner);
+        manager.install_multiple(&[&base, &split], &InstallOptions::default()).await.unwrap();
+        manager
+            .push_obb("com.example.game", &obb, "main.12.com.example.game.obb")
+            .await
+            .unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            vec![
+                "adb -s emulator-5554 exec-out cmd package install-create -r",
+                "adb -s emulator-5554 exec-out cmd package install-write -S 8 1234 0.apk -",
+                "adb -s emulator-5554 exec-out cmd package install-write -S 10 1234 1.apk -",
+                "adb -s emulator-5554 exec-out cmd package install-commit 1234",
+                "adb -s emulator-5554 push /sdcard/Android/obb/com.example.game/main.12.com.example.game.obb",
+            ]
+        );
+        assert_eq!(runner.calls()[4].stdin.as_deref(), Some(b"expansion".as_slice()));
+
+        for name in ["../../data/app.obb", ".hidden", "a b.obb", ""] {
+            assert!(matches!(
+                manager.push_obb("com.example.game", &obb, name).await,
+                Err(AppError::InvalidFileName(_))
+            ));
+        }
+        assert_eq!(runner.calls().len(), 5);
+
+        // A failed split abandons the session
+        runner.clear_calls();
+        runner.on(
+            "adb",
+            &["-s", "emulator-5554", "exec-out", "cmd package install-write -S 10 1234 1.apk -"],
+            FakeResponse::ok("Failure [INSTALL_FAILED_INVALID_APK]\n"),
+        );
+        let err = manager.install_multiple(&[&base, &split], &InstallOptions::default()).await.unwrap_err();
+        assert_eq!(err.code(), "install_failed_invalid_apk");
+        assert_eq!(
+            runner.command_lines().last().unwrap(),
+            "adb -s emulator-5554 exec-out cmd package install-abandon 1234"
+        );
+
+        runner.on("adb", &["-s", "emulator-5554", "push"], FakeResponse::fail(1, "No space left on device"));
+        let err = manager
+            .push_obb("com.example.game", &obb, "main.12.com.example.game.obb")
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::InstallError { ref message, .. } if message.contains("No space left")));
+    }
+
+    #[test]
+    async fn test_device_profile() {
+        let (manager, runner) = fake_manager();
+        runner.on("adb", &["-s", "emulator-5554", "shell", "getprop ro.product.cpu.abilist"], FakeResponse::ok("x86_64,arm64-v8a\n"));
+        runner.on("adb", &["-s", "emulator-5554", "shell", "getprop ro.sf.lcd_density"], FakeResponse::ok("420\n"));
+        assert_eq!(
+            manager.device_profile().await.unwrap(),
+            DeviceProfile {
+                abis: vec!["x86_64".to_string(), "arm64-v8a".to_string()],
+                density: 420,
+            }
+        );
+
+        // Older images only have the single-ABI property
+        runner.on("adb", &["-s", "emulator-5554", "shell", "getprop ro.product.cpu.abilist"], FakeResponse::ok("\n"));
+        runner.on("adb", &["-s", "emulator-5554", "shell", "getprop ro.product.cpu.abi"], FakeResponse::ok("x86\n"));
+        assert_eq!(manager.device_profile().await.unwrap().abis, vec!["x86"]);
+
+        runner.on("adb", &["-s", "emulator-5554", "shell", "getprop ro.sf.lcd_density"], FakeResponse::ok(""));
+        assert!(matches!(manager.device_profile().await, Err(AppError::StatusError(_))));
+    }
+
+    #[test]
+    async fn test_install_timeout() {
+        let (manager, runner) = fake_manager();
+        let manager = manager.with_policy(CommandPolicy {
+            install_timeout: Duration::from_millis(50),
+            ..Default::default()
+        });
+        runner.on("adb", &[], FakeResponse::ok("").running_for(Duration::from_secs(5)));
+
+        let apk = temp_file("app.apk", b"PK\x03\x04app");
+        let err = manager.install_app(&apk, &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(err, AppError::Timeout { after, .. } if after == Duration::from_millis(50)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    async fn test_install_app_invalid_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let (manager, runner) = fake_manager();
+        let path = Path::new(OsStr::from_bytes(b"/tmp/\xffapp.apk"));
+        let err = manager.install_app(path, &InstallOptions::default()).await.unwrap_err();
+        assert!(matches!(err, AppError::InvalidApkPath(_)));
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    async fn test_command_errors() {
+        let (manager, runner) = fake_manager();
+        runner.on("adb", &[], FakeResponse::fail(1, "Failure [DELETE_FAILED_INTERNAL_ERROR]"));
+
+        assert!(matches!(
+            manager.uninstall_app("com.example").await,
+            Err(AppError::UninstallError(_))
+        ));
+        assert!(matches!(
+            manager.start_app("com.example", ".MainActivity", false).await,
+            Err(AppError::StartError(_))
+        ));
+        assert!(matches!(
+            manager.stop_app("com.example").await,
+            Err(AppError::StopError(_))
+        ));
+        assert!(matches!(
+            manager.list_packages(true).await,
+            Err(AppError::StatusError(_))
+        ));
+
+        // The package manager of old images exits successfully on failure
+        runner.on("adb", &[], FakeResponse::ok("Failure [DELETE_FAILED_INTERNAL_ERROR]\n"));
+        assert!(matches!(
+            manager.uninstall_app("com.example").await,
+            Err(AppError::UninstallError(ref e)) if e.contains("DELETE_FAILED")
+        ));
+
+        // An unreachable device is reported as such, whatever the operation
+        runner.on("adb", &[], FakeResponse::fail(1, "error: device offline"));
+        assert!(matches!(
+            manager.processes("com.example").await,
+            Err(AppError::Adb(AdbClientError::DeviceOffline(_)))
+        ));
+        assert_eq!(
+            manager.uninstall_app("com.example").await.unwrap_err().code(),
+            "device_offline"
+        );
+    }
+
+    #[test]
+    async fn test_start_and_stop_app() {
+        let (manager, runner) = fake_manager();
+        let launch = manager.start_app("com.example", ".MainActivity", false).await.unwrap();
+        assert_eq!(launch.component, "com.example/.MainActivity");
+        manager.stop_app("com.example").await.unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            vec![
+                "adb -s emulator-5554 shell am start -n com.example/.MainActivity",
+                "adb -s emulator-5554 shell am force-stop com.example",
+            ]
+        );
+    }
+
+    #[test]
+    async fn test_start_app_waits_for_launch() {
+        let (manager, runner) = fake_manager();
+        runner.on(
+            "adb",
+            &[],
+            FakeResponse::ok("Status: ok\nLaunchState: COLD\nActivity: com.example/.MainActivity\nTotalTime: 812\nWaitTime: 820\nComplete\n"),
+        );
+        let launch = manager.start_app("com.example", ".MainActivity", true).await.unwrap();
+        assert_eq!(launch.total_time_ms, Some(812));
+        assert_eq!(launch.launch_state.as_deref(), Some("COLD"));
+        assert_eq!(
+            runner.command_lines(),
+            vec!["adb -s emulator-5554 shell am start -W -n com.example/.MainActivity"]
+        );
+
+        // am reports a missing activity on stdout and exits successfully
+        runner.on(
+            "adb",
+            &[],
+            FakeResponse::ok("Error type 3\nError: Activity class {com.example/com.example.Gone} does not exist.\n"),
+        );
+        assert!(matches!(
+            manager.start_app("com.example", ".Gone", true).await,
+            Err(AppError::StartError(ref e)) if e.contains("does not exist")
+        ));
+    }
+
+    #[test]
+    async fn test_send_intent() {
+        let (manager, runner) = fake_manager();
+        let deep_link = Intent {
+            action: Some("android.intent.action.VIEW".to_string()),
+            data: Some("myapp://search?q=it's&page=2".to_string()),
+            extras: vec![intent::IntentExtra {
+                key: "query".to_string(),
+                value: intent::ExtraValue::String("$(reboot)".to_string()),
+            }],
+            ..Default::default()
+        };
+        manager.send_intent(&deep_link).await.unwrap();
+        assert_eq!(
+            runner.command_lines(),
+            vec![r"adb -s emulator-5554 shell am start -a android.intent.action.VIEW -d 'myapp://search?q=it'\''s&page=2' --es query '$(reboot)'"]
+        );
+
+        runner.on(
+            "adb",
+            &[],
+            FakeResponse::ok("Error: Activity not started, unable to resolve Intent { act=android.intent.action.VIEW }\n"),
+        );
+        assert!(matches!(
+            manager.send_intent(&deep_link).await,
+            Err(AppError::IntentError(ref e)) if e.starts_with("Activity not started")
+        ));
+        assert_eq!(runner.calls().len(), 2);
+    }
+
+    #[test]
+    async fn test_resolve_launcher_activity() {
+        let (manager, runner) = fake_manager();
+        runner.on(
+            "adb",
+            &[],
+            FakeResponse::ok("priority=0 preferredOrder=0 match=0x108000 specificIndex=-1 isDefault=false\ncom.example/.MainActivity\n"),
+        );
+        assert_eq!(
+            manager.resolve_launcher_activity("com.example").await.unwrap().as_deref(),
+            Some(".MainActivity")
+        );
+        assert_eq!(
+            runner.command_lines(),
+            vec!["adb -s emulator-5554 shell cmd package resolve-activity --brief -a android.intent.action.MAIN -c android.intent.category.LAUNCHER com.example"]
+        );
+
+        runner.on("adb", &[], FakeResponse::ok("No activity found\n"));
+        assert_eq!(manager.resolve_launcher_activity("com.example").await.unwrap(), None);
+    }
+
+    #[test]
+    async fn test_list_packages_and_version() {
+        let (manager, runner) = fake_manager();
+        runner.on("adb", &["-s", "emulator-5554", "shell"], FakeResponse::ok("package:com.a\npackage:com.b\n"));
+        assert_eq!(manager.list_packages(true).await.unwrap(), vec!["com.a", "com.b"]);
+
+        runner.on(
+            "adb",
+            &["-s", "emulator-5554", "shell"],
+            FakeResponse::ok("Packages:\n  Package [com.a] (1f2e3d):\n    versionCode=7\n    versionName=1.2.3\n"),
+        );
+        assert_eq!(manager.get_app_version("com.a").await.unwrap(), "1.2.3");
+        assert_eq!(
+            runner.command_lines(),
+            vec![
+                "adb -s emulator-5554 shell pm list packages -3",
+                "adb -s emulator-5554 shell dumpsys package com.a",
+            ]
+        );
+
+        runner.on("adb", &["-s", "emulator-5554", "shell"], FakeResponse::ok(""));
+        assert!(matches!(
+            manager.get_app_version("com.a").await,
+            Err(AppError::StatusError(_))
+        ));
+    }
+
+    #[test]
+    async fn test_installed_packages() {
+        let (manager, runner) = fake_manager();
+        runner.on(
+            "adb",
+            &["-s", "emulator-5554", "shell", "pm list packages -f --show-versioncode"],
+            FakeResponse::ok(
+                "package:/data/app/~~Zq==/com.foo-Xw==/base.apk=com.foo versionCode:12\n\
+                 package:/data/app/~~Yr==/com.android.chrome-Ab==/base.apk=com.android.chrome versionCode:600\n",
+            ),
+        );
+        runner.on(
+            "adb",
+            &["-s", "emulator-5554", "shell", "pm list packages -s"],
+            FakeResponse::ok("package:com.android.chrome\n"),
+        );
+        runner.on(
+            "adb",
+            &["-s", "emulator-5554", "shell", "dumpsys package"],
+            FakeResponse::ok(
+                "Packages:\n  Package [com.foo] (1f2e3d):\n    versionCode=12 minSdk=24 targetSdk=34\n    \
+                 versionName=1.2.0\n    firstInstallTime=2024-04-01 09:30:00\n    installerPackageName=com.android.vending\n",
+            ),
+        );
+
+        let installed = manager.installed_packages().await.unwrap();
+        assert_eq!(installed.len(), 2);
+        assert_eq!(installed[0].package, "com.android.chrome");
+        assert!(installed[0].system);
+        assert_eq!(installed[0].version_code, Some(600));
+        assert_eq!(installed[0].version_name, None);
+        assert_eq!(
+            installed[1],
+            InstalledPackage {
+                package: "com.foo".to_string(),
+                apk_path: "/data/app/~~Zq==/com.foo-Xw==/base.apk".to_string(),
+                version_code: Some(12),
+                version_name: Some("1.2.0".to_string()),
+                system: false,
+                installer: Some("com.android.vending".to_string()),
+                first_install_time: Some("2024-04-01 09:30:00".to_string()),
+                last_update_time: None,
+            }
+        );
+    }
+
+    #[test]
+    async fn test_processes() {
+        let (manager, runner) = fake_manager();
+        runner.on(
+            "adb",
+            &[],
+            FakeResponse::ok(
+                "PID USER RSS STIME NAME\n\
+                 4242 u0_a123 154320 09:14:55 com.example\n\
+                 4400 u0_a124 88004 09:20:00 com.example2\n",
+            ),
+        );
+        let processes = manager.processes("com.example").await.unwrap();
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].pid, 4242);
+        assert_eq!(
+            runner.command_lines()[0],
+            "adb -s emulator-5554 shell ps -A -o PID,USER,RSS,STIME,NAME"
+        );
+
+        // A failing ps is an error rather than "not running"
+        runner.on("adb", &[], FakeResponse::fail(1, "ps: bad -o"));
+        assert!(matches!(
+            manager.processes("com.example").await,
+            Err(AppError::StatusError(_))
+        ));
+    }
+
+    #[test]
+    async fn test_rejects_unsafe_arguments() {
+        let (manager, runner) = fake_manager();
+        assert!(matches!(
+            manager.stop_app("x; rm -rf /sdcard").await,
+            Err(AppError::InvalidPackageName(_))
+        ));
+        assert!(matches!(
+            manager.start_app("com.example", ".Main; reboot", false).await,
+            Err(AppError::InvalidActivity(_))
+        ));
+        assert!(matches!(
+            manager.processes("com.example | reboot").await,
+            Err(AppError::InvalidPackageName(_))
+        ));
+        assert!(runner.calls().is_empty());
+    }
+
+    #[test]
+    async fn test_name_grammar() {
+        for package in ["android", "com.example", "com.Example_2.app"] {
+            assert!(validate_package_name(package).is_ok(), "{}", package);
+        }
+        for package in ["", "com..example", "com.2example", "com.example.", "com-example", "com.ex ample"] {
+            assert!(validate_package_name(package).is_err(), "{}", package);
+        }
+        for activity in [".MainActivity", "com.example.MainActivity", ".ui.Main$Inner"] {
+            assert!(validate_activity(activity).is_ok(), "{}", activity);
+        }
+        for activity in ["", ".", "..Main", "Main/../x", "$(reboot)"] {
+            assert!(validate_activity(activity).is_err(), "{}", activity);
+        }
+    }
 }
@@ -0,0 +1,607 @@
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::runner::{CommandError, CommandPolicy, CommandRunner, CommandSpec};
+use super::EmulatorError;
+
+/// Directory holding the AVDs of the current user.
+///
+/// Follows the SDK tools: `ANDROID_AVD_HOME`, then `ANDROID_SDK_HOME/.android/avd`
+/// (or `ANDROID_USER_HOME/avd`), then `~/.android/avd`.
+pub fn avd_home() -> PathBuf {
+    if let Some(dir) = env::var_os("ANDROID_AVD_HOME") {
+        return PathBuf::from(dir);
+    }
+    if let Some(dir) = env::var_os("ANDROID_USER_HOME") {
+        return PathBuf::from(dir).join("avd");
+    }
+    if let Some(dir) = env::var_os("ANDROID_SDK_HOME") {
+        return PathBuf::from(dir).join(".android").join("avd");
+    }
+    let home = env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_default();
+    home.join(".android").join("avd")
+}
+
+/// Content directory of an AVD, e.g. `~/.android/avd/<name>.avd`
+pub fn avd_dir(name: &str) -> PathBuf {
+    avd_home().join(format!("{}.avd", name))
+}
+
+/// Whether a file in an AVD directory holds user data that a wipe should remove
+fn is_user_data(file_name: &str) -> bool {
+    file_name.starts_with("userdata-qemu.img") || file_name.starts_with("cache.img")
+}
+
+/// Delete the user data partition, cache and snapshots of the AVD in `dir`,
+/// leaving its configuration and system image references intact.
+///
+/// Returns the removed paths.
+pub fn wipe_user_data_in(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut removed = Vec::new();
+    if !dir.exists() {
+        return Ok(removed);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().to_string();
+
+        if entry.file_type()?.is_dir() {
+            if file_name == "snapshots" {
+                fs::remove_dir_all(&path)?;
+                removed.push(path);
+            }
+        } else if is_user_data(&file_name) {
+            fs::remove_file(&path)?;
+            removed.push(path);
+        }
+    }
+
+    removed.sort();
+    Ok(removed)
+}
+
+/// Delete the user data and snapshots of the named AVD
+pub fn wipe_user_data(name: &str) -> io::Result<Vec<PathBuf>> {
+    wipe_user_data_in(&avd_dir(name))
+}
+
+/// Check that an AVD name only uses the characters avdmanager accepts
+pub fn validate_avd_name(name: &str) -> Result<(), EmulatorError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        && !name.starts_with(['.', '-']);
+    if valid {
+        Ok(())
+    } else {
+        Err(EmulatorError::InvalidAvdConfig(format!(
+            "invalid AVD name {:?}: use letters, digits, '_', '.' and '-'",
+            name
+        )))
+    }
+}
+
+/// Key/value file such as an AVD `config.ini`, preserving the order of its entries
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IniFile {
+    entries: Vec<(String, String)>,
+}
+
+impl IniFile {
+    pub fn parse(content: &str) -> Self {
+        let entries = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with(';'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect();
+        Self { entries }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Set a key, keeping its position if it already exists
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        let value = value.into();
+        match self.entries.iter_mut().find(|(k, _)| k == key) {
+            Some(entry) => entry.1 = value,
+            None => self.entries.push((key.to_string(), value)),
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn render(&self) -> String {
+        self.entries
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, value))
+            .collect()
+    }
+}
+
+/// Parse an ini size such as `2048`, `2048M`, `6G` or `536870912` into megabytes.
+/// Bare numbers of at least 1 MiB are treated as bytes, smaller ones as megabytes.
+fn parse_size_mb(value: &str) -> Option<u64> {
+    let value = value.trim();
+    let (number, unit) = match value.char_indices().find(|(_, c)| !c.is_ascii_digit()) {
+        Some((index, _)) => value.split_at(index),
+        None => (value, ""),
+    };
+    let number: u64 = number.parse().ok()?;
+    match unit.trim().to_ascii_uppercase().as_str() {
+        "" if number >= 1024 * 1024 => Some(number / (1024 * 1024)),
+        "" | "M" | "MB" => Some(number),
+        "K" | "KB" => Some(number / 1024),
+        "G" | "GB" => Some(number * 1024),
+        _ => None,
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "yes" | "true" | "1" => Some(true),
+        "no" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+fn format_bool(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Typed view of the commonly tuned settings in an AVD's `config.ini`.
+///
+/// Used both to read a configuration and, with only some fields set, to patch one.
+/// Settings without a typed field are available through `other`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AvdConfig {
+    /// `hw.ramSize`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ram_size_mb: Option<u64>,
+    /// `vm.heapSize`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vm_heap_size_mb: Option<u64>,
+    /// `disk.dataPartition.size`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data_partition_size_mb: Option<u64>,
+    /// `sdcard.size`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdcard_size_mb: Option<u64>,
+    /// `hw.cpu.ncore`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu_cores: Option<u32>,
+    /// `hw.lcd.density`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lcd_density: Option<u32>,
+    /// `hw.lcd.width`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lcd_width: Option<u32>,
+    /// `hw.lcd.height`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lcd_height: Option<u32>,
+    /// `hw.gpu.enabled`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_enabled: Option<bool>,
+    /// `hw.gpu.mode`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gpu_mode: Option<String>,
+    /// `hw.keyboard`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keyboard: Option<bool>,
+    /// `fastboot.forceColdBoot`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force_cold_boot: Option<bool>,
+    /// Every other setting, keyed by its `config.ini` name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub other: BTreeMap<String, String>,
+}
+
+const SIZE_KEYS: [&str; 4] = ["hw.ramSize", "vm.heapSize", "disk.dataPartition.size", "sdcard.size"];
+const NUMBER_KEYS: [&str; 4] = ["hw.cpu.ncore", "hw.lcd.density", "hw.lcd.width", "hw.lcd.height"];
+const BOOL_KEYS: [&str; 3] = ["hw.gpu.enabled", "hw.keyboard", "fastboot.forceColdBoot"];
+const TEXT_KEYS: [&str; 1] = ["hw.gpu.mode"];
+
+impl AvdConfig {
+    /// Build the typed view of an ini file
+    pub fn from_ini(ini: &IniFile) -> Self {
+        let size = |key| ini.get(key).and_then(parse_size_mb);
+        let number = |key| ini.get(key).and_then(|v| v.trim().parse().ok());
+        let boolean = |key| ini.get(key).and_then(parse_bool);
+
+        let typed: Vec<&str> = SIZE_KEYS
+            .iter()
+            .chain(&NUMBER_KEYS)
+            .chain(&BOOL_KEYS)
+            .chain(&TEXT_KEYS)
+            .copied()
+            .collect();
+
+        Self {
+            ram_size_mb: size("hw.ramSize"),
+            vm_heap_size_mb: size("vm.heapSize"),
+            data_partition_size_mb: size("disk.dataPartition.size"),
+            sdcard_size_mb: size("sdcard.size"),
+            cpu_cores: number("hw.cpu.ncore"),
+            lcd_density: number("hw.lcd.density"),
+            lcd_width: number("hw.lcd.width"),
+            lcd_height: number("hw.lcd.height"),
+            gpu_enabled: boolean("hw.gpu.enabled"),
+            gpu_mode: ini.get("hw.gpu.mode").map(str::to_string),
+            keyboard: boolean("hw.keyboard"),
+            force_cold_boot: boolean("fastboot.forceColdBoot"),
+            other: ini
+                .entries()
+                .filter(|(key, _)| !typed.contains(key))
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Check a patch before it is written
+    pub fn validate(&self) -> Result<(), EmulatorError> {
+        let invalid = |message: String| Err(EmulatorError::InvalidAvdConfig(message));
+
+        if self.ram_size_mb.is_some_and(|ram| !(128..=65536).contains(&ram)) {
+            return invalid("ram_size_mb must be between 128 and 65536".to_string());
+        }
+        if self.cpu_cores.is_some_and(|cores| !(1..=64).contains(&cores)) {
+            return invalid("cpu_cores must be between 1 and 64".to_string());
+        }
+        if self.lcd_density.is_some_and(|density| !(72..=800).contains(&density)) {
+            return invalid("lcd_density must be between 72 and 800".to_string());
+        }
+        if let Some(mode) = &self.gpu_mode {
+            if !matches!(
+                mode.as_str(),
+                "auto" | "host" | "swiftshader_indirect" | "angle_indirect" | "guest" | "off"
+            ) {
+                return invalid(format!("unsupported gpu_mode {:?}", mode));
+            }
+        }
+        for (key, value) in &self.other {
+            let key_ok = !key.is_empty()
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'));
+            if !key_ok || value.contains(['\n', '\r']) {
+                return invalid(format!("invalid config entry {:?}", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Write every field that is set into the ini file
+    pub fn apply_to(&self, ini: &mut IniFile) {
+        let sizes = [
+            ("hw.ramSize", self.ram_size_mb),
+            ("vm.heapSize", self.vm_heap_size_mb),
+            ("disk.dataPartition.size", self.data_partition_size_mb),
+            ("sdcard.size", self.sdcard_size_mb),
+        ];
+        for (key, value) in sizes {
+            if let Some(mb) = value {
+                ini.set(key, format!("{}M", mb));
+            }
+        }
+
+        let numbers = [
+            ("hw.cpu.ncore", self.cpu_cores),
+            ("hw.lcd.density", self.lcd_density),
+            ("hw.lcd.width", self.lcd_width),
+            ("hw.lcd.height", self.lcd_height),
+        ];
+        for (key, value) in numbers {
+            if let Some(value) = value {
+                ini.set(key, value.to_string());
+            }
+        }
+
+        let booleans = [
+            ("hw.gpu.enabled", self.gpu_enabled),
+            ("hw.keyboard", self.keyboard),
+            ("fastboot.forceColdBoot", self.force_cold_boot),
+        ];
+        for (key, value) in booleans {
+            if let Some(value) = value {
+                ini.set(key, format_bool(value));
+            }
+        }
+
+        if let Some(mode) = &self.gpu_mode {
+            ini.set("hw.gpu.mode", mode.clone());
+        }
+        for (key, value) in &self.other {
+            ini.set(key, value.clone());
+        }
+    }
+}
+
+/// An AVD found in the AVD home directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AvdInfo {
+    pub name: String,
+    pub path: String,
+    /// System image the AVD boots, e.g. `system-images/android-34/google_apis/x86_64/`
+    pub system_image: Option<String>,
+    pub abi: Option<String>,
+    pub device: Option<String>,
+}
+
+impl AvdInfo {
+    fn from_ini(name: &str, dir: &Path, ini: &IniFile) -> Self {
+        Self {
+            name: name.to_string(),
+            path: dir.display().to_string(),
+            system_image: ini.get("image.sysdir.1").map(str::to_string),
+            abi: ini.get("abi.type").map(str::to_string),
+            device: ini.get("hw.device.name").map(str::to_string),
+        }
+    }
+}
+
+/// Options for `avdmanager create avd`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateAvdOptions {
+    pub name: String,
+    /// SDK package of an installed system image,
+    /// e.g. `system-images;android-34;google_apis;x86_64`
+    pub system_image: String,
+    /// Device profile id or name from `avdmanager list device`, e.g. `pixel_6`
+    pub device: String,
+    /// Overwrite an existing AVD with the same name
+    #[serde(default)]
+    pub force: bool,
+    /// Settings applied to `config.ini` after the AVD is created
+    #[serde(default)]
+    pub config: Option<AvdConfig>,
+}
+
+fn config_path(name: &str) -> PathBuf {
+    avd_dir(name).join("config.ini")
+}
+
+fn read_ini(name: &str) -> Result<IniFile, EmulatorError> {
+    let path = config_path(name);
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(IniFile::parse(&content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            Err(EmulatorError::AvdNotFound(name.to_string()))
+        }
+        Err(e) => Err(EmulatorError::AvdError(format!("{}: {}", path.display(), e))),
+    }
+}
+
+/// List the AVDs in the AVD home directory
+pub fn list_avds() -> Result<Vec<AvdInfo>, EmulatorError> {
+    let home = avd_home();
+    let entries = match fs::read_dir(&home) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(EmulatorError::AvdError(format!("{}: {}", home.display(), e))),
+    };
+
+    let mut avds = Vec::new();
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(name) = file_name.strip_suffix(".avd") else {
+            continue;
+        };
+        if !entry.path().is_dir() {
+            continue;
+        }
+        let ini = read_ini(name).unwrap_or_default();
+        avds.push(AvdInfo::from_ini(name, &entry.path(), &ini));
+    }
+    avds.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(avds)
+}
+
+/// Describe a single AVD
+pub fn get_avd(name: &str) -> Result<AvdInfo, EmulatorError> {
+    validate_avd_name(name)?;
+    let ini = read_ini(name)?;
+    Ok(AvdInfo::from_ini(name, &avd_dir(name), &ini))
+}
+
+/// Read the typed configuration of an AVD
+pub fn read_config(name: &str) -> Result<AvdConfig, EmulatorError> {
+    validate_avd_name(name)?;
+    Ok(AvdConfig::from_ini(&read_ini(name)?))
+}
+
+/// Apply a patch to an AVD's `config.ini` and return the resulting configuration
+pub fn update_config(name: &str, patch: &AvdConfig) -> Result<AvdConfig, EmulatorError> {
+    validate_avd_name(name)?;
+    patch.validate()?;
+
+    let mut ini = read_ini(name)?;
+    patch.apply_to(&mut ini);
+
+    let path = config_path(name);
+    fs::write(&path, ini.render())
+        .map_err(|e| EmulatorError::AvdError(format!("{}: {}", path.display(), e)))?;
+    info!("Updated AVD config {}", path.display());
+    Ok(AvdConfig::from_ini(&ini))
+}
+
+/// Run blocking AVD file access on the blocking thread pool
+pub async fn blocking<T, F>(access: F) -> Result<T, EmulatorError>
+where
+    F: FnOnce() -> Result<T, EmulatorError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(access)
+        .await
+        .map_err(|e| EmulatorError::AvdError(format!("AVD file access failed: {}", e)))?
+}
+
+/// Create an AVD from an installed system image with `avdmanager`
+pub async fn create_avd(
+    runner: &dyn CommandRunner,
+    policy: CommandPolicy,
+    options: &CreateAvdOptions,
+) -> Result<AvdInfo, EmulatorError> {
+    validate_avd_name(&options.name)?;
+    if let Some(config) = &options.config {
+        config.validate()?;
+    }
+
+    let mut command = CommandSpec::new("avdmanager")
+        .args(["create", "avd", "--name", &options.name])
+        .args(["--package", &options.system_image, "--device", &options.device])
+        // Decline the "custom hardware profile" prompt
+        .stdin("no\n");
+    if options.force {
+        command = command.arg("--force");
+    }
+
+    info!("Creating AVD {} from {}", options.name, options.system_image);
+    // Copying the system image can take as long as an app installation
+    let output = policy
+        .for_install()
+        .run(runner, &command)
+        .await
+        .map_err(|e| match e {
+            CommandError::Timeout { .. } | CommandError::NotFound { .. } => EmulatorError::from(e),
+            e => EmulatorError::AvdError(format!("Failed to run avdmanager: {}", e)),
+        })?;
+    if !output.success() {
+        let stderr = output.stderr_lossy();
+        let stdout = output.stdout_lossy();
+        let message = stderr
+            .lines()
+            .chain(stdout.lines())
+            .find(|line| line.contains("Error"))
+            .unwrap_or_else(|| stderr.trim())
+            .to_string();
+        error!("Failed to create AVD {}: {}", options.name, message);
+        return Err(EmulatorError::AvdError(message));
+    }
+
+    let name = options.name.clone();
+    let config = options.config.clone();
+    blocking(move || {
+        if let Some(config) = &config {
+            update_config(&name, config)?;
+        }
+        get_avd(&name)
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_INI: &str = "AvdId=pixel_test\n\
+                              abi.type=x86_64\n\
+                              disk.dataPartition.size=6G\n\
+                              hw.lcd.density=420\n\
+                              hw.ramSize=2048\n\
+                              hw.gpu.enabled=yes\n\
+                              image.sysdir.1=system-images/android-34/google_apis/x86_64/\n";
+
+    #[test]
+    fn test_avd_config_from_ini() {
+        let config = AvdConfig::from_ini(&IniFile::parse(CONFIG_INI));
+        assert_eq!(config.ram_size_mb, Some(2048));
+        assert_eq!(config.data_partition_size_mb, Some(6144));
+        assert_eq!(config.lcd_density, Some(420));
+        assert_eq!(config.gpu_enabled, Some(true));
+        assert_eq!(config.other.get("abi.type").map(String::as_str), Some("x86_64"));
+        assert!(!config.other.contains_key("hw.ramSize"));
+    }
+
+    #[test]
+    fn test_avd_config_patch_preserves_order() {
+        let mut ini = IniFile::parse(CONFIG_INI);
+        let patch = AvdConfig {
+            ram_size_mb: Some(4096),
+            cpu_cores: Some(4),
+            ..Default::default()
+        };
+        patch.validate().unwrap();
+        patch.apply_to(&mut ini);
+
+        let rendered = ini.render();
+        let lines: Vec<&str> = rendered.lines().collect();
+        assert_eq!(lines[4], "hw.ramSize=4096M");
+        assert_eq!(lines.last(), Some(&"hw.cpu.ncore=4"));
+        assert_eq!(lines[2], "disk.dataPartition.size=6G");
+    }
+
+    #[test]
+    fn test_avd_config_validation() {
+        let patch = AvdConfig {
+            other: BTreeMap::from([("hw.ramSize".to_string(), "1\nimage.sysdir.1=/".to_string())]),
+            ..Default::default()
+        };
+        assert!(patch.validate().is_err());
+        assert!(AvdConfig { lcd_density: Some(5), ..Default::default() }.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_size_mb() {
+        assert_eq!(parse_size_mb("2048"), Some(2048));
+        assert_eq!(parse_size_mb("2048M"), Some(2048));
+        assert_eq!(parse_size_mb("6G"), Some(6144));
+        assert_eq!(parse_size_mb("536870912"), Some(512));
+        assert_eq!(parse_size_mb("lots"), None);
+    }
+
+    #[test]
+    fn test_validate_avd_name() {
+        assert!(validate_avd_name("Pixel_6_API_34").is_ok());
+        assert!(validate_avd_name("../etc").is_err());
+        assert!(validate_avd_name("a b").is_err());
+        assert!(validate_avd_name("").is_err());
+    }
+
+    #[test]
+    fn test_wipe_user_data_keeps_config() {
+        let dir = env::temp_dir().join(format!("tikpilot-avd-{}", std::process::id()));
+        fs::create_dir_all(dir.join("snapshots").join("default_boot")).unwrap();
+        let files = [
+            "config.ini",
+            "userdata.img",
+            "userdata-qemu.img",
+            "userdata-qemu.img.qcow2",
+            "cache.img",
+        ];
+        for file in files {
+            fs::write(dir.join(file), b"").unwrap();
+        }
+
+        let removed = wipe_user_data_in(&dir).unwrap();
+        assert_eq!(removed.len(), 4);
+        assert!(dir.join("config.ini").exists());
+        assert!(dir.join("userdata.img").exists(), "the initial userdata image is kept");
+        assert!(!dir.join("snapshots").exists());
+        assert!(!dir.join("userdata-qemu.img.qcow2").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(wipe_user_data_in(&dir).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+use tokio::process::Child;
+use log::{debug, info};
+
+use super::adb_client::AdbClient;
+use super::devices;
+use super::{Emulator, EmulatorError};
+use crate::config::EmulatorSettings;
+
+/// Controls how long and how often boot progress is polled
+#[derive(Debug, Clone, Copy)]
+pub struct BootConfig {
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+}
+
+impl Default for BootConfig {
+    fn default() -> Self {
+        EmulatorSettings::default().into()
+    }
+}
+
+impl From<EmulatorSettings> for BootConfig {
+    fn from(settings: EmulatorSettings) -> Self {
+        Self {
+            timeout: Duration::from_secs(settings.boot_timeout_secs),
+            poll_interval: Duration::from_millis(settings.boot_poll_interval_ms),
+        }
+    }
+}
+
+/// How far a booting emulator has progressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootStage {
+    /// adb does not list the device yet
+    WaitingForDevice,
+    /// The device is visible but `sys.boot_completed` is not set
+    BootingSystem,
+    /// The system reports boot completed but the boot animation is still running
+    BootAnimation,
+    /// The device is fully booted and usable
+    Completed,
+}
+
+impl BootStage {
+    /// Derive the boot stage from the raw values reported by the device
+    pub fn from_props(device_state: &str, boot_completed: &str, bootanim: &str) -> Self {
+        if device_state.trim() != "device" {
+            BootStage::WaitingForDevice
+        } else if boot_completed.trim() != "1" {
+            BootStage::BootingSystem
+        } else if bootanim.trim() != "stopped" {
+            BootStage::BootAnimation
+        } else {
+            BootStage::Completed
+        }
+    }
+}
+
+/// Read a system property of a device, or an empty string if it cannot be read
+async fn getprop(client: &AdbClient, serial: &str, name: &str) -> String {
+    client
+        .shell(serial, &format!("getprop {}", name))
+        .await
+        .map(|output| output.stdout_lossy().trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Probe the current boot stage of an emulator
+pub async fn probe(emulator: &Emulator) -> BootStage {
+    let serial = emulator.serial();
+    let device_state = match devices::list_devices(emulator.runner.as_ref(), emulator.command_policy).await {
+        Ok(devices) => devices
+            .into_iter()
+            .find(|device| device.serial == serial)
+            .map(|device| device.state)
+            .unwrap_or_default(),
+        Err(_) => String::new(),
+    };
+    if device_state != "device" {
+        return BootStage::WaitingForDevice;
+    }
+
+    let client = AdbClient::for_runner(emulator.runner.as_ref(), emulator.command_policy);
+    let boot_completed = getprop(&client, &serial, "sys.boot_completed").await;
+    let bootanim = getprop(&client, &serial, "init.svc.bootanim").await;
+    BootStage::from_props(&device_state, &boot_completed, &bootanim)
+}
+
+/// Wait until an emulator has fully booted.
+///
+/// Fails if the emulator process exits first or the boot does not complete within
+/// the configured timeout. A process that is still running is left to the caller.
+pub async fn wait_for_boot(
+    emulator: &Emulator,
+    child: &mut Child,
+    config: BootConfig,
+) -> Result<(), EmulatorError> {
+    let serial = emulator.serial();
+    let poll = async {
+        let mut last_stage = None;
+        loop {
+            let stage = probe(emulator).await;
+            if last_stage != Some(stage) {
+                debug!("{} boot stage: {:?}", serial, stage);
+                last_stage = Some(stage);
+            }
+            if stage == BootStage::Completed {
+                return;
+            }
+            tokio::time::sleep(config.poll_interval).await;
+        }
+    };
+
+    tokio::select! {
+        status = child.wait() => {
+            let reason = match status {
+                Ok(status) => format!("emulator process exited during boot ({})", status),
+                Err(e) => format!("failed to wait for emulator process: {}", e),
+            };
+            Err(EmulatorError::StartError(reason))
+        }
+        result = tokio::time::timeout(config.timeout, poll) => match result {
+            Ok(()) => {
+                info!("{} finished booting", serial);
+                Ok(())
+            }
+            Err(_) => Err(EmulatorError::StartError(format!(
+                "boot did not complete within {}s",
+                config.timeout.as_secs()
+            ))),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_boot_stage_from_props() {
+        assert_eq!(BootStage::from_props("offline", "", ""), BootStage::WaitingForDevice);
+        assert_eq!(BootStage::from_props("device", "", "running"), BootStage::BootingSystem);
+        assert_eq!(BootStage::from_props("device", "1", "running"), BootStage::BootAnimation);
+        assert_eq!(BootStage::from_props("device\n", "1\n", "stopped\n"), BootStage::Completed);
+    }
+}
@@ -0,0 +1,389 @@
+use std::env;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use log::warn;
+use thiserror::Error;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use super::runner::CommandPolicy;
+
+#[derive(Error, Debug)]
+pub enum ConsoleError {
+    #[error("Console I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Console did not answer within {0:?}")]
+    Timeout(Duration),
+    #[error("Console closed the connection")]
+    Closed,
+    #[error("The console requires authentication but its token could not be read from {path}: {source}")]
+    AuthToken { path: PathBuf, source: io::Error },
+    #[error("Console authentication failed: {0}")]
+    AuthFailed(String),
+    #[error("Invalid console command {0:?}")]
+    InvalidCommand(String),
+    #[error("Console rejected {command:?}: {reason}")]
+    Rejected { command: String, reason: String },
+}
+
+impl ConsoleError {
+    /// Whether the console dropped the connection in a way that may not happen again
+    fn is_transient(&self) -> bool {
+        match self {
+            ConsoleError::Closed => true,
+            ConsoleError::Io(e) => matches!(
+                e.kind(),
+                io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// Location of the token the emulator requires for console authentication
+pub fn auth_token_path() -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from).unwrap_or_default();
+    home.join(".emulator_console_auth_token")
+}
+
+/// Read the console auth token; an empty token file means authentication is disabled
+pub fn read_auth_token() -> Result<String, ConsoleError> {
+    let path = auth_token_path();
+    std::fs::read_to_string(&path)
+        .map(|token| token.trim().to_string())
+        .map_err(|source| ConsoleError::AuthToken { path, source })
+}
+
+/// Final line of a console reply
+enum Status {
+    Ok,
+    Ko(String),
+}
+
+fn parse_status(line: &str) -> Option<Status> {
+    if line == "OK" || line.starts_with("OK:") {
+        Some(Status::Ok)
+    } else {
+        line.strip_prefix("KO:")
+            .map(|reason| Status::Ko(reason.trim().to_string()))
+    }
+}
+
+/// Async client for the emulator console on an emulator's console port.
+///
+/// The console speaks a line protocol: every command is a single line and every
+/// reply is a number of body lines followed by `OK` or `KO: <reason>`.
+pub struct ConsoleClient {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    timeout: Duration,
+}
+
+impl ConsoleClient {
+    /// Connect to the console of the emulator on `port`, authenticating with the
+    /// token from `~/.emulator_console_auth_token` if the console asks for it
+    pub async fn connect(port: u16, policy: CommandPolicy) -> Result<Self, ConsoleError> {
+        Self::connect_to(SocketAddr::from(([127, 0, 0, 1], port)), None, policy).await
+    }
+
+    /// Connect to a console at `addr` and authenticate if it asks for it, with
+    /// `token` or else the token read from the token file.
+    ///
+    /// Connecting is retried under `policy` while the console drops the connection,
+    /// and the policy's timeout applies to the connection and every reply.
+    pub async fn connect_to(
+        addr: SocketAddr,
+        token: Option<&str>,
+        policy: CommandPolicy,
+    ) -> Result<Self, ConsoleError> {
+        let mut attempt = 0;
+        loop {
+            match Self::try_connect(addr, token, policy.timeout).await {
+                Err(e) if e.is_transient() && attempt < policy.retries => {
+                    let delay = policy.backoff_for(attempt);
+                    attempt += 1;
+                    warn!("Console at {} dropped the connection ({}), retrying in {:?}", addr, e, delay);
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn try_connect(
+        addr: SocketAddr,
+        token: Option<&str>,
+        timeout_after: Duration,
+    ) -> Result<Self, ConsoleError> {
+        let stream = timeout(timeout_after, TcpStream::connect(addr))
+            .await
+            .map_err(|_| ConsoleError::Timeout(timeout_after))??;
+        let (reader, writer) = stream.into_split();
+        let mut client = Self {
+            reader: BufReader::new(reader),
+            writer,
+            timeout: timeout_after,
+        };
+
+        // The banner ends with OK once the console is ready for commands
+        let banner = match client.read_reply().await? {
+            (banner, Status::Ok) => banner,
+            (_, Status::Ko(reason)) => return Err(ConsoleError::AuthFailed(reason)),
+        };
+        if banner.iter().any(|line| line.contains("Authentication required")) {
+            let token = match token {
+                Some(token) => token.to_string(),
+                None => read_auth_token()?,
+            };
+            if token.is_empty() {
+                return Err(ConsoleError::AuthFailed("no auth token configured".to_string()));
+            }
+            client.send_line(&format!("auth {}", token)).await?;
+            if let (_, Status::Ko(reason)) = client.read_reply().await? {
+                return Err(ConsoleError::AuthFailed(reason));
+            }
+        }
+
+        Ok(client)
+    }
+
+    /// Change how long to wait for replies, e.g. for slow commands such as snapshots
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    async fn send_line(&mut self, line: &str) -> Result<(), ConsoleError> {
+        let write = async {
+            self.writer.write_all(line.as_bytes()).await?;
+            self.writer.write_all(b"\r\n").await?;
+            self.writer.flush().await
+        };
+        timeout(self.timeout, write)
+            .await
+            .map_err(|_| ConsoleError::Timeout(self.timeout))??;
+        Ok(())
+    }
+
+    /// Read body lines up to and including the closing `OK` or `KO:` line
+    async fn read_reply(&mut self) -> Result<(Vec<String>, Status), ConsoleError> {
+        let mut body = Vec::new();
+        loop {
+            let mut line = String::new();
+            let read = timeout(self.timeout, self.reader.read_line(&mut line))
+                .await
+                .map_err(|_| ConsoleError::Timeout(self.timeout))??;
+            if read == 0 {
+                return Err(ConsoleError::Closed);
+            }
+
+            let line = line.trim_end_matches(['\r', '\n']);
+            match parse_status(line) {
+                Some(status) => return Ok((body, status)),
+                None => body.push(line.to_string()),
+            }
+        }
+    }
+
+    /// Send a command and return the body of its reply
+    pub async fn command(&mut self, command: &str) -> Result<Vec<String>, ConsoleError> {
+        if command.is_empty() || command.contains(['\r', '\n']) {
+            return Err(ConsoleError::InvalidCommand(command.to_string()));
+        }
+
+        self.send_line(command).await?;
+        match self.read_reply().await? {
+            (body, Status::Ok) => Ok(body),
+            (_, Status::Ko(reason)) => Err(ConsoleError::Rejected {
+                command: command.to_string(),
+                reason,
+            }),
+        }
+    }
+
+    /// Name of the AVD the emulator is running
+    pub async fn avd_name(&mut self) -> Result<String, ConsoleError> {
+        let body = self.command("avd name").await?;
+        Ok(body.into_iter().next().unwrap_or_default().trim().to_string())
+    }
+
+    /// Shut the emulator down
+    pub async fn kill(mut self) -> Result<(), ConsoleError> {
+        match self.command("kill").await {
+            // The emulator may exit before the reply makes it through
+            Ok(_) | Err(ConsoleError::Closed) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn snapshot_save(&mut self, name: &str) -> Result<(), ConsoleError> {
+        self.command(&format!("avd snapshot save {}", name)).await.map(|_| ())
+    }
+
+    pub async fn snapshot_load(&mut self, name: &str) -> Result<(), ConsoleError> {
+        self.command(&format!("avd snapshot load {}", name)).await.map(|_| ())
+    }
+
+    pub async fn snapshot_delete(&mut self, name: &str) -> Result<(), ConsoleError> {
+        self.command(&format!("avd snapshot delete {}", name)).await.map(|_| ())
+    }
+
+    /// Raw listing of the emulator's snapshots
+    pub async fn snapshot_list(&mut self) -> Result<Vec<String>, ConsoleError> {
+        self.command("avd snapshot list").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use tokio::test;
+
+    const TOKEN: &str = "s3cr3t";
+
+    fn policy() -> CommandPolicy {
+        CommandPolicy {
+            timeout: Duration::from_secs(10),
+            install_timeout: Duration::from_secs(10),
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    /// Serve a single console session that behaves like the emulator's console,
+    /// started with or without `-no-console-auth`
+    async fn fake_console(auth: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+
+            let banner: &[u8] = if auth {
+                b"Android Console: Authentication required\r\n\
+                  Android Console: type 'auth <auth_token>' to authenticate\r\n\
+                  OK\r\n"
+            } else {
+                b"Android Console: type 'help' for a list of commands\r\nOK\r\n"
+            };
+            writer.write_all(banner).await.unwrap();
+
+            let mut authenticated = !auth;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                let reply: &[u8] = match line.trim() {
+                    command if command.starts_with("auth ") => {
+                        if command == format!("auth {}", TOKEN) {
+                            authenticated = true;
+                            b"Android Console: type 'help' for a list of commands\r\nOK\r\n"
+                        } else {
+                            b"KO: authentication token does not match\r\n"
+                        }
+                    }
+                    _ if !authenticated => b"KO: unknown command, try 'help'\r\n",
+                    "avd name" => b"Pixel_6_API_34\r\nOK\r\n",
+                    "avd snapshot list" => {
+                        b"List of snapshots present on all disks:\r\n\
+                          ID        TAG                 VM SIZE                DATE       VM CLOCK\r\n\
+                          --        default_boot         142M 2024-05-02 10:11:12   00:01:02.345\r\n\
+                          OK\r\n"
+                    }
+                    "avd snapshot load missing" => b"KO: snapshot 'missing' does not exist\r\n",
+                    "kill" => {
+                        writer.write_all(b"OK: killing emulator, bye bye\r\n").await.unwrap();
+                        return;
+                    }
+                    _ => b"KO: unknown command, try 'help'\r\n",
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+
+        addr
+    }
+
+    #[test]
+    async fn test_console_commands() {
+        let addr = fake_console(true).await;
+        let mut client = ConsoleClient::connect_to(addr, Some(TOKEN), policy()).await.unwrap();
+
+        assert_eq!(client.avd_name().await.unwrap(), "Pixel_6_API_34");
+        let listing = client.snapshot_list().await.unwrap();
+        assert_eq!(listing.len(), 3);
+        assert!(listing[2].contains("default_boot"));
+
+        let err = client.snapshot_load("missing").await.unwrap_err();
+        assert!(matches!(
+            err,
+            ConsoleError::Rejected { ref reason, .. } if reason == "snapshot 'missing' does not exist"
+        ));
+        assert!(matches!(
+            client.command("avd name\r\nkill").await,
+            Err(ConsoleError::InvalidCommand(_))
+        ));
+
+        client.kill().await.unwrap();
+    }
+
+    #[test]
+    async fn test_console_wrong_token() {
+        let addr = fake_console(true).await;
+        let result = ConsoleClient::connect_to(addr, Some("wrong"), policy()).await;
+        assert!(matches!(result, Err(ConsoleError::AuthFailed(_))));
+    }
+
+    #[test]
+    async fn test_console_without_auth_needs_no_token() {
+        // The token file is only read when the console asks for authentication
+        let addr = fake_console(false).await;
+        let mut client = ConsoleClient::connect_to(addr, None, policy()).await.unwrap();
+        assert_eq!(client.avd_name().await.unwrap(), "Pixel_6_API_34");
+    }
+
+    #[test]
+    async fn test_console_timeout() {
+        // A server that accepts but never sends a banner
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 16];
+            let _ = stream.read(&mut buf).await;
+        });
+
+        let policy = policy().with_timeout(Duration::from_millis(50));
+        let result = ConsoleClient::connect_to(addr, Some(TOKEN), policy).await;
+        assert!(matches!(result, Err(ConsoleError::Timeout(_))));
+    }
+
+    #[test]
+    async fn test_console_connect_retries_dropped_connections() {
+        // Every other connection is closed before the banner, as a busy console does
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            for accepted in 0.. {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                if accepted % 2 == 1 {
+                    stream
+                        .write_all(b"Android Console: type 'help' for a list of commands\r\nOK\r\n")
+                        .await
+                        .unwrap();
+                    streams.push(stream);
+                }
+            }
+        });
+
+        let once = CommandPolicy { retries: 0, ..policy() };
+        let result = ConsoleClient::connect_to(addr, None, once).await;
+        assert!(matches!(result, Err(ConsoleError::Closed)));
+        ConsoleClient::connect_to(addr, None, policy()).await.unwrap();
+    }
+}
@@ -0,0 +1,94 @@
+use log::info;
+
+use super::adb_client::{AdbClient, AdbClientError};
+use super::runner::{CommandPolicy, CommandRunner, CommandSpec};
+use super::EmulatorError;
+
+/// A device line from `adb devices`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdbDevice {
+    pub serial: String,
+    /// Connection state as reported by adb, e.g. `device`, `offline` or `unauthorized`
+    pub state: String,
+}
+
+impl AdbDevice {
+    /// Whether the device is connected and accepting commands
+    pub fn is_online(&self) -> bool {
+        self.state == "device"
+    }
+
+    /// Console port of an emulator device, parsed from its `emulator-NNNN` serial
+    pub fn console_port(&self) -> Option<u16> {
+        self.serial.strip_prefix("emulator-")?.parse().ok()
+    }
+}
+
+/// Parse the output of `adb devices`
+pub fn parse_devices(output: &str) -> Vec<AdbDevice> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("List of") && !line.starts_with('*'))
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let serial = fields.next()?;
+            let state = fields.next()?;
+            Some(AdbDevice {
+                serial: serial.to_string(),
+                state: state.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Start the adb server with the CLI, for when nothing listens on its port yet
+async fn start_server(runner: &dyn CommandRunner, policy: CommandPolicy) -> Result<(), EmulatorError> {
+    info!("Starting adb server");
+    let output = policy
+        .run(runner, &CommandSpec::new("adb").arg("start-server"))
+        .await?;
+    if !output.success() {
+        return Err(EmulatorError::AdbError(output.stderr_lossy().trim().to_string()));
+    }
+    Ok(())
+}
+
+/// List every device known to the adb server of `runner`, starting the server
+/// through `runner` if needed
+pub async fn list_devices(
+    runner: &dyn CommandRunner,
+    policy: CommandPolicy,
+) -> Result<Vec<AdbDevice>, EmulatorError> {
+    let client = AdbClient::for_runner(runner, policy);
+    match client.devices().await {
+        Err(AdbClientError::ServerUnavailable(_)) => {
+            start_server(runner, policy).await?;
+            Ok(client.devices().await?)
+        }
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_devices() {
+        let output = "* daemon not running; starting now at tcp:5037\n\
+                      * daemon started successfully\n\
+                      List of devices attached\n\
+                      emulator-5554\tdevice\n\
+                      emulator-5556\toffline\n\
+                      R58M123ABC\tunauthorized\n\n";
+
+        let devices = parse_devices(output);
+        assert_eq!(devices.len(), 3);
+        assert_eq!(devices[0].serial, "emulator-5554");
+        assert!(devices[0].is_online());
+        assert_eq!(devices[0].console_port(), Some(5554));
+        assert!(!devices[1].is_online());
+        assert_eq!(devices[2].console_port(), None);
+    }
+}
@@ -0,0 +1,416 @@
+use serde::{Deserialize, Serialize};
+
+use super::activity::{self, LaunchResult};
+use super::app_manager::{is_dotted_name, validate_activity, validate_package_name, AppError};
+
+/// `Intent` flags accepted by name, with their values
+const INTENT_FLAGS: &[(&str, u32)] = &[
+    ("FLAG_GRANT_READ_URI_PERMISSION", 0x0000_0001),
+    ("FLAG_GRANT_WRITE_URI_PERMISSION", 0x0000_0002),
+    ("FLAG_EXCLUDE_STOPPED_PACKAGES", 0x0000_0010),
+    ("FLAG_INCLUDE_STOPPED_PACKAGES", 0x0000_0020),
+    ("FLAG_ACTIVITY_TASK_ON_HOME", 0x0000_4000),
+    ("FLAG_ACTIVITY_CLEAR_TASK", 0x0000_8000),
+    ("FLAG_ACTIVITY_NO_ANIMATION", 0x0001_0000),
+    ("FLAG_ACTIVITY_REORDER_TO_FRONT", 0x0002_0000),
+    ("FLAG_ACTIVITY_NO_USER_ACTION", 0x0004_0000),
+    ("FLAG_ACTIVITY_RESET_TASK_IF_NEEDED", 0x0020_0000),
+    ("FLAG_ACTIVITY_BROUGHT_TO_FRONT", 0x0040_0000),
+    ("FLAG_ACTIVITY_EXCLUDE_FROM_RECENTS", 0x0080_0000),
+    ("FLAG_ACTIVITY_PREVIOUS_IS_TOP", 0x0100_0000),
+    ("FLAG_ACTIVITY_FORWARD_RESULT", 0x0200_0000),
+    ("FLAG_ACTIVITY_CLEAR_TOP", 0x0400_0000),
+    ("FLAG_ACTIVITY_MULTIPLE_TASK", 0x0800_0000),
+    ("FLAG_ACTIVITY_NEW_TASK", 0x1000_0000),
+    ("FLAG_RECEIVER_FOREGROUND", 0x1000_0000),
+    ("FLAG_ACTIVITY_SINGLE_TOP", 0x2000_0000),
+    ("FLAG_ACTIVITY_NO_HISTORY", 0x4000_0000),
+];
+
+/// What an intent is delivered to, and so which `am` command sends it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntentTarget {
+    #[default]
+    Activity,
+    Service,
+    Broadcast,
+}
+
+impl IntentTarget {
+    fn command(self) -> &'static str {
+        match self {
+            IntentTarget::Activity => "start",
+            IntentTarget::Service => "startservice",
+            IntentTarget::Broadcast => "broadcast",
+        }
+    }
+}
+
+/// The value of an intent extra, e.g. `{"type": "int", "value": 3}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ExtraValue {
+    String(String),
+    Int(i32),
+    Long(i64),
+    Bool(bool),
+    StringArray(Vec<String>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntentExtra {
+    pub key: String,
+    #[serde(flatten)]
+    pub value: ExtraValue,
+}
+
+/// An intent to send with `am`; at least one of `action`, `data` and `component`
+/// is needed to say where it goes
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Intent {
+    #[serde(default)]
+    pub target: IntentTarget,
+    /// e.g. `android.intent.action.VIEW` (`-a`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// Data URI, e.g. a deep link such as `myapp://orders/42` (`-d`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    /// MIME type, e.g. `text/plain` (`-t`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// e.g. `android.intent.category.BROWSABLE` (`-c`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    /// Explicit component, e.g. `com.example/.MainActivity` (`-n`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub component: Option<String>,
+    /// Limits an implicit intent to one package (`-p`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// Flag names such as `FLAG_ACTIVITY_NEW_TASK` (`-f`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extras: Vec<IntentExtra>,
+    /// Wait for a started activity to draw and report the launch timings (`-W`)
+    #[serde(default)]
+    pub wait: bool,
+}
+
+impl Intent {
+    /// The `am` arguments that send the intent, starting with the command
+    pub fn to_args(&self) -> Result<Vec<String>, AppError> {
+        if self.action.is_none() && self.data.is_none() && self.component.is_none() {
+            return Err(invalid("an action, data URI or component is required".to_string()));
+        }
+        if self.wait && self.target != IntentTarget::Activity {
+            return Err(invalid("only activity launches can be waited for".to_string()));
+        }
+
+        let mut args = vec!["am".to_string(), self.target.command().to_string()];
+        if self.wait {
+            args.push("-W".to_string());
+        }
+        if let Some(action) = &self.action {
+            if !is_dotted_name(action, &['-']) {
+                return Err(invalid(format!("invalid action {:?}", action)));
+            }
+            args.extend(["-a".to_string(), action.clone()]);
+        }
+        if let Some(data) = &self.data {
+            if data.is_empty() || data.chars().any(char::is_control) {
+                return Err(invalid(format!("invalid data URI {:?}", data)));
+            }
+            args.extend(["-d".to_string(), data.clone()]);
+        }
+        if let Some(mime_type) = &self.mime_type {
+            if !is_mime_type(mime_type) {
+                return Err(invalid(format!("invalid MIME type {:?}", mime_type)));
+            }
+            args.extend(["-t".to_string(), mime_type.clone()]);
+        }
+        for category in &self.categories {
+            if !is_dotted_name(category, &['-']) {
+                return Err(invalid(format!("invalid category {:?}", category)));
+            }
+            args.extend(["-c".to_string(), category.clone()]);
+        }
+        if let Some(component) = &self.component {
+            let valid = component.split_once('/').is_some_and(|(package, class)| {
+                validate_package_name(package).is_ok() && validate_activity(class).is_ok()
+            });
+            if !valid {
+                return Err(invalid(format!("invalid component {:?}", component)));
+            }
+            args.extend(["-n".to_string(), component.clone()]);
+        }
+        if let Some(package) = &self.package {
+            validate_package_name(package)?;
+            args.extend(["-p".to_string(), package.clone()]);
+        }
+        if !self.flags.is_empty() {
+            let mut flags = 0;
+            for name in &self.flags {
+                let (_, value) = INTENT_FLAGS
+                    .iter()
+                    .find(|(flag, _)| flag.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| invalid(format!("unknown flag {:?}", name)))?;
+                flags |= value;
+            }
+            args.extend(["-f".to_string(), format!("{:#x}", flags)]);
+        }
+        for extra in &self.extras {
+            if extra.key.is_empty() || extra.key.chars().any(char::is_control) {
+                return Err(invalid(format!("invalid extra key {:?}", extra.key)));
+            }
+            let (option, value) = match &extra.value {
+                ExtraValue::String(value) => ("--es", value.clone()),
+                ExtraValue::Int(value) => ("--ei", value.to_string()),
+                ExtraValue::Long(value) => ("--el", value.to_string()),
+                ExtraValue::Bool(value) => ("--ez", value.to_string()),
+                // `am` splits the array on commas not preceded by a backslash
+                ExtraValue::StringArray(values) => (
+                    "--esa",
+                    values
+                        .iter()
+                        .map(|value| value.replace(',', r"\,"))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                ),
+            };
+            args.extend([option.to_string(), extra.key.clone(), value]);
+        }
+        Ok(args)
+    }
+}
+
+fn invalid(message: String) -> AppError {
+    AppError::InvalidIntent(message)
+}
+
+/// Whether `mime_type` looks like `type/subtype`, where either may be `*`
+fn is_mime_type(mime_type: &str) -> bool {
+    let token = |part: &str| {
+        !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '*' | '.' | '+' | '-' | '_'))
+    };
+    mime_type
+        .split_once('/')
+        .is_some_and(|(kind, subtype)| token(kind) && token(subtype))
+}
+
+/// What the receivers of a broadcast left as its result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BroadcastResult {
+    pub result_code: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+}
+
+/// What `am` reported about sending an intent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IntentResult {
+    pub target: IntentTarget,
+    /// The started activity and its launch timings, for activities
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch: Option<LaunchResult>,
+    /// Set for broadcasts once all receivers have run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broadcast: Option<BroadcastResult>,
+}
+
+/// Parse the output of `am start`, `am startservice` or `am broadcast` for an
+/// intent; like [`activity::parse_am_start`], errors are recognized from the output.
+pub fn parse_intent_result(intent: &Intent, output: &str) -> Result<IntentResult, String> {
+    let mut result = IntentResult {
+        target: intent.target,
+        launch: None,
+        broadcast: None,
+    };
+    if intent.target == IntentTarget::Activity {
+        let component = intent.component.as_deref().unwrap_or_default();
+        result.launch = Some(activity::parse_am_start(output, component)?);
+        return Ok(result);
+    }
+
+    let mut errors = Vec::new();
+    for line in output.lines().map(str::trim) {
+        if let Some(completed) = line.strip_prefix("Broadcast completed: result=") {
+            let (code, rest) = completed.split_once(',').unwrap_or((completed, ""));
+            let data = rest
+                .trim()
+                .strip_prefix("data=\"")
+                .and_then(|data| data.rsplit_once('"'))
+                .map(|(data, _)| data.to_string());
+            if let Ok(result_code) = code.trim().parse() {
+                result.broadcast = Some(BroadcastResult { result_code, data });
+            }
+        } else if let Some((key, message)) = line.split_once(':') {
+            // e.g. `Error: Not found; no service started.` or `Security exception: ...`
+            if key == "Error" || key.to_ascii_lowercase().ends_with("exception") {
+                errors.push(message.trim().to_string());
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(errors.join("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deep_link() -> Intent {
+        Intent {
+            action: Some("android.intent.action.VIEW".to_string()),
+            data: Some("myapp://orders/42?ref=push&x=1".to_string()),
+            categories: vec!["android.intent.category.BROWSABLE".to_string()],
+            package: Some("com.example".to_string()),
+            flags: vec![
+                "FLAG_ACTIVITY_NEW_TASK".to_string(),
+                "flag_activity_clear_top".to_string(),
+            ],
+            extras: vec![
+                IntentExtra {
+                    key: "title".to_string(),
+                    value: ExtraValue::String("it's here".to_string()),
+                },
+                IntentExtra {
+                    key: "count".to_string(),
+                    value: ExtraValue::Int(3),
+                },
+                IntentExtra {
+                    key: "id".to_string(),
+                    value: ExtraValue::Long(9_000_000_000),
+                },
+                IntentExtra {
+                    key: "silent".to_string(),
+                    value: ExtraValue::Bool(true),
+                },
+                IntentExtra {
+                    key: "tags".to_string(),
+                    value: ExtraValue::StringArray(vec!["a,b".to_string(), "c".to_string()]),
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_intent_args() {
+        assert_eq!(
+            deep_link().to_args().unwrap(),
+            vec![
+                "am", "start",
+                "-a", "android.intent.action.VIEW",
+                "-d", "myapp://orders/42?ref=push&x=1",
+                "-c", "android.intent.category.BROWSABLE",
+                "-p", "com.example",
+                "-f", "0x14000000",
+                "--es", "title", "it's here",
+                "--ei", "count", "3",
+                "--el", "id", "9000000000",
+                "--ez", "silent", "true",
+                "--esa", "tags", r"a\,b,c",
+            ]
+        );
+
+        let broadcast = Intent {
+            target: IntentTarget::Broadcast,
+            action: Some("com.example.SYNC".to_string()),
+            component: Some("com.example/.SyncReceiver".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            broadcast.to_args().unwrap(),
+            vec!["am", "broadcast", "-a", "com.example.SYNC", "-n", "com.example/.SyncReceiver"]
+        );
+    }
+
+    #[test]
+    fn test_intent_args_rejects_invalid_intents() {
+        let cases = [
+            Intent::default(),
+            Intent {
+                action: Some("VIEW; reboot".to_string()),
+                ..Default::default()
+            },
+            Intent {
+                component: Some("com.example".to_string()),
+                ..Default::default()
+            },
+            Intent {
+                mime_type: Some("text".to_string()),
+                ..deep_link()
+            },
+            Intent {
+                flags: vec!["FLAG_UNKNOWN".to_string()],
+                ..deep_link()
+            },
+            Intent {
+                target: IntentTarget::Service,
+                wait: true,
+                ..deep_link()
+            },
+        ];
+        for intent in cases {
+            assert!(
+                matches!(intent.to_args(), Err(AppError::InvalidIntent(_))),
+                "{:?}",
+                intent
+            );
+        }
+    }
+
+    #[test]
+    fn test_extra_json() {
+        let extra: IntentExtra = serde_json::from_str(r#"{"key": "ids", "type": "string_array", "value": ["1"]}"#).unwrap();
+        assert_eq!(extra.value, ExtraValue::StringArray(vec!["1".to_string()]));
+    }
+
+    #[test]
+    fn test_parse_intent_result() {
+        let broadcast = Intent {
+            target: IntentTarget::Broadcast,
+            ..Default::default()
+        };
+        let output = "\
+Broadcasting: Intent { act=com.example.SYNC flg=0x400000 }
+Broadcast completed: result=-1, data=\"synced, 3 items\"
+";
+        assert_eq!(
+            parse_intent_result(&broadcast, output).unwrap().broadcast,
+            Some(BroadcastResult {
+                result_code: -1,
+                data: Some("synced, 3 items".to_string()),
+            })
+        );
+        let result = parse_intent_result(&broadcast, "Broadcast completed: result=0\n").unwrap();
+        assert_eq!(result.broadcast.unwrap().data, None);
+
+        let service = Intent {
+            target: IntentTarget::Service,
+            ..Default::default()
+        };
+        let output = "\
+Starting service: Intent { cmp=com.example/.Missing }
+Error: Not found; no service started.
+";
+        assert_eq!(
+            parse_intent_result(&service, output).unwrap_err(),
+            "Not found; no service started."
+        );
+
+        let output = "Starting: Intent { act=android.intent.action.VIEW dat=myapp://x }\n";
+        let launch = parse_intent_result(&deep_link(), output).unwrap().launch.unwrap();
+        assert_eq!(launch.component, "");
+    }
+}
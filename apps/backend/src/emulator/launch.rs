@@ -0,0 +1,249 @@
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+use super::EmulatorError;
+
+/// Flags without a value that may be passed through `extra_flags`
+pub const ALLOWED_EXTRA_FLAGS: &[&str] = &[
+    "-no-snapshot",
+    "-no-snapshot-save",
+    "-no-snapstorage",
+    "-no-cache",
+    "-no-sim",
+    "-no-passive-gps",
+    "-no-location-ui",
+    "-no-hidpi-scaling",
+    "-no-metrics",
+    "-noskin",
+    "-skip-adb-auth",
+    "-writable-system",
+    "-verbose",
+];
+
+/// Graphics acceleration mode passed to `-gpu`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GpuMode {
+    Auto,
+    Host,
+    SwiftshaderIndirect,
+    AngleIndirect,
+    Guest,
+    Off,
+}
+
+impl GpuMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GpuMode::Auto => "auto",
+            GpuMode::Host => "host",
+            GpuMode::SwiftshaderIndirect => "swiftshader_indirect",
+            GpuMode::AngleIndirect => "angle_indirect",
+            GpuMode::Guest => "guest",
+            GpuMode::Off => "off",
+        }
+    }
+}
+
+/// Command line settings used every time an emulator is launched,
+/// persisted in the `emulators` table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchProfile {
+    /// Run without a window (`-no-window`)
+    pub headless: bool,
+    pub gpu_mode: Option<GpuMode>,
+    /// Guest RAM in megabytes (`-memory`), overriding the AVD's `hw.ramSize`
+    pub memory_mb: Option<u32>,
+    /// Number of virtual CPU cores (`-cores`)
+    pub cores: Option<u32>,
+    /// Cold boot instead of loading the quickboot snapshot
+    pub no_snapshot_load: bool,
+    /// Reset user data on every launch, including restarts by the supervisor
+    pub wipe_data: bool,
+    pub no_audio: bool,
+    pub no_boot_anim: bool,
+    /// Keep the AVD read-only so several instances can share it
+    pub read_only: bool,
+    pub dns_servers: Vec<IpAddr>,
+    /// Proxy in the form `http://[user:password@]host:port` or `host:port`
+    pub http_proxy: Option<String>,
+    /// Additional flags from [`ALLOWED_EXTRA_FLAGS`]
+    pub extra_flags: Vec<String>,
+}
+
+impl Default for LaunchProfile {
+    fn default() -> Self {
+        Self {
+            headless: true,
+            gpu_mode: None,
+            memory_mb: None,
+            cores: None,
+            no_snapshot_load: false,
+            wipe_data: false,
+            no_audio: false,
+            no_boot_anim: false,
+            read_only: false,
+            dns_servers: Vec::new(),
+            http_proxy: None,
+            extra_flags: Vec::new(),
+        }
+    }
+}
+
+/// Check a proxy address: an optional `http://` scheme and credentials, then `host:port`
+fn validate_proxy(proxy: &str) -> bool {
+    let address = proxy.strip_prefix("http://").unwrap_or(proxy);
+    let address = address.rsplit_once('@').map_or(address, |(_, host)| host);
+    let Some((host, port)) = address.rsplit_once(':') else {
+        return false;
+    };
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '[' | ']' | ':'))
+        && port.parse::<u16>().is_ok_and(|port| port > 0)
+        && !proxy.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+impl LaunchProfile {
+    /// Check that the profile only produces a well-formed command line
+    pub fn validate(&self) -> Result<(), EmulatorError> {
+        let invalid = |message: String| Err(EmulatorError::InvalidLaunchProfile(message));
+
+        if self.memory_mb.is_some_and(|memory| !(512..=65536).contains(&memory)) {
+            return invalid("memory_mb must be between 512 and 65536".to_string());
+        }
+        if self.cores.is_some_and(|cores| !(1..=64).contains(&cores)) {
+            return invalid("cores must be between 1 and 64".to_string());
+        }
+        if self.dns_servers.len() > 4 {
+            return invalid("at most 4 dns_servers are supported".to_string());
+        }
+        if let Some(proxy) = &self.http_proxy {
+            if !validate_proxy(proxy) {
+                return invalid(format!("invalid http_proxy {:?}", proxy));
+            }
+        }
+        if let Some(flag) = self
+            .extra_flags
+            .iter()
+            .find(|flag| !ALLOWED_EXTRA_FLAGS.contains(&flag.as_str()))
+        {
+            return invalid(format!("flag {:?} is not allowed", flag));
+        }
+        Ok(())
+    }
+
+    /// Build the emulator command line arguments for an AVD on a console port
+    pub fn args(&self, avd: &str, port: u16) -> Result<Vec<String>, EmulatorError> {
+        self.validate()?;
+
+        let mut args = vec![
+            "-avd".to_string(),
+            avd.to_string(),
+            "-port".to_string(),
+            port.to_string(),
+        ];
+        let mut option = |flag: &str, value: String| {
+            args.push(flag.to_string());
+            args.push(value);
+        };
+        if let Some(mode) = self.gpu_mode {
+            option("-gpu", mode.as_str().to_string());
+        }
+        if let Some(memory) = self.memory_mb {
+            option("-memory", memory.to_string());
+        }
+        if let Some(cores) = self.cores {
+            option("-cores", cores.to_string());
+        }
+        if !self.dns_servers.is_empty() {
+            let servers: Vec<String> = self.dns_servers.iter().map(IpAddr::to_string).collect();
+            option("-dns-server", servers.join(","));
+        }
+        if let Some(proxy) = &self.http_proxy {
+            option("-http-proxy", proxy.clone());
+        }
+
+        let switches = [
+            (self.headless, "-no-window"),
+            (self.no_snapshot_load, "-no-snapshot-load"),
+            (self.wipe_data, "-wipe-data"),
+            (self.no_audio, "-no-audio"),
+            (self.no_boot_anim, "-no-boot-anim"),
+            (self.read_only, "-read-only"),
+        ];
+        args.extend(
+            switches
+                .iter()
+                .filter(|(enabled, _)| *enabled)
+                .map(|(_, flag)| flag.to_string()),
+        );
+        args.extend(self.extra_flags.iter().cloned());
+
+        Ok(args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_profile_args() {
+        let args = LaunchProfile::default().args("pixel", 5554).unwrap();
+        assert_eq!(args, vec!["-avd", "pixel", "-port", "5554", "-no-window"]);
+    }
+
+    #[test]
+    fn test_full_profile_args() {
+        let profile = LaunchProfile {
+            headless: false,
+            gpu_mode: Some(GpuMode::SwiftshaderIndirect),
+            memory_mb: Some(4096),
+            cores: Some(4),
+            no_snapshot_load: true,
+            no_audio: true,
+            dns_servers: vec!["8.8.8.8".parse().unwrap(), "1.1.1.1".parse().unwrap()],
+            http_proxy: Some("http://proxy.local:3128".to_string()),
+            extra_flags: vec!["-no-snapshot-save".to_string()],
+            ..Default::default()
+        };
+        let args = profile.args("ci", 5556).unwrap().join(" ");
+        assert_eq!(
+            args,
+            "-avd ci -port 5556 -gpu swiftshader_indirect -memory 4096 -cores 4 \
+             -dns-server 8.8.8.8,1.1.1.1 -http-proxy http://proxy.local:3128 \
+             -no-snapshot-load -no-audio -no-snapshot-save"
+        );
+    }
+
+    #[test]
+    fn test_rejects_unsafe_profiles() {
+        let flag = LaunchProfile {
+            extra_flags: vec!["-qemu".to_string()],
+            ..Default::default()
+        };
+        assert!(flag.validate().is_err());
+
+        let proxy = LaunchProfile {
+            http_proxy: Some("proxy.local:3128 -qemu".to_string()),
+            ..Default::default()
+        };
+        assert!(proxy.validate().is_err());
+
+        let cores = LaunchProfile {
+            cores: Some(0),
+            ..Default::default()
+        };
+        assert!(cores.args("ci", 5554).is_err());
+    }
+
+    #[test]
+    fn test_partial_profile_deserializes_with_defaults() {
+        let profile: LaunchProfile = serde_json::from_str(r#"{"no_audio": true}"#).unwrap();
+        assert!(profile.headless);
+        assert!(profile.no_audio);
+    }
+}
@@ -0,0 +1,214 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use super::supervisor::RestartPolicy;
+use super::EmulatorState;
+
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+pub const MAX_PAGE_SIZE: usize = 200;
+
+/// An emulator together with its live status
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmulatorSummary {
+    pub name: String,
+    pub port: u16,
+    pub adb_port: u16,
+    pub state: EmulatorState,
+    /// Whether adb currently lists the device as online
+    pub online: bool,
+    /// Seconds since this backend started or adopted the emulator
+    pub uptime_secs: Option<i64>,
+    /// Number of third-party packages installed, if the emulator is online
+    pub installed_apps: Option<usize>,
+    pub last_error: Option<String>,
+    pub restart_policy: RestartPolicy,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortField {
+    #[default]
+    Name,
+    CreatedAt,
+    State,
+    Port,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Filtering, sorting and pagination of an emulator listing
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    /// Only include emulators in one of these states; empty means all states
+    pub states: Vec<EmulatorState>,
+    /// Only include emulators whose name starts with this prefix
+    pub name_prefix: Option<String>,
+    pub sort: SortField,
+    pub order: SortOrder,
+    /// Cursor returned as `next_cursor` by the previous page
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// One page of an emulator listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmulatorPage {
+    pub items: Vec<EmulatorSummary>,
+    /// Pass as `cursor` to fetch the next page; absent on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Value of the sort field of an emulator, compared before the name tie-breaker
+fn sort_key(summary: &EmulatorSummary, field: SortField) -> String {
+    match field {
+        SortField::Name => String::new(),
+        SortField::CreatedAt => summary.created_at.clone(),
+        SortField::State => summary.state.to_string(),
+        SortField::Port => format!("{:05}", summary.port),
+    }
+}
+
+fn encode_cursor(key: &str, name: &str) -> String {
+    format!("{}\0{}", key, name)
+        .bytes()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_cursor(cursor: &str) -> Option<(String, String)> {
+    let bytes = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(cursor.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let decoded = String::from_utf8(bytes).ok()?;
+    let (key, name) = decoded.split_once('\0')?;
+    Some((key.to_string(), name.to_string()))
+}
+
+/// Filter, sort and paginate emulator summaries.
+///
+/// Pagination is keyset based, so a page stays consistent when emulators before the
+/// cursor are added or removed. Returns `None` if the cursor is malformed.
+pub fn paginate(mut items: Vec<EmulatorSummary>, query: &ListQuery) -> Option<EmulatorPage> {
+    items.retain(|item| {
+        (query.states.is_empty() || query.states.contains(&item.state))
+            && query
+                .name_prefix
+                .as_deref()
+                .is_none_or(|prefix| item.name.starts_with(prefix))
+    });
+
+    let compare = |a: &(String, String), b: &(String, String)| {
+        let ordering = a.cmp(b);
+        match query.order {
+            SortOrder::Asc => ordering,
+            SortOrder::Desc => ordering.reverse(),
+        }
+    };
+
+    let mut keyed: Vec<((String, String), EmulatorSummary)> = items
+        .into_iter()
+        .map(|item| ((sort_key(&item, query.sort), item.name.clone()), item))
+        .collect();
+    keyed.sort_by(|a, b| compare(&a.0, &b.0));
+
+    if let Some(cursor) = &query.cursor {
+        let after = decode_cursor(cursor)?;
+        keyed.retain(|(key, _)| compare(key, &after) == Ordering::Greater);
+    }
+
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let next_cursor = if keyed.len() > limit {
+        let (key, name) = &keyed[limit - 1].0;
+        Some(encode_cursor(key, name))
+    } else {
+        None
+    };
+    keyed.truncate(limit);
+
+    Some(EmulatorPage {
+        items: keyed.into_iter().map(|(_, item)| item).collect(),
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(name: &str, port: u16, state: EmulatorState) -> EmulatorSummary {
+        EmulatorSummary {
+            name: name.to_string(),
+            port,
+            adb_port: port + 1,
+            state,
+            online: state == EmulatorState::Running,
+            uptime_secs: None,
+            installed_apps: None,
+            last_error: None,
+            restart_policy: RestartPolicy::Never,
+            created_at: format!("2024-01-01T00:00:{:02}Z", port - 5554),
+        }
+    }
+
+    fn names(page: &EmulatorPage) -> Vec<&str> {
+        page.items.iter().map(|item| item.name.as_str()).collect()
+    }
+
+    fn fleet() -> Vec<EmulatorSummary> {
+        vec![
+            summary("ci-b", 5556, EmulatorState::Running),
+            summary("ci-a", 5558, EmulatorState::Stopped),
+            summary("dev-a", 5554, EmulatorState::Running),
+            summary("ci-c", 5560, EmulatorState::Running),
+        ]
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let query = ListQuery {
+            states: vec![EmulatorState::Running],
+            name_prefix: Some("ci-".to_string()),
+            sort: SortField::Port,
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        let page = paginate(fleet(), &query).unwrap();
+        assert_eq!(names(&page), vec!["ci-c", "ci-b"]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_cursor_pagination() {
+        let mut query = ListQuery {
+            limit: Some(3),
+            ..Default::default()
+        };
+        let first = paginate(fleet(), &query).unwrap();
+        assert_eq!(names(&first), vec!["ci-a", "ci-b", "ci-c"]);
+
+        // Removing an already returned emulator does not shift the next page
+        let mut remaining = fleet();
+        remaining.retain(|item| item.name != "ci-a");
+        query.cursor = first.next_cursor;
+        let second = paginate(remaining, &query).unwrap();
+        assert_eq!(names(&second), vec!["dev-a"]);
+        assert!(second.next_cursor.is_none());
+    }
+
+    #[test]
+    fn test_malformed_cursor() {
+        let query = ListQuery {
+            cursor: Some("zz".to_string()),
+            ..Default::default()
+        };
+        assert!(paginate(fleet(), &query).is_none());
+    }
+}
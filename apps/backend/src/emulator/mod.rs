@@ -1,16 +1,52 @@
-use tokio::process::Command as TokioCommand;
+use tokio::process::Child;
 use thiserror::Error;
 use anyhow::Result;
-use log::{info, error};
+use log::{info, error, warn};
 use sqlx::sqlite::SqlitePool;
 
-mod port_manager;
-mod app_manager;
+pub mod port_manager;
+pub mod activity;
+pub mod adb_client;
+pub mod app_manager;
+pub mod avd;
+pub mod boot;
+pub mod console;
+pub mod devices;
+pub mod intent;
+pub mod launch;
+pub mod listing;
+pub mod packages;
+pub mod process;
+pub mod queue;
+pub mod registry;
+pub mod runner;
+pub mod shell;
+pub mod snapshot;
+pub mod state;
+pub mod supervisor;
+
+pub use state::EmulatorState;
 
 use port_manager::{SharedPortManager, PortError};
-use app_manager::{AppManager, AppError};
+use activity::LaunchResult;
+use intent::{Intent, IntentResult};
+use app_manager::{AppManager, AppError, InstallOptions};
+use crate::apk::bundle::DeviceProfile;
+use adb_client::{AdbClient, AdbClientError};
+use boot::BootConfig;
+use console::{ConsoleClient, ConsoleError};
+use launch::LaunchProfile;
+use listing::{EmulatorPage, EmulatorSummary, ListQuery};
+use packages::{InstalledPackage, PackageInventory};
+use process::AppProcess;
+use queue::{OperationGuard, OperationQueue};
+use registry::{EmulatorInstance, EmulatorRegistry, ProcessHandle};
+use runner::{CommandError, CommandPolicy, CommandSpec, SharedRunner, SystemRunner};
+use supervisor::{RestartPolicy, Supervisor, SupervisorConfig};
 use std::path::Path;
-use crate::db::EmulatorDb;
+use std::time::Duration;
+use crate::db::snapshot::SnapshotRecord;
+use crate::db::{EmulatorDb, PackageDb, SnapshotDb};
 
 #[derive(Error, Debug)]
 pub enum EmulatorError {
@@ -25,9 +61,182 @@ pub enum EmulatorError {
     #[error("Port error: {0}")]
     PortError(#[from] PortError),
     #[error("App error: {0}")]
-    AppError(#[from] AppError),
+    AppError(AppError),
     #[error("Database error: {0}")]
     DbError(#[from] sqlx::Error),
+    #[error("Emulator {0} not found")]
+    NotFound(String),
+    #[error("Emulator {name} is {state}; stop it first or force the operation")]
+    StillActive { name: String, state: EmulatorState },
+    #[error("AVD error: {0}")]
+    AvdError(String),
+    #[error("AVD {0} not found")]
+    AvdNotFound(String),
+    #[error("Invalid AVD configuration: {0}")]
+    InvalidAvdConfig(String),
+    #[error("Invalid launch profile: {0}")]
+    InvalidLaunchProfile(String),
+    #[error("Emulator {name} is {state}; it must be running")]
+    NotRunning { name: String, state: EmulatorState },
+    #[error("Snapshot error: {0}")]
+    SnapshotError(String),
+    #[error(transparent)]
+    ConsoleError(ConsoleError),
+    #[error(transparent)]
+    AdbClientError(AdbClientError),
+    #[error("{operation} timed out after {after:?}")]
+    Timeout { operation: String, after: Duration },
+    #[error("Invalid snapshot name {0:?}: use letters, digits, '_', '.' and '-'")]
+    InvalidSnapshotName(String),
+    #[error("Snapshot {snapshot} of emulator {name} not found")]
+    SnapshotNotFound { name: String, snapshot: String },
+    #[error("Cannot move emulator {name} from {from} to {to}")]
+    InvalidTransition {
+        name: String,
+        from: EmulatorState,
+        to: EmulatorState,
+    },
+    #[error("{0} is not installed or not on PATH")]
+    ToolNotFound(String),
+    #[error("Invalid query: {0}")]
+    InvalidQuery(String),
+}
+
+impl EmulatorError {
+    /// Stable, machine-readable identifier of the error for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            EmulatorError::StartError(_) => "start_failed",
+            EmulatorError::StopError(_) => "stop_failed",
+            EmulatorError::StatusCheckError(_) => "status_check_failed",
+            EmulatorError::AdbError(_) => "adb_failed",
+            EmulatorError::PortError(e) => e.code(),
+            EmulatorError::AppError(e) => e.code(),
+            EmulatorError::DbError(_) => "database_error",
+            EmulatorError::NotFound(_) => "emulator_not_found",
+            EmulatorError::StillActive { .. } => "emulator_active",
+            EmulatorError::AvdError(_) => "avd_failed",
+            EmulatorError::AvdNotFound(_) => "avd_not_found",
+            EmulatorError::InvalidAvdConfig(_) => "invalid_avd_config",
+            EmulatorError::InvalidLaunchProfile(_) => "invalid_launch_profile",
+            EmulatorError::NotRunning { .. } => "emulator_not_running",
+            EmulatorError::SnapshotError(_) => "snapshot_failed",
+            EmulatorError::ConsoleError(ConsoleError::Rejected { .. }) => "console_command_rejected",
+            EmulatorError::ConsoleError(_) => "console_unavailable",
+            EmulatorError::AdbClientError(e) => e.code(),
+            EmulatorError::Timeout { .. } => "timeout",
+            EmulatorError::InvalidSnapshotName(_) => "invalid_snapshot_name",
+            EmulatorError::SnapshotNotFound { .. } => "snapshot_not_found",
+            EmulatorError::InvalidTransition { .. } => "invalid_transition",
+            EmulatorError::ToolNotFound(_) => "tool_not_found",
+            EmulatorError::InvalidQuery(_) => "invalid_query",
+        }
+    }
+}
+
+impl From<AppError> for EmulatorError {
+    fn from(error: AppError) -> Self {
+        match error {
+            AppError::Timeout { command, after } => EmulatorError::Timeout {
+                operation: command,
+                after,
+            },
+            AppError::Adb(e) => EmulatorError::AdbClientError(e),
+            error => EmulatorError::AppError(error),
+        }
+    }
+}
+
+impl From<ConsoleError> for EmulatorError {
+    fn from(error: ConsoleError) -> Self {
+        match error {
+            ConsoleError::Timeout(after) => EmulatorError::Timeout {
+                operation: "Emulator console".to_string(),
+                after,
+            },
+            error => EmulatorError::ConsoleError(error),
+        }
+    }
+}
+
+impl From<AdbClientError> for EmulatorError {
+    fn from(error: AdbClientError) -> Self {
+        match error {
+            AdbClientError::Timeout(after) => EmulatorError::Timeout {
+                operation: "adb request".to_string(),
+                after,
+            },
+            error => EmulatorError::AdbClientError(error),
+        }
+    }
+}
+
+impl From<CommandError> for EmulatorError {
+    fn from(error: CommandError) -> Self {
+        match error {
+            CommandError::Timeout { command, after } => EmulatorError::Timeout {
+                operation: command,
+                after,
+            },
+            CommandError::NotFound { program } => EmulatorError::ToolNotFound(program),
+            error => EmulatorError::AdbError(error.to_string()),
+        }
+    }
+}
+
+/// The legal transitions that take an emulator from its stored state to what adb
+/// shows after a restart, and the failure reason recorded on the way.
+///
+/// An emulator that is online was started by someone else or finished booting
+/// while the backend was down, so it goes through `booting` to `running`. One that
+/// was booting or stopping when the backend went away is marked `failed`, since
+/// neither can be finished any more; a stopping emulator that still runs can then
+/// be stopped again.
+fn reconcile_path(stored: EmulatorState, online: bool) -> (&'static [EmulatorState], Option<&'static str>) {
+    use EmulatorState::*;
+
+    match (stored, online) {
+        (Created | Stopped | Failed, true) => (&[Booting, Running], None),
+        (Booting, true) => (&[Running], None),
+        (Running, true) => (&[], None),
+        (Stopping, true) => (&[Failed], Some("the backend restarted while the emulator was stopping")),
+        (Booting, false) => (&[Failed], Some("the backend restarted while the emulator was booting")),
+        (Running | Stopping, false) => (&[Stopped], None),
+        (Created | Stopped | Failed, false) => (&[], None),
+    }
+}
+
+/// Outcome of reconciling the database with the port manager and adb
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    /// Emulators whose ports were reserved again
+    pub restored: Vec<String>,
+    /// Emulators whose ports clash with an emulator restored earlier
+    pub port_conflicts: Vec<String>,
+    /// Emulators found running on adb
+    pub running: Vec<String>,
+    /// Emulators not visible on adb
+    pub stopped: Vec<String>,
+    /// `emulator-NNNN` serials on adb that do not belong to any known emulator
+    pub unknown_serials: Vec<String>,
+}
+
+/// Options for deleting an emulator
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeleteOptions {
+    /// Stop the emulator first if it is running instead of refusing to delete it
+    pub force: bool,
+    /// Also remove the AVD's user data and snapshots
+    pub wipe: bool,
+}
+
+/// What deleting an emulator did
+#[derive(Debug, Default)]
+pub struct DeleteReport {
+    /// Whether a running emulator had to be stopped
+    pub stopped: bool,
+    /// Files and directories removed from the AVD
+    pub wiped: Vec<std::path::PathBuf>,
 }
 
 /// Manages multiple emulator instances
@@ -35,22 +244,98 @@ pub enum EmulatorError {
 pub struct EmulatorManager {
     port_manager: SharedPortManager,
     db: EmulatorDb,
+    snapshots: SnapshotDb,
+    packages: PackageDb,
+    boot_config: BootConfig,
+    supervisor_config: SupervisorConfig,
+    registry: EmulatorRegistry,
+    runner: SharedRunner,
+    command_policy: CommandPolicy,
+    queue: OperationQueue,
 }
 
 impl EmulatorManager {
     pub fn new(pool: SqlitePool) -> Self {
         Self {
             port_manager: SharedPortManager::new(),
-            db: EmulatorDb::new(pool),
+            db: EmulatorDb::new(pool.clone()),
+            snapshots: SnapshotDb::new(pool.clone()),
+            packages: PackageDb::new(pool),
+            boot_config: BootConfig::default(),
+            supervisor_config: SupervisorConfig::default(),
+            registry: EmulatorRegistry::new(),
+            runner: SystemRunner::shared(),
+            command_policy: CommandPolicy::default(),
+            queue: OperationQueue::new(),
         }
     }
 
+    /// Run `emulator` and `adb` through `runner` instead of spawning them directly
+    pub fn with_runner(mut self, runner: SharedRunner) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    /// Override the boot timeout and polling interval
+    pub fn with_boot_config(mut self, boot_config: BootConfig) -> Self {
+        self.boot_config = boot_config;
+        self
+    }
+
+    /// Override the output buffer size and stop grace period of supervised emulators
+    pub fn with_supervisor_config(mut self, supervisor_config: SupervisorConfig) -> Self {
+        self.supervisor_config = supervisor_config;
+        self
+    }
+
+    /// Registry of live emulator instances launched by this manager
+    pub fn registry(&self) -> &EmulatorRegistry {
+        &self.registry
+    }
+
+    /// Override the timeouts and retries of device commands
+    pub fn with_command_policy(mut self, command_policy: CommandPolicy) -> Self {
+        self.command_policy = command_policy;
+        self
+    }
+
+    /// Runner used for the SDK command line tools
+    pub fn runner(&self) -> &SharedRunner {
+        &self.runner
+    }
+
+    /// Timeouts and retries applied to device commands
+    pub fn command_policy(&self) -> CommandPolicy {
+        self.command_policy
+    }
+
+    /// Wait for exclusive access to an emulator name, e.g. before creating it.
+    ///
+    /// Operations queue up per emulator, so concurrent start and stop requests for
+    /// one emulator run one after another while other emulators are unaffected.
+    pub async fn lock_emulator(&self, name: &str) -> OperationGuard {
+        self.queue.acquire(name).await
+    }
+
+    /// Wait for exclusive access to an emulator, then load its current state
+    pub async fn acquire(&self, name: &str) -> Result<(OperationGuard, Emulator), EmulatorError> {
+        let guard = self.lock_emulator(name).await;
+        let emulator = self
+            .get_emulator(name)
+            .await
+            .ok_or_else(|| EmulatorError::NotFound(name.to_string()))?;
+        Ok((guard, emulator))
+    }
+
     /// Create a new emulator instance with automatic port allocation
     pub async fn create_emulator(&self, name: String) -> Result<Emulator, EmulatorError> {
+        // The name is used as the AVD name on the emulator command line
+        avd::validate_avd_name(&name)?;
         let (console_port, _) = self.port_manager.allocate_ports(&name).await?;
         
         // Create emulator instance
-        let emulator = Emulator::new(name.clone(), console_port, self.port_manager.clone());
+        let emulator =
+            Emulator::new(name.clone(), console_port, self.runner.clone(), self.command_policy);
         
         // Save to database
         self.db.save_emulator(&emulator.to_config()).await?;
@@ -58,10 +343,17 @@ impl EmulatorManager {
         Ok(emulator)
     }
 
-    /// Get an emulator instance by name
+    /// Build an emulator from its stored configuration
+    fn load(&self, config: crate::db::emulator::EmulatorConfig) -> Emulator {
+        Emulator::from_config(config, self.runner.clone(), self.command_policy)
+    }
+
+    /// Get an emulator instance by name, attached to its live instance if it is running
     pub async fn get_emulator(&self, name: &str) -> Option<Emulator> {
         if let Ok(Some(config)) = self.db.get_emulator(name).await {
-            Some(Emulator::from_config(config, self.port_manager.clone()))
+            let mut emulator = self.load(config);
+            self.attach(&mut emulator).await;
+            Some(emulator)
         } else {
             None
         }
@@ -70,39 +362,561 @@ impl EmulatorManager {
     /// List all emulators
     pub async fn list_emulators(&self) -> Result<Vec<Emulator>, EmulatorError> {
         let configs = self.db.list_emulators().await?;
-        Ok(configs.into_iter()
-            .map(|config| Emulator::from_config(config, self.port_manager.clone()))
-            .collect())
+        let mut emulators = Vec::with_capacity(configs.len());
+        for config in configs {
+            let mut emulator = self.load(config);
+            self.attach(&mut emulator).await;
+            emulators.push(emulator);
+        }
+        Ok(emulators)
+    }
+
+    /// List emulators with their live status, filtered, sorted and paginated.
+    ///
+    /// Online status for all emulators comes from a single `adb devices` call.
+    /// Returns `None` if the query's cursor is malformed.
+    pub async fn list_summaries(
+        &self,
+        query: &ListQuery,
+    ) -> Result<Option<EmulatorPage>, EmulatorError> {
+        let emulators = self.list_emulators().await?;
+        let devices = devices::list_devices(self.runner.as_ref(), self.command_policy)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Could not list adb devices: {}", e);
+                Vec::new()
+            });
+
+        let now = chrono::Utc::now();
+        let mut summaries = Vec::with_capacity(emulators.len());
+        let mut app_counts = tokio::task::JoinSet::new();
+        for (index, emulator) in emulators.iter().enumerate() {
+            let serial = emulator.serial();
+            let online = devices.iter().any(|device| device.serial == serial && device.is_online());
+            let instance = self.registry.get(&emulator.name).await;
+
+            if online {
+                let app_manager = instance
+                    .as_ref()
+                    .map(|instance| instance.app_manager.clone())
+                    .unwrap_or_else(|| {
+                        AppManager::new(serial)
+                            .with_runner(self.runner.clone())
+                            .with_policy(self.command_policy)
+                    });
+                app_counts.spawn(async move {
+                    (index, app_manager.list_packages(true).await.ok().map(|p| p.len()))
+                });
+            }
+
+            summaries.push(EmulatorSummary {
+                name: emulator.name.clone(),
+                port: emulator.port,
+                adb_port: emulator.adb_port,
+                state: emulator.state,
+                online,
+                uptime_secs: instance
+                    .filter(|_| emulator.state.is_active())
+                    .map(|instance| (now - instance.started_at).num_seconds()),
+                installed_apps: None,
+                last_error: emulator.last_error.clone(),
+                restart_policy: emulator.restart_policy,
+                created_at: emulator.created_at.clone(),
+            });
+        }
+
+        while let Some(result) = app_counts.join_next().await {
+            if let Ok((index, count)) = result {
+                summaries[index].installed_apps = count;
+            }
+        }
+
+        Ok(listing::paginate(summaries, query))
+    }
+
+    /// Bring the in-memory state in line with the database and adb after a restart.
+    ///
+    /// Reserves the ports of every stored emulator so they are not handed out again,
+    /// moves each emulator's state in line with whether adb lists it, through legal
+    /// transitions only, and adopts running emulators into the registry.
+    pub async fn reconcile(&self) -> Result<ReconcileReport, EmulatorError> {
+        let configs = self.db.list_emulators().await?;
+        let mut report = ReconcileReport::default();
+
+        for config in &configs {
+            match self.port_manager.reserve_ports(&config.name, config.console_port).await {
+                Ok(()) => report.restored.push(config.name.clone()),
+                Err(e) => {
+                    warn!("Could not restore ports of emulator {}: {}", config.name, e);
+                    report.port_conflicts.push(config.name.clone());
+                }
+            }
+        }
+
+        let devices = match devices::list_devices(self.runner.as_ref(), self.command_policy).await {
+            Ok(devices) => devices,
+            Err(e) => {
+                warn!("Skipping emulator state reconciliation, adb is unavailable: {}", e);
+                return Ok(report);
+            }
+        };
+        self.apply_devices(&configs, &devices, &mut report).await?;
+
+        Ok(report)
+    }
+
+    /// Update stored states and the registry from a device listing
+    async fn apply_devices(
+        &self,
+        configs: &[crate::db::emulator::EmulatorConfig],
+        devices: &[devices::AdbDevice],
+        report: &mut ReconcileReport,
+    ) -> Result<(), EmulatorError> {
+        for config in configs {
+            let emulator = self.load(config.clone());
+            let serial = emulator.serial();
+            let online = devices.iter().any(|device| device.serial == serial && device.is_online());
+
+            if online {
+                if self.registry.get(&config.name).await.is_none() {
+                    self.registry
+                        .insert(
+                            EmulatorInstance::new(config.name.clone(), serial, None)
+                                .with_commands(self.runner.clone(), self.command_policy),
+                        )
+                        .await;
+                }
+                report.running.push(config.name.clone());
+            } else {
+                self.registry.remove(&config.name).await;
+                report.stopped.push(config.name.clone());
+            }
+
+            let (path, reason) = reconcile_path(config.state, online);
+            let mut from = config.state;
+            for &to in path {
+                let reason = (to == EmulatorState::Failed).then_some(reason).flatten();
+                if !self.db.update_state(&config.name, from, to, reason).await? {
+                    warn!("Emulator {} changed state while it was reconciled", config.name);
+                    break;
+                }
+                info!("Emulator {} was {}, now {}", config.name, from, to);
+                from = to;
+            }
+            // An emulator found running has recovered from whatever failed before
+            if online && from == EmulatorState::Running && config.last_error.is_some() {
+                self.db.clear_last_error(&config.name).await?;
+            }
+        }
+
+        for device in devices {
+            let Some(port) = device.console_port() else {
+                continue;
+            };
+            if !configs.iter().any(|config| config.console_port == port) {
+                warn!("Found unknown emulator {} ({})", device.serial, device.state);
+                report.unknown_serials.push(device.serial.clone());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Give a running emulator access to the app manager of its live instance
+    async fn attach(&self, emulator: &mut Emulator) {
+        if emulator.state != EmulatorState::Running {
+            return;
+        }
+        emulator.app_manager = self
+            .registry
+            .get(&emulator.name)
+            .await
+            .map(|instance| instance.app_manager);
+    }
+
+    /// Move an emulator to a new lifecycle state, enforcing the state machine.
+    ///
+    /// `last_error` is recorded as the emulator's failure reason when given.
+    pub async fn transition(
+        &self,
+        emulator: &mut Emulator,
+        to: EmulatorState,
+        last_error: Option<String>,
+    ) -> Result<(), EmulatorError> {
+        let from = emulator.state;
+        if !from.can_transition_to(to) {
+            return Err(EmulatorError::InvalidTransition {
+                name: emulator.name.clone(),
+                from,
+                to,
+            });
+        }
+
+        if !self.db.update_state(&emulator.name, from, to, last_error.as_deref()).await? {
+            // Someone else moved the emulator in the meantime
+            let current = self
+                .db
+                .get_emulator(&emulator.name)
+                .await?
+                .ok_or_else(|| EmulatorError::NotFound(emulator.name.clone()))?;
+            return Err(EmulatorError::InvalidTransition {
+                name: emulator.name.clone(),
+                from: current.state,
+                to,
+            });
+        }
+
+        emulator.state = to;
+        if last_error.is_some() {
+            emulator.last_error = last_error;
+        }
+        Ok(())
+    }
+
+    /// Tear an emulator down: stop it if needed, release its ports, remove its
+    /// database row and optionally wipe its AVD data.
+    pub async fn delete_emulator(
+        &self,
+        emulator: &mut Emulator,
+        options: DeleteOptions,
+    ) -> Result<DeleteReport, EmulatorError> {
+        let mut report = DeleteReport::default();
+
+        if emulator.state.is_active() {
+            if !options.force {
+                return Err(EmulatorError::StillActive {
+                    name: emulator.name.clone(),
+                    state: emulator.state,
+                });
+            }
+
+            if emulator.state == EmulatorState::Stopping {
+                // A stop is already under way, make sure the process goes away
+                let instance = self.registry.get(&emulator.name).await;
+                if let Some(process) = instance.and_then(|instance| instance.process) {
+                    process.kill();
+                }
+            } else if let Err(e) = self.stop_emulator(emulator).await {
+                warn!("Deleting emulator {} that could not be stopped: {}", emulator.name, e);
+            }
+            report.stopped = true;
+        }
+
+        self.registry.remove(&emulator.name).await;
+        self.port_manager.release_ports(&emulator.name).await;
+        if !self.db.delete_emulator(&emulator.name).await? {
+            return Err(EmulatorError::NotFound(emulator.name.clone()));
+        }
+        self.snapshots.delete_snapshots(&emulator.name).await?;
+        self.packages.delete_packages(&emulator.name).await?;
+        info!("Deleted emulator {}", emulator.name);
+
+        if options.wipe {
+            // The images can be gigabytes, so they are removed off the async runtime
+            let name = emulator.name.clone();
+            report.wiped = avd::blocking(move || {
+                avd::wipe_user_data(&name).map_err(|e| EmulatorError::AvdError(e.to_string()))
+            })
+            .await?;
+        }
+
+        Ok(report)
+    }
+
+    /// Change what happens when the emulator process exits on its own.
+    ///
+    /// Takes effect the next time the emulator is started.
+    pub async fn set_restart_policy(
+        &self,
+        emulator: &mut Emulator,
+        policy: RestartPolicy,
+    ) -> Result<(), EmulatorError> {
+        if !self.db.set_restart_policy(&emulator.name, policy).await? {
+            return Err(EmulatorError::NotFound(emulator.name.clone()));
+        }
+        emulator.restart_policy = policy;
+        Ok(())
+    }
+
+    /// Change the command line settings of an emulator.
+    ///
+    /// Takes effect the next time the emulator is started.
+    pub async fn set_launch_profile(
+        &self,
+        emulator: &mut Emulator,
+        profile: LaunchProfile,
+    ) -> Result<(), EmulatorError> {
+        profile.validate()?;
+        if !self.db.set_launch_profile(&emulator.name, &profile).await? {
+            return Err(EmulatorError::NotFound(emulator.name.clone()));
+        }
+        emulator.launch_profile = profile;
+        Ok(())
+    }
+
+    /// Fail unless the emulator is running
+    fn require_running(emulator: &Emulator) -> Result<(), EmulatorError> {
+        if emulator.state != EmulatorState::Running {
+            return Err(EmulatorError::NotRunning {
+                name: emulator.name.clone(),
+                state: emulator.state,
+            });
+        }
+        Ok(())
+    }
+
+    /// Save the current state of a running emulator as a named snapshot and record
+    /// its metadata, replacing an existing snapshot with the same name
+    pub async fn save_snapshot(
+        &self,
+        emulator: &Emulator,
+        name: &str,
+    ) -> Result<SnapshotRecord, EmulatorError> {
+        Self::require_running(emulator)?;
+        emulator.save_snapshot(name).await?;
+
+        let mut record = SnapshotRecord::new(&emulator.name, name);
+        record.android_build = match emulator.android_build().await {
+            Ok(build) => Some(build),
+            Err(e) => {
+                warn!("Could not read the Android build of {}: {}", emulator.name, e);
+                None
+            }
+        };
+        let dir = snapshot::snapshot_dir(&emulator.name, name);
+        record.size_bytes = avd::blocking(move || Ok(snapshot::dir_size(&dir).ok()))
+            .await?
+            .map(|size| size as i64);
+        self.snapshots.save_snapshot(&record).await?;
+        info!("Saved snapshot {} of emulator {}", name, emulator.name);
+        Ok(record)
+    }
+
+    /// Restore a running emulator to a named snapshot
+    pub async fn load_snapshot(&self, emulator: &Emulator, name: &str) -> Result<(), EmulatorError> {
+        Self::require_running(emulator)?;
+        emulator.load_snapshot(name).await?;
+        info!("Loaded snapshot {} of emulator {}", name, emulator.name);
+        Ok(())
+    }
+
+    /// List the snapshots of an emulator.
+    ///
+    /// While the emulator runs the list comes from its console, so snapshots taken
+    /// outside this backend (such as the quickboot `default_boot`) are included too.
+    pub async fn list_snapshots(
+        &self,
+        emulator: &Emulator,
+    ) -> Result<Vec<SnapshotRecord>, EmulatorError> {
+        let mut records = self.snapshots.list_snapshots(&emulator.name).await?;
+        if emulator.state != EmulatorState::Running {
+            return Ok(records);
+        }
+
+        let live = emulator.list_snapshots().await?;
+        records.retain(|record| live.contains(&record.name));
+        for name in live {
+            if records.iter().any(|record| record.name == name) {
+                continue;
+            }
+            let dir = snapshot::snapshot_dir(&emulator.name, &name);
+            let mut record = SnapshotRecord::new(&emulator.name, &name);
+            let (size, modified) = avd::blocking(move || {
+                let modified = dir.metadata().and_then(|metadata| metadata.modified()).ok();
+                Ok((snapshot::dir_size(&dir).ok(), modified))
+            })
+            .await?;
+            record.size_bytes = size.map(|size| size as i64);
+            if let Some(modified) = modified {
+                record.created_at = chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339();
+            }
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// List the packages installed on an emulator.
+    ///
+    /// A running emulator is asked and the result is cached, so the packages of an
+    /// emulator that is not running are the ones it had when it last ran.
+    pub async fn list_packages(&self, emulator: &Emulator) -> Result<PackageInventory, EmulatorError> {
+        if emulator.state != EmulatorState::Running {
+            let cached = self.packages.list_packages(&emulator.name).await?;
+            let (refreshed_at, packages) = cached.unzip();
+            return Ok(PackageInventory {
+                refreshed_at,
+                live: false,
+                packages: packages.unwrap_or_default(),
+            });
+        }
+
+        let packages = emulator.installed_packages().await?;
+        let refreshed_at = self.packages.replace_packages(&emulator.name, &packages).await?;
+        Ok(PackageInventory {
+            refreshed_at: Some(refreshed_at),
+            live: true,
+            packages,
+        })
+    }
+
+    /// Delete a named snapshot, through the console if the emulator is running and
+    /// from the AVD directory if it is not. A booting or stopping emulator still has
+    /// its snapshots open, so they are left alone.
+    pub async fn delete_snapshot(&self, emulator: &Emulator, name: &str) -> Result<(), EmulatorError> {
+        snapshot::validate_snapshot_name(name)?;
+
+        let existed_on_disk = match emulator.state {
+            EmulatorState::Running => {
+                emulator.delete_snapshot(name).await?;
+                true
+            }
+            EmulatorState::Booting | EmulatorState::Stopping => {
+                return Err(EmulatorError::StillActive {
+                    name: emulator.name.clone(),
+                    state: emulator.state,
+                })
+            }
+            _ => {
+                let dir = snapshot::snapshot_dir(&emulator.name, name);
+                avd::blocking(move || match std::fs::remove_dir_all(dir) {
+                    Ok(()) => Ok(true),
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+                    Err(e) => Err(EmulatorError::SnapshotError(e.to_string())),
+                })
+                .await?
+            }
+        };
+
+        let recorded = self.snapshots.delete_snapshot(&emulator.name, name).await?;
+        if !existed_on_disk && !recorded {
+            return Err(EmulatorError::SnapshotNotFound {
+                name: emulator.name.clone(),
+                snapshot: name.to_string(),
+            });
+        }
+        info!("Deleted snapshot {} of emulator {}", name, emulator.name);
+        Ok(())
+    }
+
+    /// Launch an emulator without waiting for it to boot.
+    ///
+    /// The emulator stays `booting` until the device has fully booted, at which point
+    /// it moves to `running`. If the boot fails or times out it moves to `failed`.
+    /// The process is then supervised and restarted according to its restart policy.
+    pub async fn start_emulator(&self, emulator: &mut Emulator) -> Result<(), EmulatorError> {
+        self.transition(emulator, EmulatorState::Booting, None).await?;
+
+        let child = match emulator.start().await {
+            Ok(child) => child,
+            Err(e) => {
+                self.transition(emulator, EmulatorState::Failed, Some(e.to_string())).await?;
+                return Err(e);
+            }
+        };
+
+        let process = ProcessHandle::new(child.id(), self.supervisor_config.log_lines);
+        let instance =
+            EmulatorInstance::new(emulator.name.clone(), emulator.serial(), Some(process.clone()))
+                .with_commands(self.runner.clone(), self.command_policy);
+        self.registry.insert(instance).await;
+
+        let supervisor = Supervisor {
+            emulator: emulator.clone(),
+            policy: emulator.restart_policy,
+            process,
+            db: self.db.clone(),
+            registry: self.registry.clone(),
+            boot_config: self.boot_config,
+            config: self.supervisor_config,
+        };
+        tokio::spawn(supervisor.run(child));
+
+        Ok(())
+    }
+
+    /// Stop a running or failed emulator and remove it from the registry.
+    ///
+    /// The supervisor is told not to restart the emulator and kills the process if it
+    /// does not exit on its own, for example because adb cannot reach it while it is
+    /// still booting.
+    pub async fn stop_emulator(&self, emulator: &mut Emulator) -> Result<(), EmulatorError> {
+        let failed = emulator.state == EmulatorState::Failed;
+        self.transition(emulator, EmulatorState::Stopping, None).await?;
+
+        let instance = self.registry.get(&emulator.name).await;
+        let process = instance.and_then(|instance| instance.process);
+        if let Some(process) = &process {
+            process.kill();
+        }
+
+        if let Err(e) = emulator.stop().await {
+            if failed && process.is_none() {
+                // A failed emulator whose process is gone usually has nothing left
+                // to stop, so its console and adb cannot be reached either
+                info!("Emulator {} is already down: {}", emulator.name, e);
+            } else if process.is_none() {
+                self.transition(emulator, EmulatorState::Failed, Some(e.to_string())).await?;
+                return Err(e);
+            }
+            warn!("Stopping emulator {} over adb failed, killing it: {}", emulator.name, e);
+        }
+
+        self.registry.remove(&emulator.name).await;
+        emulator.app_manager = None;
+        self.transition(emulator, EmulatorState::Stopped, None).await
     }
 }
 
 /// Represents an Android emulator instance
+#[derive(Clone)]
 pub struct Emulator {
     name: String,
     port: u16,
     adb_port: u16,
-    port_manager: SharedPortManager,
+    state: EmulatorState,
+    last_error: Option<String>,
+    restart_policy: RestartPolicy,
+    launch_profile: LaunchProfile,
+    created_at: String,
     app_manager: Option<AppManager>,
+    runner: SharedRunner,
+    command_policy: CommandPolicy,
 }
 
 impl Emulator {
-    fn new(name: String, port: u16, port_manager: SharedPortManager) -> Self {
+    fn new(name: String, port: u16, runner: SharedRunner, command_policy: CommandPolicy) -> Self {
         Self {
             name,
             port,
             adb_port: port + 1,
-            port_manager,
+            state: EmulatorState::Created,
+            last_error: None,
+            restart_policy: RestartPolicy::default(),
+            launch_profile: LaunchProfile::default(),
+            created_at: chrono::Utc::now().to_rfc3339(),
             app_manager: None,
+            runner,
+            command_policy,
         }
     }
 
-    fn from_config(config: crate::db::emulator::EmulatorConfig, port_manager: SharedPortManager) -> Self {
+    fn from_config(
+        config: crate::db::emulator::EmulatorConfig,
+        runner: SharedRunner,
+        command_policy: CommandPolicy,
+    ) -> Self {
         Self {
             name: config.name,
             port: config.console_port,
             adb_port: config.adb_port,
-            port_manager,
+            state: config.state,
+            last_error: config.last_error,
+            restart_policy: config.restart_policy.0,
+            launch_profile: config.launch_profile.0,
+            created_at: config.created_at,
             app_manager: None,
+            runner,
+            command_policy,
         }
     }
 
@@ -111,11 +925,50 @@ impl Emulator {
             name: self.name.clone(),
             console_port: self.port,
             adb_port: self.adb_port,
-            created_at: chrono::Utc::now().to_rfc3339(),
+            state: self.state,
+            last_error: self.last_error.clone(),
+            restart_policy: sqlx::types::Json(self.restart_policy),
+            launch_profile: sqlx::types::Json(self.launch_profile.clone()),
+            created_at: self.created_at.clone(),
             updated_at: chrono::Utc::now().to_rfc3339(),
         }
     }
 
+    /// Get the emulator's name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the adb serial of the emulator
+    pub fn serial(&self) -> String {
+        format!("emulator-{}", self.port)
+    }
+
+    /// Get the emulator's lifecycle state
+    pub fn state(&self) -> EmulatorState {
+        self.state
+    }
+
+    /// Get the reason the emulator last failed, if any
+    pub fn last_error(&self) -> Option<&str> {
+        self.last_error.as_deref()
+    }
+
+    /// Get what happens when the emulator process exits on its own
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.restart_policy
+    }
+
+    /// Get the command line settings used to launch the emulator
+    pub fn launch_profile(&self) -> &LaunchProfile {
+        &self.launch_profile
+    }
+
+    /// Get when the emulator was created, as an RFC 3339 timestamp
+    pub fn created_at(&self) -> &str {
+        &self.created_at
+    }
+
     /// Get the emulator's console port
     pub fn port(&self) -> u16 {
         self.port
@@ -126,133 +979,201 @@ impl Emulator {
         self.adb_port
     }
 
-    /// Start the emulator instance
-    pub async fn start(&mut self) -> Result<(), EmulatorError> {
+    /// Spawn the emulator process and return without waiting for it to boot
+    pub async fn start(&mut self) -> Result<Child, EmulatorError> {
         info!("Starting emulator {} on port {}", self.name, self.port);
-        
-        let output = TokioCommand::new("emulator")
-            .arg("-avd")
-            .arg(&self.name)
-            .arg("-port")
-            .arg(self.port.to_string())
-            .arg("-no-window")  // Run headless
-            .output()
-            .await
-            .map_err(|e| EmulatorError::StartError(e.to_string()))?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to start emulator: {}", error);
-            return Err(EmulatorError::StartError(error.to_string()));
-        }
+        let args = self.launch_profile.args(&self.name, self.port)?;
+        let child = self
+            .runner
+            .spawn(&CommandSpec::new("emulator").args(args))
+            .map_err(|e| {
+                error!("Failed to start emulator: {}", e);
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    EmulatorError::ToolNotFound("emulator".to_string())
+                } else {
+                    EmulatorError::StartError(e.to_string())
+                }
+            })?;
 
-        // Initialize app manager after emulator is started
-        self.app_manager = Some(AppManager::new(format!("emulator-{}", self.port)));
-        info!("Successfully started emulator {}", self.name);
-        Ok(())
+        info!("Spawned emulator {} (pid {:?})", self.name, child.id());
+        Ok(child)
     }
 
-    /// Stop the emulator instance and release its ports
+    /// Stop the emulator instance through its console, falling back to `adb emu kill`
+    /// when the console cannot be reached directly.
+    ///
+    /// Its ports stay reserved for as long as the emulator exists so it can be
+    /// restarted on the same serial.
     pub async fn stop(&mut self) -> Result<(), EmulatorError> {
         info!("Stopping emulator {}", self.name);
-        
-        let output = TokioCommand::new("adb")
-            .arg("-s")
-            .arg(format!("emulator-{}", self.port))
-            .arg("emu")
-            .arg("kill")
-            .output()
-            .await
-            .map_err(|e| EmulatorError::StopError(e.to_string()))?;
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to stop emulator: {}", error);
-            return Err(EmulatorError::StopError(error.to_string()));
+        match ConsoleClient::connect(self.port, self.command_policy).await {
+            Ok(console) => console.kill().await?,
+            Err(e) => {
+                warn!("Console of emulator {} unavailable, using adb: {}", self.name, e);
+                self.adb_command(&["emu", "kill"])
+                    .await
+                    .map_err(|e| EmulatorError::StopError(e.to_string()))?;
+            }
         }
 
-        // Release the ports
-        self.port_manager.release_ports(&self.name).await;
         info!("Successfully stopped emulator {}", self.name);
         Ok(())
     }
 
     /// Check if the emulator is running
     pub async fn is_running(&self) -> Result<bool, EmulatorError> {
-        let output = TokioCommand::new("adb")
-            .arg("devices")
-            .output()
-            .await
-            .map_err(|e| EmulatorError::StatusCheckError(e.to_string()))?;
-
-        let devices = String::from_utf8_lossy(&output.stdout);
-        Ok(devices.contains(&format!("emulator-{}", self.port)))
+        let serial = self.serial();
+        Ok(devices::list_devices(self.runner.as_ref(), self.command_policy)
+            .await?
+            .iter()
+            .any(|device| device.serial == serial && device.is_online()))
     }
 
-    /// Execute an ADB command on the emulator
+    /// Execute an ADB command on the emulator, subject to the command timeout and
+    /// retried on transient adb errors
     pub async fn adb_command(&self, args: &[&str]) -> Result<String, EmulatorError> {
-        let mut command = TokioCommand::new("adb");
-        command
+        let command = CommandSpec::new("adb")
             .arg("-s")
-            .arg(format!("emulator-{}", self.port));
-        
-        for arg in args {
-            command.arg(arg);
+            .arg(self.serial())
+            .args(args.iter().copied());
+
+        let output = self.command_policy.run(self.runner.as_ref(), &command).await?;
+
+        if !output.success() {
+            return Err(EmulatorError::AdbError(output.stderr_lossy()));
         }
 
-        let output = command
-            .output()
-            .await
-            .map_err(|e| EmulatorError::AdbError(e.to_string()))?;
+        Ok(output.stdout_lossy())
+    }
+
+    /// Connect to the emulator console for a snapshot command
+    async fn snapshot_console(&self) -> Result<ConsoleClient, EmulatorError> {
+        let mut console = ConsoleClient::connect(self.port, self.command_policy).await?;
+        console.set_timeout(snapshot::SNAPSHOT_TIMEOUT);
+        Ok(console)
+    }
+
+    /// Save the current state of the emulator as a named snapshot
+    pub async fn save_snapshot(&self, name: &str) -> Result<(), EmulatorError> {
+        snapshot::validate_snapshot_name(name)?;
+        Ok(self.snapshot_console().await?.snapshot_save(name).await?)
+    }
 
-        if !output.status.success() {
-            let error = String::from_utf8_lossy(&output.stderr);
-            return Err(EmulatorError::AdbError(error.to_string()));
+    /// Restore the emulator to a named snapshot
+    pub async fn load_snapshot(&self, name: &str) -> Result<(), EmulatorError> {
+        snapshot::validate_snapshot_name(name)?;
+        Ok(self.snapshot_console().await?.snapshot_load(name).await?)
+    }
+
+    /// Delete a named snapshot
+    pub async fn delete_snapshot(&self, name: &str) -> Result<(), EmulatorError> {
+        snapshot::validate_snapshot_name(name)?;
+        Ok(self.snapshot_console().await?.snapshot_delete(name).await?)
+    }
+
+    /// List the names of the emulator's snapshots
+    pub async fn list_snapshots(&self) -> Result<Vec<String>, EmulatorError> {
+        let lines = self.snapshot_console().await?.snapshot_list().await?;
+        Ok(snapshot::parse_snapshot_list(&lines))
+    }
+
+    /// Get the build fingerprint of the system running on the emulator
+    pub async fn android_build(&self) -> Result<String, EmulatorError> {
+        let output = AdbClient::for_runner(self.runner.as_ref(), self.command_policy)
+            .shell(&self.serial(), "getprop ro.build.fingerprint")
+            .await?;
+        if !output.success() {
+            return Err(EmulatorError::AdbError(output.stderr_lossy()));
         }
+        Ok(output.stdout_lossy().trim().to_string())
+    }
 
-        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    /// App manager of the running emulator
+    fn app_manager(&self) -> Result<&AppManager, EmulatorError> {
+        self.app_manager.as_ref().ok_or_else(|| EmulatorError::NotRunning {
+            name: self.name.clone(),
+            state: self.state,
+        })
     }
 
     /// Install an application on the emulator
-    pub async fn install_app<P: AsRef<Path>>(&self, apk_path: P) -> Result<(), EmulatorError> {
-        let app_manager = self.app_manager.as_ref()
-            .ok_or_else(|| EmulatorError::StartError("Emulator not started".to_string()))?;
-        Ok(app_manager.install_app(apk_path).await?)
+    pub async fn install_app<P: AsRef<Path>>(
+        &self,
+        apk_path: P,
+        options: &InstallOptions,
+    ) -> Result<(), EmulatorError> {
+        Ok(self.app_manager()?.install_app(apk_path, options).await?)
+    }
+
+    /// Install the split APKs of an application together
+    pub async fn install_multiple<P: AsRef<Path>>(
+        &self,
+        apk_paths: &[P],
+        options: &InstallOptions,
+    ) -> Result<(), EmulatorError> {
+        Ok(self.app_manager()?.install_multiple(apk_paths, options).await?)
+    }
+
+    /// Copy an expansion file of an application to the emulator
+    pub async fn push_obb<P: AsRef<Path>>(
+        &self,
+        package_name: &str,
+        obb_path: P,
+        file_name: &str,
+    ) -> Result<(), EmulatorError> {
+        Ok(self.app_manager()?.push_obb(package_name, obb_path, file_name).await?)
+    }
+
+    /// The ABIs and screen density of the emulator
+    pub async fn device_profile(&self) -> Result<DeviceProfile, EmulatorError> {
+        Ok(self.app_manager()?.device_profile().await?)
     }
 
     /// Uninstall an application from the emulator
     pub async fn uninstall_app(&self, package_name: &str) -> Result<(), EmulatorError> {
-        let app_manager = self.app_manager.as_ref()
-            .ok_or_else(|| EmulatorError::StartError("Emulator not started".to_string()))?;
-        Ok(app_manager.uninstall_app(package_name).await?)
+        Ok(self.app_manager()?.uninstall_app(package_name).await?)
+    }
+
+    /// Start an application on the emulator, optionally waiting for it to draw
+    pub async fn start_app(
+        &self,
+        package_name: &str,
+        activity: &str,
+        wait: bool,
+    ) -> Result<LaunchResult, EmulatorError> {
+        Ok(self.app_manager()?.start_app(package_name, activity, wait).await?)
+    }
+
+    /// Resolve the launcher activity of an application on the emulator
+    pub async fn resolve_launcher_activity(&self, package_name: &str) -> Result<Option<String>, EmulatorError> {
+        Ok(self.app_manager()?.resolve_launcher_activity(package_name).await?)
     }
 
-    /// Start an application on the emulator
-    pub async fn start_app(&self, package_name: &str, activity: &str) -> Result<(), EmulatorError> {
-        let app_manager = self.app_manager.as_ref()
-            .ok_or_else(|| EmulatorError::StartError("Emulator not started".to_string()))?;
-        Ok(app_manager.start_app(package_name, activity).await?)
+    /// Send an intent to an activity, service or broadcast receivers on the emulator
+    pub async fn send_intent(&self, intent: &Intent) -> Result<IntentResult, EmulatorError> {
+        Ok(self.app_manager()?.send_intent(intent).await?)
     }
 
     /// Stop an application on the emulator
     pub async fn stop_app(&self, package_name: &str) -> Result<(), EmulatorError> {
-        let app_manager = self.app_manager.as_ref()
-            .ok_or_else(|| EmulatorError::StartError("Emulator not started".to_string()))?;
-        Ok(app_manager.stop_app(package_name).await?)
+        Ok(self.app_manager()?.stop_app(package_name).await?)
+    }
+
+    /// List the running processes of an application on the emulator
+    pub async fn app_processes(&self, package_name: &str) -> Result<Vec<AppProcess>, EmulatorError> {
+        Ok(self.app_manager()?.processes(package_name).await?)
     }
 
-    /// Check if an application is running on the emulator
-    pub async fn is_app_running(&self, package_name: &str) -> Result<bool, EmulatorError> {
-        let app_manager = self.app_manager.as_ref()
-            .ok_or_else(|| EmulatorError::StartError("Emulator not started".to_string()))?;
-        Ok(app_manager.is_app_running(package_name).await?)
+    /// List the packages installed on the emulator
+    pub async fn installed_packages(&self) -> Result<Vec<InstalledPackage>, EmulatorError> {
+        Ok(self.app_manager()?.installed_packages().await?)
     }
 
     /// Get the version of an installed application
     pub async fn get_app_version(&self, package_name: &str) -> Result<String, EmulatorError> {
-        let app_manager = self.app_manager.as_ref()
-            .ok_or_else(|| EmulatorError::StartError("Emulator not started".to_string()))?;
-        Ok(app_manager.get_app_version(package_name).await?)
+        Ok(self.app_manager()?.get_app_version(package_name).await?)
     }
 }
 
@@ -261,9 +1182,13 @@ mod tests {
     use super::*;
     use tokio::test;
 
+    async fn test_manager() -> EmulatorManager {
+        EmulatorManager::new(crate::db::create_pool("sqlite::memory:").await.unwrap())
+    }
+
     #[test]
     async fn test_emulator_manager() {
-        let manager = EmulatorManager::new(SqlitePool::connect("sqlite::memory:").await.unwrap());
+        let manager = test_manager().await;
         
         // Create first emulator
         let emu1 = manager.create_emulator("test_avd1".to_string()).await.unwrap();
@@ -280,8 +1205,218 @@ mod tests {
 
     #[test]
     async fn test_emulator_creation() {
-        let manager = EmulatorManager::new(SqlitePool::connect("sqlite::memory:").await.unwrap());
+        let manager = test_manager().await;
         let emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
         assert_eq!(emu.name, "test_avd");
     }
+
+    #[test]
+    async fn test_get_emulator_attaches_running_instance() {
+        let manager = test_manager().await;
+        manager.create_emulator("test_avd".to_string()).await.unwrap();
+
+        let mut emu = manager.get_emulator("test_avd").await.unwrap();
+        assert!(emu.app_manager.is_none());
+
+        let instance = EmulatorInstance::new("test_avd".to_string(), emu.serial(), None);
+        manager.registry().insert(instance).await;
+        manager.transition(&mut emu, EmulatorState::Booting, None).await.unwrap();
+        let emu = manager.get_emulator("test_avd").await.unwrap();
+        assert!(emu.app_manager.is_none(), "booting emulators have no app manager");
+
+        manager.db.update_state("test_avd", EmulatorState::Booting, EmulatorState::Running, None)
+            .await
+            .unwrap();
+        let emu = manager.get_emulator("test_avd").await.unwrap();
+        assert!(emu.app_manager.is_some());
+    }
+
+    #[test]
+    async fn test_state_is_persisted() {
+        let manager = test_manager().await;
+        let mut emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
+        assert_eq!(emu.state(), EmulatorState::Created);
+
+        manager.transition(&mut emu, EmulatorState::Booting, None).await.unwrap();
+        manager
+            .transition(&mut emu, EmulatorState::Failed, Some("boot timed out".to_string()))
+            .await
+            .unwrap();
+
+        let stored = manager.get_emulator("test_avd").await.unwrap();
+        assert_eq!(stored.state(), EmulatorState::Failed);
+        assert_eq!(stored.last_error(), Some("boot timed out"));
+    }
+
+    #[test]
+    async fn test_illegal_transition_is_rejected() {
+        let manager = test_manager().await;
+        let mut emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
+        manager.transition(&mut emu, EmulatorState::Booting, None).await.unwrap();
+
+        let err = manager.start_emulator(&mut emu).await.unwrap_err();
+        assert!(matches!(
+            err,
+            EmulatorError::InvalidTransition {
+                from: EmulatorState::Booting,
+                to: EmulatorState::Booting,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    async fn test_stale_transition_is_rejected() {
+        let manager = test_manager().await;
+        let mut emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
+        let mut stale = manager.get_emulator("test_avd").await.unwrap();
+
+        manager.transition(&mut emu, EmulatorState::Booting, None).await.unwrap();
+        let err = manager.transition(&mut stale, EmulatorState::Booting, None).await.unwrap_err();
+        assert!(matches!(
+            err,
+            EmulatorError::InvalidTransition { from: EmulatorState::Booting, .. }
+        ));
+    }
+
+    #[test]
+    async fn test_apply_devices_reconciles_states() {
+        let manager = test_manager().await;
+        let mut running = manager.create_emulator("running_avd".to_string()).await.unwrap();
+        let mut crashed = manager.create_emulator("crashed_avd".to_string()).await.unwrap();
+        manager.create_emulator("fresh_avd".to_string()).await.unwrap();
+        manager.transition(&mut crashed, EmulatorState::Booting, None).await.unwrap();
+        manager.transition(&mut running, EmulatorState::Booting, None).await.unwrap();
+
+        let devices = devices::parse_devices(
+            "List of devices attached\nemulator-5554\tdevice\nemulator-5580\tdevice\n",
+        );
+        let configs = manager.db.list_emulators().await.unwrap();
+        let mut report = ReconcileReport::default();
+        manager.apply_devices(&configs, &devices, &mut report).await.unwrap();
+
+        assert_eq!(report.running, vec!["running_avd".to_string()]);
+        assert_eq!(report.unknown_serials, vec!["emulator-5580".to_string()]);
+
+        let running = manager.get_emulator("running_avd").await.unwrap();
+        assert_eq!(running.state(), EmulatorState::Running);
+        assert!(running.app_manager.is_some());
+        let crashed = manager.get_emulator("crashed_avd").await.unwrap();
+        assert_eq!(crashed.state(), EmulatorState::Failed);
+        assert!(crashed.last_error().unwrap().contains("while the emulator was booting"));
+        let fresh = manager.get_emulator("fresh_avd").await.unwrap();
+        assert_eq!(fresh.state(), EmulatorState::Created);
+    }
+
+    #[test]
+    async fn test_apply_devices_clears_recovered_failure() {
+        let manager = test_manager().await;
+        let mut emulator = manager.create_emulator("test_avd".to_string()).await.unwrap();
+        manager.transition(&mut emulator, EmulatorState::Booting, None).await.unwrap();
+        manager
+            .transition(&mut emulator, EmulatorState::Failed, Some("boot timed out".to_string()))
+            .await
+            .unwrap();
+
+        let devices = devices::parse_devices("emulator-5554\tdevice\n");
+        let configs = manager.db.list_emulators().await.unwrap();
+        let mut report = ReconcileReport::default();
+        manager.apply_devices(&configs, &devices, &mut report).await.unwrap();
+
+        let emulator = manager.get_emulator("test_avd").await.unwrap();
+        assert_eq!(emulator.state(), EmulatorState::Running);
+        assert_eq!(emulator.last_error(), None);
+    }
+
+    #[test]
+    async fn test_reconcile_paths_follow_the_state_machine() {
+        use EmulatorState::*;
+
+        for stored in [Created, Booting, Running, Stopping, Stopped, Failed] {
+            for online in [true, false] {
+                let (path, _) = reconcile_path(stored, online);
+                let mut from = stored;
+                for &to in path {
+                    assert!(from.can_transition_to(to), "{} -> {}", from, to);
+                    from = to;
+                }
+                let expected = match (stored, online) {
+                    (Stopping, true) => Failed,
+                    (_, true) => Running,
+                    (Booting, false) => Failed,
+                    (Running | Stopping, false) => Stopped,
+                    (state, false) => state,
+                };
+                assert_eq!(from, expected, "{} online={}", stored, online);
+            }
+        }
+    }
+
+    #[test]
+    async fn test_reconcile_restores_ports() {
+        let pool = crate::db::create_pool("sqlite::memory:").await.unwrap();
+        let before_restart = EmulatorManager::new(pool.clone());
+        before_restart.create_emulator("test_avd1".to_string()).await.unwrap();
+
+        let manager = EmulatorManager::new(pool);
+        let report = manager.reconcile().await.unwrap();
+        assert_eq!(report.restored, vec!["test_avd1".to_string()]);
+
+        let emu = manager.create_emulator("test_avd2".to_string()).await.unwrap();
+        assert_eq!(emu.port, 5556);
+    }
+
+    #[test]
+    async fn test_delete_emulator_releases_ports() {
+        let manager = test_manager().await;
+        let mut emu = manager.create_emulator("test_avd1".to_string()).await.unwrap();
+        manager.create_emulator("test_avd2".to_string()).await.unwrap();
+
+        let report = manager.delete_emulator(&mut emu, DeleteOptions::default()).await.unwrap();
+        assert!(!report.stopped);
+        assert!(manager.get_emulator("test_avd1").await.is_none());
+
+        let emu = manager.create_emulator("test_avd3".to_string()).await.unwrap();
+        assert_eq!(emu.port, 5554);
+    }
+
+    #[test]
+    async fn test_delete_active_emulator_requires_force() {
+        let manager = test_manager().await;
+        let mut emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
+        manager.transition(&mut emu, EmulatorState::Booting, None).await.unwrap();
+
+        let err = manager.delete_emulator(&mut emu, DeleteOptions::default()).await.unwrap_err();
+        assert!(matches!(err, EmulatorError::StillActive { state: EmulatorState::Booting, .. }));
+        assert!(manager.get_emulator("test_avd").await.is_some());
+    }
+
+    #[test]
+    async fn test_snapshots_require_running_emulator() {
+        let manager = test_manager().await;
+        let emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
+
+        let err = manager.save_snapshot(&emu, "clean").await.unwrap_err();
+        assert!(matches!(err, EmulatorError::NotRunning { state: EmulatorState::Created, .. }));
+        let err = manager.load_snapshot(&emu, "clean").await.unwrap_err();
+        assert!(matches!(err, EmulatorError::NotRunning { .. }));
+    }
+
+    #[test]
+    async fn test_delete_snapshot_of_stopped_emulator() {
+        let manager = test_manager().await;
+        let mut emu = manager.create_emulator("test_avd".to_string()).await.unwrap();
+        manager.snapshots.save_snapshot(&SnapshotRecord::new("test_avd", "clean")).await.unwrap();
+        assert_eq!(manager.list_snapshots(&emu).await.unwrap().len(), 1);
+
+        manager.delete_snapshot(&emu, "clean").await.unwrap();
+        assert!(manager.list_snapshots(&emu).await.unwrap().is_empty());
+        let err = manager.delete_snapshot(&emu, "clean").await.unwrap_err();
+        assert!(matches!(err, EmulatorError::SnapshotNotFound { .. }));
+
+        // Deleting the emulator forgets its snapshots
+        manager.snapshots.save_snapshot(&SnapshotRecord::new("test_avd", "clean")).await.unwrap();
+        manager.delete_emulator(&mut emu, DeleteOptions::default()).await.unwrap();
+        assert!(manager.snapshots.list_snapshots("test_avd").await.unwrap().is_empty());
+    }
 }
@@ -0,0 +1,226 @@
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use std::collections::HashMap;
+
+/// A package installed on the device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, FromRow)]
+pub struct InstalledPackage {
+    pub package: String,
+    /// Path of the base APK on the device
+    pub apk_path: String,
+    pub version_code: Option<i64>,
+    pub version_name: Option<String>,
+    /// Whether the package is part of the system image, even if updated since
+    pub system: bool,
+    /// Package that installed it, e.g. `com.android.vending`
+    pub installer: Option<String>,
+    /// Local time on the device, as printed by `dumpsys`
+    pub first_install_time: Option<String>,
+    pub last_update_time: Option<String>,
+}
+
+/// The packages installed on an emulator
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageInventory {
+    /// When the packages were read from the emulator; `None` if it has not run
+    /// since inventories were kept
+    pub refreshed_at: Option<String>,
+    /// Whether the packages were just read from the running emulator rather than
+    /// taken from the cache
+    pub live: bool,
+    pub packages: Vec<InstalledPackage>,
+}
+
+/// What `dumpsys package` says about one package beyond `pm list packages`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PackageDetails {
+    pub version_code: Option<i64>,
+    pub version_name: Option<String>,
+    pub installer: Option<String>,
+    pub first_install_time: Option<String>,
+    pub last_update_time: Option<String>,
+}
+
+/// Parse the output of `pm list packages -f --show-versioncode` into package name,
+/// APK path and version code.
+///
+/// Lines look like `package:/data/app/~~Zq==/com.foo-Xw==/base.apk=com.foo versionCode:12`;
+/// the path itself may contain `=`, so the package is whatever follows the last one.
+pub fn parse_package_list(output: &str) -> Result<Vec<(String, String, Option<i64>)>, String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let invalid = || format!("unexpected pm line {:?}", line);
+            let entry = line.strip_prefix("package:").ok_or_else(invalid)?;
+            let (entry, version_code) = match entry.rsplit_once(" versionCode:") {
+                Some((entry, code)) => (entry, Some(code.trim().parse().map_err(|_| invalid())?)),
+                None => (entry, None),
+            };
+            let (path, package) = entry.rsplit_once('=').ok_or_else(invalid)?;
+            if path.is_empty() || package.is_empty() {
+                return Err(invalid());
+            }
+            Ok((package.to_string(), path.to_string(), version_code))
+        })
+        .collect()
+}
+
+/// Parse the package names printed by `pm list packages`
+pub fn parse_package_names(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("package:"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Parse the `Packages:` section of `dumpsys package`.
+///
+/// Later sections such as `Hidden system packages:` describe the versions an update
+/// replaced, so they are skipped.
+pub fn parse_dumpsys_packages(output: &str) -> HashMap<String, PackageDetails> {
+    let mut packages = HashMap::new();
+    let mut in_packages = false;
+    let mut current: Option<(String, PackageDetails)> = None;
+
+    for line in output.lines() {
+        // Section headers are the only unindented lines
+        if !line.starts_with(' ') && !line.trim().is_empty() {
+            in_packages = line.trim_end() == "Packages:";
+            packages.extend(current.take());
+            continue;
+        }
+        if !in_packages {
+            continue;
+        }
+
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Package [") {
+            packages.extend(current.take());
+            if let Some((package, _)) = rest.split_once(']') {
+                current = Some((package.to_string(), PackageDetails::default()));
+            }
+            continue;
+        }
+        let Some((_, details)) = current.as_mut() else {
+            continue;
+        };
+        // `versionCode=12 minSdk=24 targetSdk=34` holds several fields on one line
+        for field in line.split_whitespace() {
+            if let Some(code) = field.strip_prefix("versionCode=") {
+                details.version_code = details.version_code.or(code.parse().ok());
+            }
+        }
+        let value = |prefix: &str| {
+            line.strip_prefix(prefix)
+                .map(str::trim)
+                .filter(|value| !value.is_empty() && *value != "null")
+                .map(str::to_string)
+        };
+        if let Some(name) = value("versionName=") {
+            details.version_name.get_or_insert(name);
+        } else if let Some(installer) = value("installerPackageName=") {
+            details.installer.get_or_insert(installer);
+        } else if let Some(time) = value("firstInstallTime=") {
+            details.first_install_time.get_or_insert(time);
+        } else if let Some(time) = value("lastUpdateTime=") {
+            details.last_update_time.get_or_insert(time);
+        }
+    }
+    packages.extend(current);
+    packages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMPSYS_OUTPUT: &str = "\
+Activity Resolver Table:
+  Non-Data Actions:
+      android.intent.action.MAIN:
+        1f2e3d com.foo/.MainActivity filter 9a8b7c
+
+Packages:
+  Package [com.foo] (1f2e3d):
+    userId=10123
+    pkg=Package{4c5d6e com.foo}
+    codePath=/data/app/~~Zq==/com.foo-Xw==
+    versionCode=12 minSdk=24 targetSdk=34
+    versionName=1.2.0
+    timeStamp=2024-05-01 10:00:00
+    firstInstallTime=2024-04-01 09:30:00
+    lastUpdateTime=2024-05-01 10:00:01
+    installerPackageName=com.android.vending
+    User 0: ceDataInode=1234 installed=true hidden=false
+      firstInstallTime=2024-04-01 09:30:00
+  Package [com.android.chrome] (7a8b9c):
+    versionCode=600000000 minSdk=29 targetSdk=34
+    versionName=120.0
+    firstInstallTime=2009-01-01 00:00:00
+    lastUpdateTime=2024-03-01 12:00:00
+    installerPackageName=null
+
+Hidden system packages:
+  Package [com.android.chrome] (1a2b3c):
+    versionCode=500000000 minSdk=29 targetSdk=33
+    versionName=110.0
+
+Queries:
+  system apps queryable: false
+";
+
+    #[test]
+    fn test_parse_package_list() {
+        let output = "\
+package:/data/app/~~Zq==/com.foo-Xw==/base.apk=com.foo versionCode:12
+package:/system/priv-app/Settings/Settings.apk=com.android.settings versionCode:34
+package:/system/app/Old/Old.apk=com.old
+";
+        assert_eq!(
+            parse_package_list(output).unwrap(),
+            vec![
+                (
+                    "com.foo".to_string(),
+                    "/data/app/~~Zq==/com.foo-Xw==/base.apk".to_string(),
+                    Some(12)
+                ),
+                (
+                    "com.android.settings".to_string(),
+                    "/system/priv-app/Settings/Settings.apk".to_string(),
+                    Some(34)
+                ),
+                ("com.old".to_string(), "/system/app/Old/Old.apk".to_string(), None),
+            ]
+        );
+        assert!(parse_package_list("Error: unknown option --show-versioncode\n").is_err());
+        assert_eq!(
+            parse_package_names("package:com.a\npackage:com.b\n"),
+            vec!["com.a", "com.b"]
+        );
+    }
+
+    #[test]
+    fn test_parse_dumpsys_packages() {
+        let packages = parse_dumpsys_packages(DUMPSYS_OUTPUT);
+        assert_eq!(packages.len(), 2);
+        assert_eq!(
+            packages["com.foo"],
+            PackageDetails {
+                version_code: Some(12),
+                version_name: Some("1.2.0".to_string()),
+                installer: Some("com.android.vending".to_string()),
+                first_install_time: Some("2024-04-01 09:30:00".to_string()),
+                last_update_time: Some("2024-05-01 10:00:01".to_string()),
+            }
+        );
+        // The updated system app, not the version hidden behind the update
+        let chrome = &packages["com.android.chrome"];
+        assert_eq!(chrome.version_name.as_deref(), Some("120.0"));
+        assert_eq!(chrome.installer, None);
+
+        assert!(parse_dumpsys_packages("").is_empty());
+    }
+}
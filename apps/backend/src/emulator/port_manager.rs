@@ -17,6 +17,17 @@ pub enum PortError {
     InvalidPort(u16),
 }
 
+impl PortError {
+    /// Stable, machine-readable identifier of the error for API clients
+    pub fn code(&self) -> &'static str {
+        match self {
+            PortError::NoAvailablePorts => "no_available_ports",
+            PortError::PortInUse(_) => "port_in_use",
+            PortError::InvalidPort(_) => "invalid_port",
+        }
+    }
+}
+
 /// Manages port allocation for multiple emulator instances
 #[derive(Debug, Default)]
 pub struct PortManager {
@@ -50,6 +61,19 @@ impl PortManager {
         Err(PortError::NoAvailablePorts)
     }
 
+    /// Reserve a specific port pair for an emulator, e.g. one restored from the database
+    pub fn reserve_ports(&mut self, emulator_name: &str, console_port: u16) -> Result<(), PortError> {
+        self.validate_port(console_port)?;
+
+        match self.allocated_ports.iter().find(|(_, &port)| port == console_port) {
+            Some((owner, _)) if owner != emulator_name => Err(PortError::PortInUse(console_port)),
+            _ => {
+                self.allocated_ports.insert(emulator_name.to_string(), console_port);
+                Ok(())
+            }
+        }
+    }
+
     /// Release ports allocated to an emulator
     pub fn release_ports(&mut self, emulator_name: &str) {
         self.allocated_ports.remove(emulator_name);
@@ -69,7 +93,7 @@ impl PortManager {
 
     /// Validate if a port is in the valid range and even
     pub fn validate_port(&self, port: u16) -> Result<(), PortError> {
-        if port < MIN_PORT || port > MAX_PORT || port % 2 != 0 {
+        if !(MIN_PORT..=MAX_PORT).contains(&port) || !port.is_multiple_of(2) {
             return Err(PortError::InvalidPort(port));
         }
         Ok(())
@@ -90,6 +114,11 @@ impl SharedPortManager {
         manager.allocate_ports(emulator_name)
     }
 
+    pub async fn reserve_ports(&self, emulator_name: &str, console_port: u16) -> Result<(), PortError> {
+        let mut manager = self.0.lock().await;
+        manager.reserve_ports(emulator_name, console_port)
+    }
+
     pub async fn release_ports(&self, emulator_name: &str) {
         let mut manager = self.0.lock().await;
         manager.release_ports(emulator_name)
@@ -138,4 +167,24 @@ mod tests {
         // Should return same ports
         assert_eq!(ports1, ports2);
     }
+
+    #[test]
+    async fn test_reserved_ports_are_skipped() {
+        let manager = SharedPortManager::new();
+
+        manager.reserve_ports("restored", MIN_PORT).await.unwrap();
+        let (console_port, _) = manager.allocate_ports("fresh").await.unwrap();
+        assert_eq!(console_port, MIN_PORT + 2);
+
+        // Reserving the same pair again for its owner is fine, for anyone else it is not
+        manager.reserve_ports("restored", MIN_PORT).await.unwrap();
+        assert!(matches!(
+            manager.reserve_ports("other", MIN_PORT).await,
+            Err(PortError::PortInUse(MIN_PORT))
+        ));
+        assert!(matches!(
+            manager.reserve_ports("other", MIN_PORT + 1).await,
+            Err(PortError::InvalidPort(_))
+        ));
+    }
 }
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+
+/// Columns requested from the device's `ps`, in the order `parse_ps` expects them
+pub const PS_COLUMNS: &str = "PID,USER,RSS,STIME,NAME";
+
+/// A process running on the device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AppProcess {
+    pub pid: u32,
+    pub user: String,
+    /// Resident set size in kilobytes
+    pub rss_kb: u64,
+    /// Start time as printed by `ps`: the time of day for processes started within
+    /// the last day, otherwise the date
+    pub start_time: String,
+    /// Process name: the package name, or `<package>:<name>` for processes an app
+    /// declares with `android:process`
+    pub name: String,
+}
+
+impl AppProcess {
+    /// Whether the process belongs to `package`, matching the name exactly so that
+    /// `com.foo` does not match `com.foobar`
+    pub fn belongs_to(&self, package: &str) -> bool {
+        self.name
+            .strip_prefix(package)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(':'))
+    }
+}
+
+/// Parse the output of `ps -A -o PID,USER,RSS,STIME,NAME`.
+///
+/// The start time may contain spaces, so the name is taken from the end of the line
+/// and the start time is whatever remains between the RSS and the name.
+pub fn parse_ps(output: &str) -> Result<Vec<AppProcess>, String> {
+    let mut lines = output.lines().filter(|line| !line.trim().is_empty());
+    match lines.next() {
+        Some(header) if header.split_whitespace().eq(PS_COLUMNS.split(',')) => {}
+        other => return Err(format!("unexpected ps header {:?}", other.unwrap_or_default())),
+    }
+
+    lines
+        .map(|line| {
+            let invalid = || format!("unexpected ps line {:?}", line);
+            let mut fields = line.split_whitespace();
+            let pid = fields.next().and_then(|pid| pid.parse().ok()).ok_or_else(invalid)?;
+            let user = fields.next().ok_or_else(invalid)?.to_string();
+            let rss_kb = fields.next().and_then(|rss| rss.parse().ok()).ok_or_else(invalid)?;
+            let mut rest: Vec<&str> = fields.collect();
+            let name = rest.pop().ok_or_else(invalid)?.to_string();
+            if rest.is_empty() {
+                return Err(invalid());
+            }
+            Ok(AppProcess {
+                pid,
+                user,
+                rss_kb,
+                start_time: rest.join(" "),
+                name,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PS_OUTPUT: &str = "\
+PID   USER           RSS STIME    NAME
+1     root          9876 09:12:01 init
+4242  u0_a123     154320 09:14:55 com.foo
+4301  u0_a123      61288 09:15:02 com.foo:sync
+4400  u0_a124      88004 Jan 05   com.foobar
+";
+
+    #[test]
+    fn test_parse_ps() {
+        let processes = parse_ps(PS_OUTPUT).unwrap();
+        assert_eq!(processes.len(), 4);
+        assert_eq!(
+            processes[1],
+            AppProcess {
+                pid: 4242,
+                user: "u0_a123".to_string(),
+                rss_kb: 154320,
+                start_time: "09:14:55".to_string(),
+                name: "com.foo".to_string(),
+            }
+        );
+        assert_eq!(processes[3].start_time, "Jan 05");
+
+        let matching: Vec<u32> = processes
+            .iter()
+            .filter(|process| process.belongs_to("com.foo"))
+            .map(|process| process.pid)
+            .collect();
+        assert_eq!(matching, vec![4242, 4301]);
+    }
+
+    #[test]
+    fn test_parse_ps_rejects_unexpected_output() {
+        assert!(parse_ps("").is_err());
+        assert!(parse_ps("/system/bin/sh: ps: not found\n").is_err());
+        assert!(parse_ps("PID USER RSS STIME NAME\nabc root 1 09:00:00 init\n").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Exclusive access to one emulator, released when dropped
+#[derive(Debug)]
+pub struct OperationGuard {
+    _guard: OwnedMutexGuard<()>,
+}
+
+/// Serializes operations per emulator while letting different emulators proceed
+/// in parallel.
+///
+/// Every emulator has its own lock. Tokio mutexes hand the lock out in the order
+/// it was requested, so waiting operations form a first-in first-out queue.
+#[derive(Debug, Default, Clone)]
+pub struct OperationQueue(Arc<StdMutex<HashMap<String, Arc<Mutex<()>>>>>);
+
+impl OperationQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait until every operation queued earlier for `name` has finished
+    pub async fn acquire(&self, name: &str) -> OperationGuard {
+        let lock = {
+            let mut locks = self.0.lock().unwrap();
+            // Drop the locks nobody holds or waits for, such as those of deleted emulators
+            locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+            locks.entry(name.to_string()).or_default().clone()
+        };
+        OperationGuard {
+            _guard: lock.lock_owned().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::test;
+
+    #[test]
+    async fn test_same_emulator_is_serialized() {
+        let queue = OperationQueue::new();
+        let order = Arc::new(StdMutex::new(Vec::new()));
+
+        let first = queue.acquire("a").await;
+        let mut waiters = Vec::new();
+        for i in 0..3 {
+            let queue = queue.clone();
+            let order = order.clone();
+            waiters.push(tokio::spawn(async move {
+                let _guard = queue.acquire("a").await;
+                order.lock().unwrap().push(i);
+            }));
+            // Let the task enqueue before the next one
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        assert!(order.lock().unwrap().is_empty());
+        drop(first);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), vec![0, 1, 2]);
+        // The last waiter released the emulator
+        tokio::time::timeout(Duration::from_secs(1), queue.acquire("a"))
+            .await
+            .expect("the emulator is free again");
+    }
+
+    #[test]
+    async fn test_different_emulators_run_in_parallel() {
+        let queue = OperationQueue::new();
+        let _a = queue.acquire("a").await;
+        let _b = tokio::time::timeout(Duration::from_secs(1), queue.acquire("b"))
+            .await
+            .expect("another emulator's operation does not block");
+        assert!(tokio::time::timeout(Duration::from_millis(50), queue.acquire("a"))
+            .await
+            .is_err());
+    }
+}
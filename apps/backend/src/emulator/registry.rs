@@ -0,0 +1,208 @@
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex as StdMutex};
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+
+use super::app_manager::AppManager;
+use super::runner::{CommandPolicy, SharedRunner};
+use super::supervisor::{ExitRecord, LogBuffer};
+
+/// Runtime details of a supervised emulator process
+#[derive(Debug, Default)]
+struct ProcessInfo {
+    pid: Option<u32>,
+    restarts: u32,
+    last_exit: Option<ExitRecord>,
+}
+
+/// Handle to an emulator process owned by its supervisor task
+#[derive(Debug, Clone)]
+pub struct ProcessHandle {
+    kill: CancellationToken,
+    info: Arc<StdMutex<ProcessInfo>>,
+    logs: LogBuffer,
+}
+
+impl ProcessHandle {
+    pub fn new(pid: Option<u32>, log_lines: usize) -> Self {
+        Self {
+            kill: CancellationToken::new(),
+            info: Arc::new(StdMutex::new(ProcessInfo {
+                pid,
+                ..Default::default()
+            })),
+            logs: LogBuffer::new(log_lines),
+        }
+    }
+
+    /// OS process id of the current emulator process, if known
+    pub fn pid(&self) -> Option<u32> {
+        self.info.lock().unwrap().pid
+    }
+
+    pub fn set_pid(&self, pid: Option<u32>) {
+        self.info.lock().unwrap().pid = pid;
+    }
+
+    /// Number of times the supervisor restarted the emulator
+    pub fn restarts(&self) -> u32 {
+        self.info.lock().unwrap().restarts
+    }
+
+    pub fn record_restart(&self) {
+        self.info.lock().unwrap().restarts += 1;
+    }
+
+    /// How the most recent emulator process ended
+    pub fn last_exit(&self) -> Option<ExitRecord> {
+        self.info.lock().unwrap().last_exit.clone()
+    }
+
+    pub fn record_exit(&self, status: ExitStatus) {
+        self.info.lock().unwrap().last_exit = Some(status.into());
+    }
+
+    /// Recent output of the emulator process
+    pub fn logs(&self) -> &LogBuffer {
+        &self.logs
+    }
+
+    /// Ask the supervisor to shut the emulator process down
+    pub fn kill(&self) {
+        self.kill.cancel();
+    }
+
+    /// Resolves once `kill` has been called
+    pub async fn killed(&self) {
+        self.kill.cancelled().await
+    }
+
+    /// Whether both handles refer to the same supervised process
+    pub fn same_as(&self, other: &ProcessHandle) -> bool {
+        Arc::ptr_eq(&self.info, &other.info)
+    }
+}
+
+/// A live emulator launched by this backend
+#[derive(Debug, Clone)]
+pub struct EmulatorInstance {
+    pub name: String,
+    pub serial: String,
+    pub process: Option<ProcessHandle>,
+    pub app_manager: AppManager,
+    pub started_at: DateTime<Utc>,
+}
+
+impl EmulatorInstance {
+    pub fn new(name: String, serial: String, process: Option<ProcessHandle>) -> Self {
+        Self {
+            app_manager: AppManager::new(serial.clone()),
+            name,
+            serial,
+            process,
+            started_at: Utc::now(),
+        }
+    }
+
+    /// Run the instance's adb commands through `runner` with the given timeouts and retries
+    pub fn with_commands(mut self, runner: SharedRunner, policy: CommandPolicy) -> Self {
+        self.app_manager = self.app_manager.with_runner(runner).with_policy(policy);
+        self
+    }
+}
+
+/// Thread-safe registry of live emulator instances, keyed by emulator name
+#[derive(Debug, Default, Clone)]
+pub struct EmulatorRegistry(Arc<Mutex<HashMap<String, EmulatorInstance>>>);
+
+impl EmulatorRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register an instance, replacing any previous entry with the same name
+    pub async fn insert(&self, instance: EmulatorInstance) {
+        let mut instances = self.0.lock().await;
+        instances.insert(instance.name.clone(), instance);
+    }
+
+    pub async fn get(&self, name: &str) -> Option<EmulatorInstance> {
+        let instances = self.0.lock().await;
+        instances.get(name).cloned()
+    }
+
+    pub async fn remove(&self, name: &str) -> Option<EmulatorInstance> {
+        let mut instances = self.0.lock().await;
+        instances.remove(name)
+    }
+
+    /// Remove an instance, but only if it is still supervised by `process`
+    pub async fn remove_process(&self, name: &str, process: &ProcessHandle) -> bool {
+        let mut instances = self.0.lock().await;
+        let owned = instances
+            .get(name)
+            .and_then(|instance| instance.process.as_ref())
+            .is_some_and(|current| current.same_as(process));
+        if owned {
+            instances.remove(name);
+        }
+        owned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    #[test]
+    async fn test_registry_tracks_instances() {
+        let registry = EmulatorRegistry::new();
+        let process = ProcessHandle::new(Some(42), 10);
+        registry
+            .insert(EmulatorInstance::new(
+                "avd".to_string(),
+                "emulator-5554".to_string(),
+                Some(process),
+            ))
+            .await;
+
+        let instance = registry.get("avd").await.unwrap();
+        assert_eq!(instance.serial, "emulator-5554");
+        assert_eq!(instance.process.unwrap().pid(), Some(42));
+
+        assert!(registry.remove("avd").await.is_some());
+        assert!(registry.get("avd").await.is_none());
+    }
+
+    #[test]
+    async fn test_remove_process_ignores_newer_instance() {
+        let registry = EmulatorRegistry::new();
+        let old = ProcessHandle::new(None, 10);
+        let new = ProcessHandle::new(None, 10);
+        registry
+            .insert(EmulatorInstance::new(
+                "avd".to_string(),
+                "emulator-5554".to_string(),
+                Some(new.clone()),
+            ))
+            .await;
+
+        assert!(!registry.remove_process("avd", &old).await);
+        assert!(registry.get("avd").await.is_some());
+        assert!(registry.remove_process("avd", &new).await);
+        assert!(registry.get("avd").await.is_none());
+    }
+
+    #[test]
+    async fn test_process_handle_kill_is_shared() {
+        let process = ProcessHandle::new(None, 10);
+        let watcher = process.clone();
+        process.kill();
+        tokio::time::timeout(std::time::Duration::from_secs(1), watcher.killed())
+            .await
+            .unwrap();
+    }
+}
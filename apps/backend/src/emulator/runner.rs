@@ -0,0 +1,762 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use log::warn;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpSocket, TcpStream};
+use tokio::process::{Child, Command as TokioCommand};
+
+use crate::config::EmulatorSettings;
+use super::adb_client::{self, SHELL_EXIT, SHELL_STDERR, SHELL_STDOUT};
+use super::shell;
+
+/// adb errors that usually go away when the command is repeated
+const TRANSIENT_ERRORS: &[&str] = &[
+    "device offline",
+    "error: closed",
+    "device still connecting",
+    "device still authorizing",
+    "protocol fault",
+];
+
+/// An external command to run, such as `adb` or `emulator`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandSpec {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Bytes written to the command's stdin; stdin is closed when `None`
+    pub stdin: Option<Vec<u8>>,
+}
+
+impl CommandSpec {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            stdin: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn stdin(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    fn to_command(&self) -> TokioCommand {
+        let mut command = TokioCommand::new(&self.program);
+        command
+            .args(&self.args)
+            .stdin(if self.stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        command
+    }
+}
+
+impl fmt::Display for CommandSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.program)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Captured result of a finished command
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// Exit code, or `None` if the command was killed by a signal
+    pub code: Option<i32>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl CommandOutput {
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    pub fn stdout_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stdout).into_owned()
+    }
+
+    pub fn stderr_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.stderr).into_owned()
+    }
+
+    /// Whether the command failed with an error that is worth retrying
+    pub fn is_transient_failure(&self) -> bool {
+        if self.success() {
+            return false;
+        }
+        is_transient_error(&self.stderr_lossy()) || is_transient_error(&self.stdout_lossy())
+    }
+}
+
+/// Whether an adb error message describes a failure worth retrying
+pub fn is_transient_error(message: &str) -> bool {
+    TRANSIENT_ERRORS.iter().any(|error| message.contains(error))
+}
+
+pub type OutputFuture<'a> = Pin<Box<dyn Future<Output = io::Result<CommandOutput>> + Send + 'a>>;
+
+/// Runs the external tools the emulator layer depends on.
+///
+/// [`SystemRunner`] runs real processes; [`FakeRunner`] lets tests script the
+/// tools' behaviour without an Android SDK.
+pub trait CommandRunner: Send + Sync + fmt::Debug {
+    /// Run a command to completion and capture its output
+    fn output<'a>(&'a self, command: &'a CommandSpec) -> OutputFuture<'a>;
+
+    /// Start a long running command with piped stdout and stderr
+    fn spawn(&self, command: &CommandSpec) -> io::Result<Child>;
+
+    /// Address of the adb server that device requests are sent to
+    fn adb_server(&self) -> SocketAddr {
+        adb_client::default_server_addr()
+    }
+}
+
+pub type SharedRunner = Arc<dyn CommandRunner>;
+
+/// Runs commands as real child processes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl SystemRunner {
+    pub fn shared() -> SharedRunner {
+        Arc::new(SystemRunner)
+    }
+}
+
+impl CommandRunner for SystemRunner {
+    fn output<'a>(&'a self, command: &'a CommandSpec) -> OutputFuture<'a> {
+        Box::pin(async move {
+            let mut child = command.to_command().spawn()?;
+            if let (Some(input), Some(mut stdin)) = (&command.stdin, child.stdin.take()) {
+                // The command may exit without reading all of its input
+                let _ = stdin.write_all(input).await;
+            }
+            let output = child.wait_with_output().await?;
+            Ok(CommandOutput {
+                code: output.status.code(),
+                stdout: output.stdout,
+                stderr: output.stderr,
+            })
+        })
+    }
+
+    fn spawn(&self, command: &CommandSpec) -> io::Result<Child> {
+        let mut process = command.to_command();
+        // Long running processes are owned by their supervisor, not by a handle
+        process.kill_on_drop(false);
+        process.spawn()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("{program} is not installed or not on PATH")]
+    NotFound { program: String },
+    #[error("Failed to run {command}: {source}")]
+    Io { command: String, source: io::Error },
+    #[error("{command} timed out after {after:?}")]
+    Timeout { command: String, after: Duration },
+}
+
+/// Timeout and retry behaviour for device commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CommandPolicy {
+    /// Time allowed for a single attempt; the command is killed when it expires
+    pub timeout: Duration,
+    /// Time allowed for slow operations such as installs and pushes
+    pub install_timeout: Duration,
+    /// Extra attempts for commands that fail with a transient adb error
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every further retry
+    pub backoff: Duration,
+}
+
+impl Default for CommandPolicy {
+    fn default() -> Self {
+        EmulatorSettings::default().into()
+    }
+}
+
+impl From<EmulatorSettings> for CommandPolicy {
+    fn from(settings: EmulatorSettings) -> Self {
+        Self {
+            timeout: Duration::from_secs(settings.command_timeout_secs),
+            install_timeout: Duration::from_secs(settings.install_timeout_secs),
+            retries: settings.command_retries,
+            backoff: Duration::from_millis(settings.command_retry_backoff_ms),
+        }
+    }
+}
+
+impl CommandPolicy {
+    /// The same policy with a different per-attempt timeout
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// The policy for app installations
+    pub fn for_install(self) -> Self {
+        self.with_timeout(self.install_timeout)
+    }
+
+    /// Delay before retry number `attempt`, counting from zero
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(2u32.saturating_pow(attempt))
+    }
+
+    /// Run a command, killing it if an attempt times out and retrying it with
+    /// exponential backoff while it fails with a transient adb error.
+    ///
+    /// Timed out commands are not retried. The output of the last attempt is
+    /// returned whether or not the command succeeded.
+    pub async fn run(
+        &self,
+        runner: &dyn CommandRunner,
+        command: &CommandSpec,
+    ) -> Result<CommandOutput, CommandError> {
+        let mut attempt = 0;
+        loop {
+            // Dropping the future on timeout kills the child process
+            let output = match tokio::time::timeout(self.timeout, runner.output(command)).await {
+                Ok(Ok(output)) => output,
+                Ok(Err(source)) if source.kind() == io::ErrorKind::NotFound => {
+                    return Err(CommandError::NotFound {
+                        program: command.program.clone(),
+                    })
+                }
+                Ok(Err(source)) => {
+                    return Err(CommandError::Io {
+                        command: command.to_string(),
+                        source,
+                    })
+                }
+                Err(_) => {
+                    return Err(CommandError::Timeout {
+                        command: command.to_string(),
+                        after: self.timeout,
+                    })
+                }
+            };
+
+            if attempt >= self.retries || !output.is_transient_failure() {
+                return Ok(output);
+            }
+            let delay = self.backoff_for(attempt);
+            attempt += 1;
+            warn!(
+                "{} failed ({}), retrying in {:?}",
+                command,
+                output.stderr_lossy().trim(),
+                delay
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+}
+
+/// Canned behaviour of a command run by a [`FakeRunner`]
+#[derive(Debug, Clone, Default)]
+pub struct FakeResponse {
+    pub code: i32,
+    pub stdout: String,
+    pub stderr: String,
+    /// How long a spawned process keeps running before it exits
+    pub run_for: Duration,
+    /// Fail to start the command, as if the program were not installed
+    pub missing: bool,
+}
+
+impl FakeResponse {
+    /// Exit successfully with the given stdout
+    pub fn ok(stdout: impl Into<String>) -> Self {
+        Self {
+            stdout: stdout.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Exit with `code` and the given stderr
+    pub fn fail(code: i32, stderr: impl Into<String>) -> Self {
+        Self {
+            code,
+            stderr: stderr.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Fail to start, as if the program were not installed
+    pub fn missing() -> Self {
+        Self {
+            missing: true,
+            ..Default::default()
+        }
+    }
+
+    /// Keep a spawned process alive for `duration` before it exits
+    pub fn running_for(mut self, duration: Duration) -> Self {
+        self.run_for = duration;
+        self
+    }
+
+    fn not_found(command: &CommandSpec) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{}: command not found", command.program),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    /// Scripted responses, most recently added first
+    rules: VecDeque<(String, Vec<String>, FakeResponse)>,
+    calls: Vec<CommandSpec>,
+    /// Address of the fake adb server once it is in use
+    adb_server: Option<SocketAddr>,
+    /// Whether the fake adb server waits for `adb start-server` before it listens
+    adb_stopped: bool,
+    /// Socket of a fake adb server that does not listen yet
+    adb_socket: Option<TcpSocket>,
+}
+
+/// Scriptable [`CommandRunner`] for tests.
+///
+/// Records every command it is asked to run and answers with the response of the
+/// most recently added rule whose program matches and whose arguments start with
+/// the rule's arguments. Commands without a matching rule succeed with no output.
+/// Spawned commands are real `sh` processes that print the canned output.
+///
+/// Requests to its adb server are served in-process: each one is recorded and
+/// answered as the adb command line that does the same: `host:devices` is
+/// `adb devices`, and on a device `shell` is `adb -s <serial> shell <command>`,
+/// `exec` is `adb -s <serial> exec-out <command>` and a sync push is
+/// `adb -s <serial> push <remote path>`. A failing response whose stderr reads
+/// `error: <message>` fails the request with that message, as the adb server would.
+#[derive(Debug, Default, Clone)]
+pub struct FakeRunner {
+    state: Arc<Mutex<FakeState>>,
+}
+
+impl FakeRunner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Answer commands of `program` whose arguments start with `args` with `response`
+    pub fn on(&self, program: &str, args: &[&str], response: FakeResponse) -> &Self {
+        self.state.lock().unwrap().rules.push_front((
+            program.to_string(),
+            args.iter().map(|arg| arg.to_string()).collect(),
+            response,
+        ));
+        self
+    }
+
+    /// Every command run so far, in order
+    pub fn calls(&self) -> Vec<CommandSpec> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Forget the commands run so far
+    pub fn clear_calls(&self) {
+        self.state.lock().unwrap().calls.clear();
+    }
+
+    /// Commands run so far, rendered as command lines
+    pub fn command_lines(&self) -> Vec<String> {
+        self.calls().iter().map(ToString::to_string).collect()
+    }
+
+    /// Refuse connections to the adb server until `adb start-server` succeeds, as if
+    /// it were not running yet. Must be called before the server is first used.
+    pub fn stop_adb_server(&self) -> &Self {
+        let mut state = self.state.lock().unwrap();
+        assert!(state.adb_server.is_none(), "the fake adb server is already in use");
+        state.adb_stopped = true;
+        self
+    }
+
+    /// Accept connections to the fake adb server on `socket`
+    fn listen(&self, socket: TcpSocket) {
+        let listener = match socket.listen(16) {
+            Ok(listener) => listener,
+            Err(e) => return warn!("Fake adb server cannot listen: {}", e),
+        };
+        let runner = self.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(runner.clone().serve_adb(stream));
+            }
+        });
+    }
+
+    /// Serve one connection to the fake adb server
+    async fn serve_adb(self, mut stream: TcpStream) -> io::Result<()> {
+        let request = read_adb_request(&mut stream).await?;
+        if request == "host:devices" {
+            let output = self.output(&CommandSpec::new("adb").arg("devices")).await?;
+            if !output.success() {
+                return write_adb_failure(&mut stream, &output.stderr_lossy()).await;
+            }
+            let listing = output.stdout_lossy();
+            let reply = format!("OKAY{:04x}{}", listing.len(), listing);
+            return stream.write_all(reply.as_bytes()).await;
+        }
+        let Some(serial) = request.strip_prefix("host:transport:") else {
+            return write_adb_failure(&mut stream, "unknown host service").await;
+        };
+        stream.write_all(b"OKAY").await?;
+
+        let request = read_adb_request(&mut stream).await?;
+        let device = CommandSpec::new("adb").args(["-s", serial]);
+        if request == "sync:" {
+            stream.write_all(b"OKAY").await?;
+            return self.serve_sync(device, stream).await;
+        }
+        let (shell, command) = if let Some(command) = request.strip_prefix("shell,v2,raw:") {
+            (true, device.args(["shell", command]))
+        } else if let Some(command) = request.strip_prefix("exec:") {
+            (false, device.args(["exec-out", command]))
+        } else {
+            return write_adb_failure(&mut stream, "unknown service").await;
+        };
+
+        let output = self.output(&command).await?;
+        // adb prints a failure of the server as `error: <message>`
+        let stderr = output.stderr_lossy();
+        if let (false, Some(message)) = (output.success(), stderr.trim().strip_prefix("error: ")) {
+            return write_adb_failure(&mut stream, message).await;
+        }
+        stream.write_all(b"OKAY").await?;
+
+        if shell {
+            for (id, data) in [(SHELL_STDOUT, &output.stdout), (SHELL_STDERR, &output.stderr)] {
+                if !data.is_empty() {
+                    stream.write_all(&[id]).await?;
+                    stream.write_all(&(data.len() as u32).to_le_bytes()).await?;
+                    stream.write_all(data).await?;
+                }
+            }
+            let code = output.code.unwrap_or(255) as u8;
+            stream.write_all(&[SHELL_EXIT, 1, 0, 0, 0, code]).await
+        } else {
+            // Like the package manager, read as much input as `-S <size>` announces
+            let size = command.args[3]
+                .split_whitespace()
+                .skip_while(|word| *word != "-S")
+                .nth(1)
+                .and_then(|size| size.parse().ok())
+                .unwrap_or(0);
+            let mut input = vec![0; size];
+            stream.read_exact(&mut input).await?;
+            stream.write_all(&output.stdout).await?;
+            stream.write_all(&output.stderr).await
+        }
+    }
+
+    /// Serve the file pushes of a `sync:` session, each one answered as
+    /// `adb -s <serial> push <remote path>` with the file's content as input
+    async fn serve_sync(&self, device: CommandSpec, mut stream: TcpStream) -> io::Result<()> {
+        loop {
+            let (id, target) = read_sync_packet(&mut stream).await?;
+            if &id != b"SEND" {
+                // QUIT, or a request the fake does not support
+                return Ok(());
+            }
+            let target = String::from_utf8_lossy(&target).into_owned();
+            let path = target.rsplit_once(',').map_or(target.as_str(), |(path, _mode)| path);
+
+            let mut data = Vec::new();
+            loop {
+                let (id, chunk) = read_sync_packet(&mut stream).await?;
+                if &id == b"DONE" {
+                    break;
+                }
+                data.extend_from_slice(&chunk);
+            }
+
+            let output = self.output(&device.clone().args(["push", path]).stdin(data)).await?;
+            if output.success() {
+                stream.write_all(b"OKAY\0\0\0\0").await?;
+            } else {
+                stream.write_all(b"FAIL").await?;
+                stream.write_all(&(output.stderr.len() as u32).to_le_bytes()).await?;
+                stream.write_all(&output.stderr).await?;
+            }
+        }
+    }
+
+    fn respond(&self, command: &CommandSpec) -> FakeResponse {
+        let mut state = self.state.lock().unwrap();
+        state.calls.push(command.clone());
+        state
+            .rules
+            .iter()
+            .find(|(program, args, _)| {
+                *program == command.program && command.args.starts_with(args)
+            })
+            .map(|(_, _, response)| response.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Read a request to the adb server, prefixed with its length as four hex digits
+async fn read_adb_request(stream: &mut TcpStream) -> io::Result<String> {
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    let len = std::str::from_utf8(&len)
+        .ok()
+        .and_then(|len| usize::from_str_radix(len, 16).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid request length"))?;
+    let mut request = vec![0; len];
+    stream.read_exact(&mut request).await?;
+    Ok(String::from_utf8_lossy(&request).into_owned())
+}
+
+/// Read a sync packet: a four letter id and a payload prefixed with its
+/// little-endian length. The length of `DONE` is a timestamp, not a payload.
+async fn read_sync_packet(stream: &mut TcpStream) -> io::Result<([u8; 4], Vec<u8>)> {
+    let mut id = [0; 4];
+    stream.read_exact(&mut id).await?;
+    let mut len = [0; 4];
+    stream.read_exact(&mut len).await?;
+    if &id == b"DONE" {
+        return Ok((id, Vec::new()));
+    }
+    let mut payload = vec![0; u32::from_le_bytes(len) as usize];
+    stream.read_exact(&mut payload).await?;
+    Ok((id, payload))
+}
+
+async fn write_adb_failure(stream: &mut TcpStream, message: &str) -> io::Result<()> {
+    let reply = format!("FAIL{:04x}{}", message.len(), message);
+    stream.write_all(reply.as_bytes()).await
+}
+
+impl CommandRunner for FakeRunner {
+    fn output<'a>(&'a self, command: &'a CommandSpec) -> OutputFuture<'a> {
+        let response = self.respond(command);
+        Box::pin(async move {
+            if response.missing {
+                return Err(FakeResponse::not_found(command));
+            }
+            tokio::time::sleep(response.run_for).await;
+            if response.code == 0 && command.program == "adb" && command.args == ["start-server"] {
+                let socket = self.state.lock().unwrap().adb_socket.take();
+                if let Some(socket) = socket {
+                    self.listen(socket);
+                }
+            }
+            Ok(CommandOutput {
+                code: Some(response.code),
+                stdout: response.stdout.into_bytes(),
+                stderr: response.stderr.into_bytes(),
+            })
+        })
+    }
+
+    fn spawn(&self, command: &CommandSpec) -> io::Result<Child> {
+        let response = self.respond(command);
+        if response.missing {
+            return Err(FakeResponse::not_found(command));
+        }
+        let script = format!(
+            "printf '%s' {}; printf '%s' {} >&2; sleep {}; exit {}",
+            shell::quote(&response.stdout),
+            shell::quote(&response.stderr),
+            response.run_for.as_secs_f64(),
+            response.code
+        );
+        TokioCommand::new("sh")
+            .arg("-c")
+            .arg(script)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+    }
+
+    fn adb_server(&self) -> SocketAddr {
+        let mut state = self.state.lock().unwrap();
+        if let Some(addr) = state.adb_server {
+            return addr;
+        }
+
+        // A bound socket refuses connections until it listens
+        let socket = TcpSocket::new_v4()
+            .and_then(|socket| {
+                socket.bind(SocketAddr::from(([127, 0, 0, 1], 0)))?;
+                Ok(socket)
+            })
+            .expect("bind the fake adb server");
+        let addr = socket.local_addr().expect("address of the fake adb server");
+        state.adb_server = Some(addr);
+        if state.adb_stopped {
+            state.adb_socket = Some(socket);
+        } else {
+            self.listen(socket);
+        }
+        addr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::test;
+
+    #[test]
+    async fn test_fake_runner_matches_latest_rule() {
+        let runner = FakeRunner::new();
+        runner
+            .on("adb", &["-s"], FakeResponse::ok("generic"))
+            .on("adb", &["-s", "emulator-5554", "shell"], FakeResponse::fail(1, "boom"));
+
+        let shell = CommandSpec::new("adb").args(["-s", "emulator-5554", "shell", "true"]);
+        let output = runner.output(&shell).await.unwrap();
+        assert!(!output.success());
+        assert_eq!(output.stderr_lossy(), "boom");
+
+        let install = CommandSpec::new("adb").args(["-s", "emulator-5554", "install"]);
+        assert_eq!(runner.output(&install).await.unwrap().stdout_lossy(), "generic");
+
+        let other = CommandSpec::new("emulator").arg("-version");
+        assert!(runner.output(&other).await.unwrap().success());
+        assert_eq!(runner.calls().len(), 3);
+        assert_eq!(runner.command_lines()[2], "emulator -version");
+    }
+
+    #[test]
+    async fn test_fake_runner_spawn() {
+        let runner = FakeRunner::new();
+        runner.on("emulator", &[], FakeResponse::fail(3, "it's broken"));
+
+        let child = runner.spawn(&CommandSpec::new("emulator").arg("-avd")).unwrap();
+        let output = child.wait_with_output().await.unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "it's broken");
+    }
+
+    #[test]
+    async fn test_fake_adb_server() {
+        use crate::emulator::adb_client::{AdbClient, AdbClientError};
+
+        let runner = FakeRunner::new();
+        runner
+            .on("adb", &["devices"], FakeResponse::ok("List of devices attached\nemulator-5554\tdevice\n"))
+            .on("adb", &["-s", "emulator-5554", "shell"], FakeResponse::fail(2, "no such file"));
+        let client = AdbClient::for_runner(&runner, CommandPolicy::default());
+
+        let devices = client.devices().await.unwrap();
+        assert_eq!(devices.len(), 1);
+        assert!(devices[0].is_online());
+
+        let output = client.shell("emulator-5554", "cat /nope").await.unwrap();
+        assert_eq!(output.code, Some(2));
+        assert_eq!(output.stderr_lossy(), "no such file");
+        assert_eq!(
+            runner.command_lines(),
+            vec!["adb devices", "adb -s emulator-5554 shell cat /nope"]
+        );
+
+        // A stopped server refuses connections until it is started
+        let runner = FakeRunner::new();
+        runner.stop_adb_server();
+        let client = AdbClient::for_runner(&runner, CommandPolicy::default());
+        assert!(matches!(client.devices().await, Err(AdbClientError::ServerUnavailable(_))));
+        runner.output(&CommandSpec::new("adb").arg("start-server")).await.unwrap();
+        assert!(client.devices().await.unwrap().is_empty());
+    }
+
+    fn quick_policy() -> CommandPolicy {
+        CommandPolicy {
+            timeout: Duration::from_millis(200),
+            install_timeout: Duration::from_secs(1),
+            retries: 2,
+            backoff: Duration::from_millis(1),
+        }
+    }
+
+    #[test]
+    async fn test_policy_retries_transient_failures() {
+        let runner = FakeRunner::new();
+        runner.on("adb", &[], FakeResponse::fail(1, "error: device offline"));
+        let command = CommandSpec::new("adb").args(["-s", "emulator-5554", "shell", "true"]);
+
+        let output = quick_policy().run(&runner, &command).await.unwrap();
+        assert!(!output.success());
+        assert_eq!(runner.calls().len(), 3);
+
+        // Permanent failures are returned right away
+        runner.on("adb", &[], FakeResponse::fail(1, "error: unknown command"));
+        quick_policy().run(&runner, &command).await.unwrap();
+        assert_eq!(runner.calls().len(), 4);
+    }
+
+    #[test]
+    async fn test_policy_timeout() {
+        let runner = FakeRunner::new();
+        runner.on("adb", &[], FakeResponse::ok("").running_for(Duration::from_secs(10)));
+        let command = CommandSpec::new("adb").arg("install");
+
+        let err = quick_policy().run(&runner, &command).await.unwrap_err();
+        assert!(matches!(err, CommandError::Timeout { after, .. } if after == Duration::from_millis(200)));
+        assert_eq!(runner.calls().len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    async fn test_policy_timeout_kills_process() {
+        let marker = std::env::temp_dir().join(format!("runner-timeout-{}", std::process::id()));
+        let _ = std::fs::remove_file(&marker);
+        let script = format!("sleep 1; touch {}", marker.display());
+        let command = CommandSpec::new("sh").args(["-c", &script]);
+
+        let policy = quick_policy().with_timeout(Duration::from_millis(100));
+        assert!(policy.run(&SystemRunner, &command).await.is_err());
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    async fn test_system_runner_stdin() {
+        let command = CommandSpec::new("sh").args(["-c", "cat; exit 2"]).stdin("no\n");
+        let output = SystemRunner.output(&command).await.unwrap();
+        assert_eq!(output.code, Some(2));
+        assert_eq!(output.stdout_lossy(), "no\n");
+    }
+}
@@ -0,0 +1,52 @@
+use std::borrow::Cow;
+
+/// Characters that never need quoting in a POSIX shell word
+fn is_safe(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | ':' | '=' | '@' | '%' | '+' | ',')
+}
+
+/// Quote a single argument for the device's `sh`.
+///
+/// Arguments made of safe characters are passed as they are; anything else is
+/// wrapped in single quotes, which the shell does not interpret at all.
+pub fn quote(arg: &str) -> Cow<'_, str> {
+    if !arg.is_empty() && arg.chars().all(is_safe) {
+        Cow::Borrowed(arg)
+    } else {
+        Cow::Owned(format!("'{}'", arg.replace('\'', r"'\''")))
+    }
+}
+
+/// Build a command line for `adb shell` in which every argument stays a single word
+pub fn join<I, S>(args: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    args.into_iter()
+        .map(|arg| quote(arg.as_ref()).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("com.example/.MainActivity"), "com.example/.MainActivity");
+        assert_eq!(quote(""), "''");
+        assert_eq!(quote("x; rm -rf /sdcard"), "'x; rm -rf /sdcard'");
+        assert_eq!(quote("it's"), r"'it'\''s'");
+        assert_eq!(quote("$(reboot)"), "'$(reboot)'");
+    }
+
+    #[test]
+    fn test_join() {
+        assert_eq!(
+            join(["am", "force-stop", "a|b"]),
+            "am force-stop 'a|b'"
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use std::time::Duration;
+
+use super::avd;
+use super::EmulatorError;
+
+/// How long to wait for the console to save, load or delete a snapshot
+pub const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Check that a snapshot name is safe to use as a console argument and directory name
+pub fn validate_snapshot_name(name: &str) -> Result<(), EmulatorError> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        && !name.starts_with(['.', '-']);
+    if valid {
+        Ok(())
+    } else {
+        Err(EmulatorError::InvalidSnapshotName(name.to_string()))
+    }
+}
+
+/// Parse the snapshot names from the reply to `avd snapshot list`
+pub fn parse_snapshot_list<S: AsRef<str>>(lines: &[S]) -> Vec<String> {
+    lines
+        .iter()
+        .map(|line| line.as_ref().trim())
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let id = fields.next()?;
+            let tag = fields.next()?;
+            (id == "--" || id.chars().all(|c| c.is_ascii_digit())).then(|| tag.to_string())
+        })
+        .collect()
+}
+
+/// Directory holding a snapshot of an AVD
+pub fn snapshot_dir(avd_name: &str, name: &str) -> PathBuf {
+    avd::avd_dir(avd_name).join("snapshots").join(name)
+}
+
+/// Total size of the files in a directory tree
+pub fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        size += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_snapshot_list() {
+        let output = "List of snapshots present on all disks:\n\
+                      ID        TAG                 VM SIZE                DATE       VM CLOCK\n\
+                      --        default_boot         142M 2024-05-02 10:11:12   00:01:02.345\n\
+                      --        clean-login          150M 2024-05-02 11:00:00   00:03:00.000\n\
+                      OK\n";
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(parse_snapshot_list(&lines), vec!["default_boot", "clean-login"]);
+    }
+
+    #[test]
+    fn test_validate_snapshot_name() {
+        assert!(validate_snapshot_name("clean-login_v2").is_ok());
+        assert!(validate_snapshot_name("../default_boot").is_err());
+        assert!(validate_snapshot_name("a b").is_err());
+    }
+}
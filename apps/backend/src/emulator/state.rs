@@ -0,0 +1,105 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Lifecycle state of an emulator, persisted in the `emulators` table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum EmulatorState {
+    /// Registered but never started
+    Created,
+    /// Process spawned, waiting for the device to finish booting
+    Booting,
+    /// Fully booted and usable
+    Running,
+    /// A stop has been requested and is in progress
+    Stopping,
+    /// Shut down cleanly
+    Stopped,
+    /// Failed to boot, crashed or could not be stopped
+    Failed,
+}
+
+impl EmulatorState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmulatorState::Created => "created",
+            EmulatorState::Booting => "booting",
+            EmulatorState::Running => "running",
+            EmulatorState::Stopping => "stopping",
+            EmulatorState::Stopped => "stopped",
+            EmulatorState::Failed => "failed",
+        }
+    }
+
+    /// Whether the emulator has (or may have) a live process
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            EmulatorState::Booting | EmulatorState::Running | EmulatorState::Stopping
+        )
+    }
+
+    /// Whether the state machine allows moving from `self` to `next`
+    pub fn can_transition_to(&self, next: EmulatorState) -> bool {
+        use EmulatorState::*;
+
+        matches!(
+            (self, next),
+            (Created | Stopped | Failed, Booting)
+                | (Booting, Running | Stopping | Failed)
+                | (Running, Stopping | Stopped | Failed)
+                | (Stopping, Stopped | Failed)
+                | (Failed, Stopping | Stopped)
+        )
+    }
+}
+
+impl FromStr for EmulatorState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "created" => Ok(EmulatorState::Created),
+            "booting" => Ok(EmulatorState::Booting),
+            "running" => Ok(EmulatorState::Running),
+            "stopping" => Ok(EmulatorState::Stopping),
+            "stopped" => Ok(EmulatorState::Stopped),
+            "failed" => Ok(EmulatorState::Failed),
+            other => Err(format!("unknown emulator state: {}", other)),
+        }
+    }
+}
+
+impl fmt::Display for EmulatorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EmulatorState::*;
+
+    #[test]
+    fn test_legal_lifecycle() {
+        let path = [
+            Created, Booting, Running, Stopping, Stopped, Booting, Failed, Booting, Failed,
+            Stopping, Stopped,
+        ];
+        for pair in path.windows(2) {
+            assert!(pair[0].can_transition_to(pair[1]), "{} -> {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn test_illegal_transitions() {
+        assert!(!Booting.can_transition_to(Booting));
+        assert!(!Running.can_transition_to(Booting));
+        assert!(!Stopping.can_transition_to(Booting));
+        assert!(!Created.can_transition_to(Running));
+        assert!(!Stopped.can_transition_to(Stopping));
+    }
+}
@@ -0,0 +1,419 @@
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Child;
+
+use super::boot::{self, BootConfig};
+use super::registry::{EmulatorRegistry, ProcessHandle};
+use super::{Emulator, EmulatorState};
+use crate::config::EmulatorSettings;
+use crate::db::EmulatorDb;
+
+/// Upper bound for the delay between restarts
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// How long an emulator has to stay up after booting for a crash to count as a
+/// fresh failure rather than part of a crash loop
+const STABLE_UPTIME: Duration = Duration::from_secs(60);
+
+fn default_backoff_ms() -> u64 {
+    1000
+}
+
+/// What to do when a supervised emulator process exits on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "kebab-case")]
+pub enum RestartPolicy {
+    /// Leave the emulator down
+    #[default]
+    Never,
+    /// Restart after a crash or failed boot, up to `max_retries` times in a row.
+    /// An emulator that stayed up for a while after booting starts a new streak.
+    OnFailure {
+        max_retries: u32,
+        #[serde(default = "default_backoff_ms")]
+        backoff_ms: u64,
+    },
+    /// Restart whenever the process exits, including clean shutdowns
+    Always {
+        #[serde(default = "default_backoff_ms")]
+        backoff_ms: u64,
+    },
+}
+
+impl RestartPolicy {
+    /// Delay before restart number `attempt` (starting at 1), or `None` if the policy
+    /// does not allow another restart
+    pub fn next_delay(&self, failed: bool, attempt: u32) -> Option<Duration> {
+        let backoff_ms = match *self {
+            RestartPolicy::Never => return None,
+            RestartPolicy::OnFailure { max_retries, backoff_ms } => {
+                if !failed || attempt > max_retries {
+                    return None;
+                }
+                backoff_ms
+            }
+            RestartPolicy::Always { backoff_ms } => backoff_ms,
+        };
+
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Some(Duration::from_millis(backoff_ms.saturating_mul(factor)).min(MAX_BACKOFF))
+    }
+}
+
+/// Tunables for supervising emulator processes
+#[derive(Debug, Clone, Copy)]
+pub struct SupervisorConfig {
+    /// Number of output lines kept per emulator
+    pub log_lines: usize,
+    /// How long a stopping emulator may take to exit before it is killed
+    pub stop_grace: Duration,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        EmulatorSettings::default().into()
+    }
+}
+
+impl From<EmulatorSettings> for SupervisorConfig {
+    fn from(settings: EmulatorSettings) -> Self {
+        Self {
+            log_lines: settings.log_buffer_lines,
+            stop_grace: Duration::from_secs(settings.stop_grace_secs),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A line of emulator output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub line: String,
+    pub at: DateTime<Utc>,
+}
+
+/// Ring buffer holding the most recent output lines of an emulator
+#[derive(Debug, Clone)]
+pub struct LogBuffer {
+    capacity: usize,
+    lines: Arc<Mutex<VecDeque<LogLine>>>,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+        }
+    }
+
+    pub fn push(&self, stream: LogStream, line: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut lines = self.lines.lock().unwrap();
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(LogLine {
+            stream,
+            line,
+            at: Utc::now(),
+        });
+    }
+
+    /// Copy of the buffered lines, oldest first
+    pub fn lines(&self) -> Vec<LogLine> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// How an emulator process ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExitRecord {
+    pub success: bool,
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub at: DateTime<Utc>,
+}
+
+impl From<ExitStatus> for ExitRecord {
+    fn from(status: ExitStatus) -> Self {
+        #[cfg(unix)]
+        let signal = std::os::unix::process::ExitStatusExt::signal(&status);
+        #[cfg(not(unix))]
+        let signal = None;
+
+        Self {
+            success: status.success(),
+            code: status.code(),
+            signal,
+            at: Utc::now(),
+        }
+    }
+}
+
+/// Forward every line of a child output stream into the log buffer
+fn capture<R>(stream: Option<R>, kind: LogStream, logs: LogBuffer)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    let Some(stream) = stream else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(stream).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            logs.push(kind, line);
+        }
+    });
+}
+
+/// Result of running the emulator process once
+enum Outcome {
+    /// A stop was requested through the process handle
+    Stopped,
+    /// The process did not boot or exited on its own
+    Exited {
+        failed: bool,
+        /// Whether the emulator ran long enough to end a streak of failures
+        stable: bool,
+        reason: String,
+    },
+}
+
+/// Watches an emulator process, records its output and exit status, and restarts it
+/// according to its restart policy.
+pub struct Supervisor {
+    pub emulator: Emulator,
+    pub policy: RestartPolicy,
+    pub process: ProcessHandle,
+    pub db: EmulatorDb,
+    pub registry: EmulatorRegistry,
+    pub boot_config: BootConfig,
+    pub config: SupervisorConfig,
+}
+
+impl Supervisor {
+    /// Supervise an already spawned emulator process until it is stopped or the
+    /// restart policy gives up
+    pub async fn run(mut self, mut child: Child) {
+        let name = self.emulator.name().to_string();
+        let mut attempt = 0;
+
+        loop {
+            self.process.set_pid(child.id());
+            capture(child.stdout.take(), LogStream::Stdout, self.process.logs().clone());
+            capture(child.stderr.take(), LogStream::Stderr, self.process.logs().clone());
+
+            let (failed, reason) = match self.run_once(&mut child).await {
+                Outcome::Stopped => return,
+                Outcome::Exited { failed, stable, reason } => {
+                    if stable || !failed {
+                        attempt = 0;
+                    }
+                    (failed, reason)
+                }
+            };
+            attempt += 1;
+
+            let Some(delay) = self.policy.next_delay(failed, attempt) else {
+                self.registry.remove_process(&name, &self.process).await;
+                return;
+            };
+
+            warn!("Restarting emulator {} in {:?} ({})", name, delay, reason);
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = self.process.killed() => return,
+            }
+
+            // Only restart if nobody stopped or restarted the emulator in the meantime
+            let current = match self.db.get_emulator(&name).await {
+                Ok(Some(config)) => config.state,
+                _ => return,
+            };
+            if !current.can_transition_to(EmulatorState::Booting)
+                || !self.move_state(current, EmulatorState::Booting, None).await
+            {
+                info!("Not restarting emulator {}, it is now {}", name, current);
+                return;
+            }
+
+            child = match self.emulator.start().await {
+                Ok(child) => child,
+                Err(e) => {
+                    error!("Failed to restart emulator {}: {}", name, e);
+                    let reason = e.to_string();
+                    self.move_state(EmulatorState::Booting, EmulatorState::Failed, Some(&reason))
+                        .await;
+                    self.registry.remove_process(&name, &self.process).await;
+                    return;
+                }
+            };
+            self.process.record_restart();
+        }
+    }
+
+    /// Wait for one process lifetime: boot, run, exit
+    async fn run_once(&self, child: &mut Child) -> Outcome {
+        let name = self.emulator.name();
+        let booted = tokio::select! {
+            booted = boot::wait_for_boot(&self.emulator, child, self.boot_config) => booted,
+            _ = self.process.killed() => return self.shutdown(child).await,
+        };
+        if let Err(e) = booted {
+            error!("Emulator {} failed to boot: {}", name, e);
+            // A hung emulator keeps running; kill it so a restart does not end up
+            // with two processes on the same console port
+            let status = match child.try_wait() {
+                Ok(Some(status)) => Ok(status),
+                _ => {
+                    let _ = child.kill().await;
+                    child.wait().await
+                }
+            };
+            if let Ok(status) = status {
+                self.process.record_exit(status);
+            }
+            let reason = e.to_string();
+            self.move_state(EmulatorState::Booting, EmulatorState::Failed, Some(&reason)).await;
+            return Outcome::Exited {
+                failed: true,
+                stable: false,
+                reason,
+            };
+        }
+        if !self.move_state(EmulatorState::Booting, EmulatorState::Running, None).await {
+            // Someone else took over the emulator while it was booting
+            return self.shutdown(child).await;
+        }
+
+        let booted_at = Instant::now();
+        let status = tokio::select! {
+            status = child.wait() => status,
+            _ = self.process.killed() => return self.shutdown(child).await,
+        };
+        let stable = booted_at.elapsed() >= STABLE_UPTIME;
+
+        match status {
+            Ok(status) => {
+                info!("Emulator {} exited ({})", name, status);
+                self.process.record_exit(status);
+                if status.success() {
+                    self.move_state(EmulatorState::Running, EmulatorState::Stopped, None).await;
+                    Outcome::Exited {
+                        failed: false,
+                        stable,
+                        reason: format!("exited ({})", status),
+                    }
+                } else {
+                    let reason = format!("emulator process crashed ({})", status);
+                    self.move_state(EmulatorState::Running, EmulatorState::Failed, Some(&reason))
+                        .await;
+                    Outcome::Exited {
+                        failed: true,
+                        stable,
+                        reason,
+                    }
+                }
+            }
+            Err(e) => {
+                let reason = format!("failed to wait for emulator process: {}", e);
+                self.move_state(EmulatorState::Running, EmulatorState::Failed, Some(&reason)).await;
+                Outcome::Exited {
+                    failed: true,
+                    stable,
+                    reason,
+                }
+            }
+        }
+    }
+
+    /// Give a stopping emulator time to exit on its own, then kill it
+    async fn shutdown(&self, child: &mut Child) -> Outcome {
+        let status = match tokio::time::timeout(self.config.stop_grace, child.wait()).await {
+            Ok(status) => status,
+            Err(_) => {
+                warn!("Emulator {} did not exit in time, killing it", self.emulator.name());
+                let _ = child.kill().await;
+                child.wait().await
+            }
+        };
+        if let Ok(status) = status {
+            self.process.record_exit(status);
+        }
+        Outcome::Stopped
+    }
+
+    /// Record a state change unless the emulator is no longer in state `from`
+    async fn move_state(
+        &self,
+        from: EmulatorState,
+        to: EmulatorState,
+        reason: Option<&str>,
+    ) -> bool {
+        match self.db.update_state(self.emulator.name(), from, to, reason).await {
+            Ok(moved) => moved,
+            Err(e) => {
+                error!("Failed to record state of emulator {}: {}", self.emulator.name(), e);
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_policy_delays() {
+        assert_eq!(RestartPolicy::Never.next_delay(true, 1), None);
+
+        let on_failure = RestartPolicy::OnFailure { max_retries: 2, backoff_ms: 100 };
+        assert_eq!(on_failure.next_delay(false, 1), None);
+        assert_eq!(on_failure.next_delay(true, 1), Some(Duration::from_millis(100)));
+        assert_eq!(on_failure.next_delay(true, 2), Some(Duration::from_millis(200)));
+        assert_eq!(on_failure.next_delay(true, 3), None);
+
+        let always = RestartPolicy::Always { backoff_ms: 1000 };
+        assert_eq!(always.next_delay(false, 1), Some(Duration::from_secs(1)));
+        assert_eq!(always.next_delay(true, 40), Some(MAX_BACKOFF));
+    }
+
+    #[test]
+    fn test_restart_policy_serde() {
+        let policy: RestartPolicy =
+            serde_json::from_str(r#"{"policy":"on-failure","max_retries":3}"#).unwrap();
+        assert_eq!(policy, RestartPolicy::OnFailure { max_retries: 3, backoff_ms: 1000 });
+        assert_eq!(
+            serde_json::to_value(RestartPolicy::Never).unwrap(),
+            serde_json::json!({"policy": "never"})
+        );
+    }
+
+    #[test]
+    fn test_log_buffer_keeps_last_lines() {
+        let logs = LogBuffer::new(2);
+        logs.push(LogStream::Stdout, "one".to_string());
+        logs.push(LogStream::Stderr, "two".to_string());
+        logs.push(LogStream::Stdout, "three".to_string());
+
+        let lines: Vec<_> = logs.lines().into_iter().map(|line| line.line).collect();
+        assert_eq!(lines, vec!["two", "three"]);
+    }
+}
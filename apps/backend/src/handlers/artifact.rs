@@ -0,0 +1,66 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse};
+use futures_util::TryStreamExt;
+
+use crate::artifacts::{ArtifactError, ArtifactStore};
+
+/// Upload an APK as the `file` field of a multipart form.
+///
+/// Answers 201 with the new artifact, or 200 with the existing one when the same
+/// content was uploaded before.
+async fn upload_apk(
+    store: web::Data<ArtifactStore>,
+    mut payload: Multipart,
+) -> Result<HttpResponse, ArtifactError> {
+    let invalid = |e: actix_multipart::MultipartError| ArtifactError::InvalidUpload(e.to_string());
+    while let Some(field) = payload.try_next().await.map_err(invalid)? {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let file_name = field
+            .content_disposition()
+            .and_then(|disposition| disposition.get_filename())
+            .unwrap_or("upload.apk")
+            .to_string();
+        let (record, created) = store.save(&file_name, field).await?;
+        return Ok(if created {
+            HttpResponse::Created().json(record)
+        } else {
+            HttpResponse::Ok().json(record)
+        });
+    }
+    Err(ArtifactError::InvalidUpload("missing the file field".to_string()))
+}
+
+/// List the uploaded APKs, newest first
+async fn list_apks(store: web::Data<ArtifactStore>) -> Result<HttpResponse, ArtifactError> {
+    Ok(HttpResponse::Ok().json(store.list().await?))
+}
+
+/// Get the metadata of an uploaded APK
+async fn get_apk(
+    store: web::Data<ArtifactStore>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, ArtifactError> {
+    Ok(HttpResponse::Ok().json(store.get(&id).await?))
+}
+
+/// Delete an uploaded APK
+async fn delete_apk(
+    store: web::Data<ArtifactStore>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, ArtifactError> {
+    store.delete(&id).await?;
+    Ok(HttpResponse::Ok().json(()))
+}
+
+/// Configure artifact API routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/artifacts/apks")
+            .route("", web::post().to(upload_apk))
+            .route("", web::get().to(list_apks))
+            .route("/{id}", web::get().to(get_apk))
+            .route("/{id}", web::delete().to(delete_apk)),
+    );
+}
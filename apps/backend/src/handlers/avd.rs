@@ -0,0 +1,65 @@
+use actix_web::{web, HttpResponse};
+
+use crate::emulator::avd::{self, AvdConfig, CreateAvdOptions};
+use crate::emulator::EmulatorError;
+
+use super::emulator::SharedEmulatorManager;
+
+/// List the AVDs available on the host
+async fn list_avds() -> Result<HttpResponse, EmulatorError> {
+    Ok(HttpResponse::Ok().json(avd::blocking(avd::list_avds).await?))
+}
+
+/// Create an AVD from an installed system image and device profile
+async fn create_avd(
+    manager: web::Data<SharedEmulatorManager>,
+    req: web::Json<CreateAvdOptions>,
+) -> Result<HttpResponse, EmulatorError> {
+    // Hold the emulator's queue so it cannot be started while its AVD is replaced
+    let _guard = manager.lock_emulator(&req.name).await;
+    // Never replace the AVD of an emulator that is currently using it
+    if req.force {
+        if let Some(emulator) = manager.get_emulator(&req.name).await {
+            if emulator.state().is_active() {
+                return Err(EmulatorError::StillActive {
+                    name: req.name.clone(),
+                    state: emulator.state(),
+                });
+            }
+        }
+    }
+
+    let info = avd::create_avd(manager.runner().as_ref(), manager.command_policy(), &req).await?;
+    Ok(HttpResponse::Created().json(info))
+}
+
+/// Get the configuration of an AVD
+async fn get_avd_config(name: web::Path<String>) -> Result<HttpResponse, EmulatorError> {
+    let name = name.into_inner();
+    Ok(HttpResponse::Ok().json(avd::blocking(move || avd::read_config(&name)).await?))
+}
+
+/// Patch the configuration of an AVD; changes apply on the next boot
+async fn update_avd_config(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+    patch: web::Json<AvdConfig>,
+) -> Result<HttpResponse, EmulatorError> {
+    let name = name.into_inner();
+    // Serialize with other edits and with create_avd, which rewrite the same config.ini
+    let _guard = manager.lock_emulator(&name).await;
+    let patch = patch.into_inner();
+    let config = avd::blocking(move || avd::update_config(&name, &patch)).await?;
+    Ok(HttpResponse::Ok().json(config))
+}
+
+/// Configure AVD provisioning API routes
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/avds")
+            .route("", web::get().to(list_avds))
+            .route("", web::post().to(create_avd))
+            .route("/{name}/config", web::get().to(get_avd_config))
+            .route("/{name}/config", web::patch().to(update_avd_config)),
+    );
+}
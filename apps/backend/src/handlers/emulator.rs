@@ -1,198 +1,496 @@
 use actix_web::{web, HttpResponse};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tokio::sync::Mutex;
 
-use crate::emulator::{EmulatorManager, EmulatorError};
+use crate::apk::bundle::{ChosenSplit, DeviceProfile};
+use crate::artifacts::{ArtifactStore, PreparedInstall};
+use crate::db::artifact::ArtifactRecord;
+use crate::emulator::app_manager::{AppError, InstallOptions};
+use crate::emulator::intent::Intent;
+use crate::emulator::launch::LaunchProfile;
+use crate::emulator::listing::{ListQuery, SortField, SortOrder};
+use crate::emulator::supervisor::{ExitRecord, LogLine, RestartPolicy};
+use crate::emulator::{DeleteOptions, Emulator, EmulatorManager, EmulatorError};
 
-pub type SharedEmulatorManager = Arc<Mutex<EmulatorManager>>;
+/// The emulator manager shared by all workers; it synchronizes internally, one
+/// emulator at a time
+pub type SharedEmulatorManager = Arc<EmulatorManager>;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateEmulatorRequest {
-    name: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub restart_policy: Option<RestartPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launch_profile: Option<LaunchProfile>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InstallAppRequest {
-    apk_path: String,
+    /// ID of an APK, `.apks` or `.xapk` uploaded to `/artifacts/apks`
+    pub artifact_id: String,
+    /// IDs of uploaded split APKs to install along with a base APK
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub split_artifact_ids: Vec<String>,
+    #[serde(default)]
+    pub options: InstallOptions,
 }
 
+/// What an installation put on the emulator
 #[derive(Debug, Serialize, Deserialize)]
+pub struct InstallAppResponse {
+    pub artifact: ArtifactRecord,
+    /// The profile the splits were chosen for, when there were splits to choose from
+    pub device: Option<DeviceProfile>,
+    pub splits: Vec<ChosenSplit>,
+    /// Configuration splits for other ABIs and densities
+    pub skipped_splits: Vec<String>,
+    /// Expansion files pushed to the app's OBB directory
+    pub obbs: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListAppsQuery {
+    /// Only system packages when true, only third-party packages when false
+    pub system: Option<bool>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct StartAppRequest {
-    package_name: String,
-    activity: String,
+    /// Defaults to the launcher activity the device resolves for the package, or
+    /// failing that the one of the uploaded APK last installed for it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub activity: Option<String>,
+    /// Wait for the activity to draw and report the launch timings (`am start -W`)
+    #[serde(default)]
+    pub wait: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmulatorResponse {
-    name: String,
-    port: u16,
-    adb_port: u16,
-    status: String,
+    pub name: String,
+    pub port: u16,
+    pub adb_port: u16,
+    pub status: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    #[serde(default)]
+    pub launch_profile: LaunchProfile,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ListEmulatorsQuery {
+    /// Comma-separated list of states to include
+    pub state: Option<String>,
+    /// Only include emulators whose name starts with this prefix
+    pub prefix: Option<String>,
+    pub sort: Option<SortField>,
+    pub order: Option<SortOrder>,
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
 }
 
-#[derive(Debug, Serialize)]
-pub struct ErrorResponse {
-    error: String,
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeleteEmulatorQuery {
+    #[serde(default)]
+    pub force: bool,
+    #[serde(default)]
+    pub wipe: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteEmulatorResponse {
+    pub name: String,
+    pub stopped: bool,
+    pub wiped: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmulatorLogsResponse {
+    pub name: String,
+    pub pid: Option<u32>,
+    pub restarts: u32,
+    pub last_exit: Option<ExitRecord>,
+    pub lines: Vec<LogLine>,
 }
 
-impl From<EmulatorError> for ErrorResponse {
-    fn from(error: EmulatorError) -> Self {
+impl From<&Emulator> for EmulatorResponse {
+    fn from(emulator: &Emulator) -> Self {
         Self {
-            error: error.to_string(),
+            name: emulator.name().to_string(),
+            port: emulator.port(),
+            adb_port: emulator.adb_port(),
+            status: emulator.state().to_string(),
+            last_error: emulator.last_error().map(str::to_string),
+            restart_policy: emulator.restart_policy(),
+            launch_profile: emulator.launch_profile().clone(),
         }
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveSnapshotRequest {
+    pub name: String,
+}
+
+/// List emulators with their live status
+async fn list_emulators(
+    manager: web::Data<SharedEmulatorManager>,
+    query: web::Query<ListEmulatorsQuery>,
+) -> Result<HttpResponse, EmulatorError> {
+    let query = query.into_inner();
+    let states = match query.state.as_deref() {
+        Some(states) => states
+            .split(',')
+            .map(str::trim)
+            .filter(|state| !state.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(EmulatorError::InvalidQuery)?,
+        None => Vec::new(),
+    };
+    let list_query = ListQuery {
+        states,
+        name_prefix: query.prefix,
+        sort: query.sort.unwrap_or_default(),
+        order: query.order.unwrap_or_default(),
+        cursor: query.cursor,
+        limit: query.limit,
+    };
+
+    match manager.list_summaries(&list_query).await? {
+        Some(page) => Ok(HttpResponse::Ok().json(page)),
+        None => Err(EmulatorError::InvalidQuery("invalid cursor".to_string())),
+    }
+}
+
 /// Create a new emulator instance
 async fn create_emulator(
     manager: web::Data<SharedEmulatorManager>,
     req: web::Json<CreateEmulatorRequest>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.create_emulator(req.name.clone()).await {
-        Ok(emulator) => HttpResponse::Ok().json(EmulatorResponse {
-            name: req.name.clone(),
-            port: emulator.port(),
-            adb_port: emulator.adb_port(),
-            status: "created".to_string(),
-        }),
-        Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::from(e)),
+) -> Result<HttpResponse, EmulatorError> {
+    let req = req.into_inner();
+    if let Some(profile) = &req.launch_profile {
+        profile.validate()?;
+    }
+
+    let _guard = manager.lock_emulator(&req.name).await;
+    let mut emulator = manager.create_emulator(req.name.clone()).await?;
+    if let Some(policy) = req.restart_policy {
+        manager.set_restart_policy(&mut emulator, policy).await?;
     }
+    if let Some(profile) = req.launch_profile {
+        manager.set_launch_profile(&mut emulator, profile).await?;
+    }
+    Ok(HttpResponse::Ok().json(EmulatorResponse::from(&emulator)))
 }
 
-/// Start an emulator
-async fn start_emulator(
+/// Delete an emulator, stopping it first when `force=true` and wiping its AVD data
+/// when `wipe=true`
+async fn delete_emulator(
     manager: web::Data<SharedEmulatorManager>,
     name: web::Path<String>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.get_emulator(&name).await {
-        Some(mut emulator) => match emulator.start().await {
-            Ok(_) => HttpResponse::Ok().json(EmulatorResponse {
-                name: name.to_string(),
-                port: emulator.port(),
-                adb_port: emulator.adb_port(),
-                status: "running".to_string(),
-            }),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::from(e)),
-        },
-        None => HttpResponse::NotFound().json(ErrorResponse {
-            error: format!("Emulator {} not found", name),
-        }),
+    query: web::Query<DeleteEmulatorQuery>,
+) -> Result<HttpResponse, EmulatorError> {
+    let options = DeleteOptions {
+        force: query.force,
+        wipe: query.wipe,
+    };
+    let (_guard, mut emulator) = manager.acquire(&name).await?;
+    let report = manager.delete_emulator(&mut emulator, options).await?;
+    Ok(HttpResponse::Ok().json(DeleteEmulatorResponse {
+        name: name.to_string(),
+        stopped: report.stopped,
+        wiped: report
+            .wiped
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+    }))
+}
+
+/// Change the restart policy of an emulator
+async fn set_restart_policy(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+    policy: web::Json<RestartPolicy>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (_guard, mut emulator) = manager.acquire(&name).await?;
+    manager.set_restart_policy(&mut emulator, *policy).await?;
+    Ok(HttpResponse::Ok().json(EmulatorResponse::from(&emulator)))
+}
+
+/// Replace the launch profile of an emulator
+async fn set_launch_profile(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+    profile: web::Json<LaunchProfile>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (_guard, mut emulator) = manager.acquire(&name).await?;
+    manager.set_launch_profile(&mut emulator, profile.into_inner()).await?;
+    Ok(HttpResponse::Ok().json(EmulatorResponse::from(&emulator)))
+}
+
+/// List the snapshots of an emulator with their metadata
+async fn list_snapshots(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let snapshots = manager.list_snapshots(&emulator).await?;
+    Ok(HttpResponse::Ok().json(snapshots))
+}
+
+/// Save a running emulator as a named snapshot
+async fn save_snapshot(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+    req: web::Json<SaveSnapshotRequest>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let record = manager.save_snapshot(&emulator, &req.name).await?;
+    Ok(HttpResponse::Created().json(record))
+}
+
+/// Restore a running emulator to a named snapshot
+async fn load_snapshot(
+    manager: web::Data<SharedEmulatorManager>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (name, snapshot) = path.into_inner();
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    manager.load_snapshot(&emulator, &snapshot).await?;
+    Ok(HttpResponse::Ok().json(()))
+}
+
+/// Delete a named snapshot of an emulator
+async fn delete_snapshot(
+    manager: web::Data<SharedEmulatorManager>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (name, snapshot) = path.into_inner();
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    manager.delete_snapshot(&emulator, &snapshot).await?;
+    Ok(HttpResponse::Ok().json(()))
+}
+
+/// Get the recent output and exit history of a supervised emulator process
+async fn get_emulator_logs(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, EmulatorError> {
+    if manager.get_emulator(&name).await.is_none() {
+        return Err(EmulatorError::NotFound(name.to_string()));
     }
+
+    let process = manager
+        .registry()
+        .get(&name)
+        .await
+        .and_then(|instance| instance.process);
+    Ok(HttpResponse::Ok().json(EmulatorLogsResponse {
+        name: name.to_string(),
+        pid: process.as_ref().and_then(|process| process.pid()),
+        restarts: process.as_ref().map_or(0, |process| process.restarts()),
+        last_exit: process.as_ref().and_then(|process| process.last_exit()),
+        lines: process.map(|process| process.logs().lines()).unwrap_or_default(),
+    }))
+}
+
+/// Start an emulator; returns as soon as the process is spawned and the emulator is booting
+async fn start_emulator(
+    manager: web::Data<SharedEmulatorManager>,
+    name: web::Path<String>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (_guard, mut emulator) = manager.acquire(&name).await?;
+    manager.start_emulator(&mut emulator).await?;
+    Ok(HttpResponse::Accepted().json(EmulatorResponse::from(&emulator)))
 }
 
 /// Stop an emulator
 async fn stop_emulator(
     manager: web::Data<SharedEmulatorManager>,
     name: web::Path<String>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.get_emulator(&name).await {
-        Some(mut emulator) => match emulator.stop().await {
-            Ok(_) => HttpResponse::Ok().json(EmulatorResponse {
-                name: name.to_string(),
-                port: emulator.port(),
-                adb_port: emulator.adb_port(),
-                status: "stopped".to_string(),
-            }),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::from(e)),
-        },
-        None => HttpResponse::NotFound().json(ErrorResponse {
-            error: format!("Emulator {} not found", name),
-        }),
-    }
+) -> Result<HttpResponse, EmulatorError> {
+    let (_guard, mut emulator) = manager.acquire(&name).await?;
+    manager.stop_emulator(&mut emulator).await?;
+    Ok(HttpResponse::Ok().json(EmulatorResponse::from(&emulator)))
 }
 
-/// Install an app on an emulator
+/// Install an uploaded APK on an emulator
 async fn install_app(
     manager: web::Data<SharedEmulatorManager>,
+    artifacts: web::Data<ArtifactStore>,
     name: web::Path<String>,
     req: web::Json<InstallAppRequest>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.get_emulator(&name).await {
-        Some(emulator) => match emulator.install_app(&req.apk_path).await {
-            Ok(_) => HttpResponse::Ok().json(()),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::from(e)),
-        },
-        None => HttpResponse::NotFound().json(ErrorResponse {
-            error: format!("Emulator {} not found", name),
-        }),
+) -> Result<HttpResponse, actix_web::Error> {
+    let plan = artifacts
+        .plan_install(&req.artifact_id, &req.split_artifact_ids)
+        .await?;
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let device = match plan.has_splits() {
+        true => {
+            let mut device = emulator.device_profile().await?;
+            // An ABI asked for explicitly wins over the device's preference
+            if let Some(abi) = &req.options.abi {
+                device.abis.retain(|supported| supported != abi);
+                device.abis.insert(0, abi.clone());
+            }
+            Some(device)
+        }
+        false => None,
+    };
+    let selection = plan.select(device.as_ref());
+
+    let prepared = artifacts.prepare_install(&plan, &selection).await?;
+    let result = install_prepared(&emulator, plan.package.as_deref(), &prepared, &req.options).await;
+    prepared.cleanup().await;
+    let obbs = result?;
+
+    for id in &plan.artifact_ids {
+        artifacts.record_install(id, &name).await?;
     }
+    Ok(HttpResponse::Ok().json(InstallAppResponse {
+        artifact: plan.artifact,
+        device,
+        splits: selection.chosen,
+        skipped_splits: selection.skipped,
+        obbs,
+    }))
+}
+
+/// Install the APKs, then push the expansion files, returning their names
+async fn install_prepared(
+    emulator: &Emulator,
+    package: Option<&str>,
+    prepared: &PreparedInstall,
+    options: &InstallOptions,
+) -> Result<Vec<String>, EmulatorError> {
+    match prepared.apks.as_slice() {
+        [apk] => emulator.install_app(apk, options).await?,
+        apks => emulator.install_multiple(apks, options).await?,
+    }
+    let mut obbs = Vec::new();
+    if let Some(package) = package {
+        for (file_name, path) in &prepared.obbs {
+            emulator.push_obb(package, path, file_name).await?;
+            obbs.push(file_name.clone());
+        }
+    }
+    Ok(obbs)
 }
 
 /// Start an app on an emulator
 async fn start_app(
     manager: web::Data<SharedEmulatorManager>,
-    name: web::Path<String>,
+    artifacts: web::Data<ArtifactStore>,
+    path: web::Path<(String, String)>,
     req: web::Json<StartAppRequest>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.get_emulator(&name).await {
-        Some(emulator) => match emulator.start_app(&req.package_name, &req.activity).await {
-            Ok(_) => HttpResponse::Ok().json(()),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::from(e)),
+) -> Result<HttpResponse, actix_web::Error> {
+    let (name, package) = path.into_inner();
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let activity = match &req.activity {
+        Some(activity) => activity.clone(),
+        None => match emulator.resolve_launcher_activity(&package).await {
+            Ok(Some(activity)) => activity,
+            // Older images lack `cmd package resolve-activity`
+            Ok(None) | Err(EmulatorError::AppError(AppError::StatusError(_))) => {
+                artifacts.launcher_activity(&name, &package).await?
+            }
+            Err(e) => return Err(e.into()),
         },
-        None => HttpResponse::NotFound().json(ErrorResponse {
-            error: format!("Emulator {} not found", name),
-        }),
-    }
+    };
+    let launch = emulator.start_app(&package, &activity, req.wait).await?;
+    Ok(HttpResponse::Ok().json(launch))
 }
 
 /// Stop an app on an emulator
 async fn stop_app(
+    manager: web::Data<SharedEmulatorManager>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (name, package_name) = path.into_inner();
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    emulator.stop_app(&package_name).await?;
+    Ok(HttpResponse::Ok().json(()))
+}
+
+/// Send an intent, e.g. to open a deep link or deliver a broadcast
+async fn send_intent(
+    manager: web::Data<SharedEmulatorManager>,
+    path: web::Path<String>,
+    req: web::Json<Intent>,
+) -> Result<HttpResponse, EmulatorError> {
+    let name = path.into_inner();
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let result = emulator.send_intent(&req).await?;
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// List the running processes of an app, empty when the app is not running
+async fn get_app_processes(
+    manager: web::Data<SharedEmulatorManager>,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, EmulatorError> {
+    let (name, package_name) = path.into_inner();
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let processes = emulator.app_processes(&package_name).await?;
+    Ok(HttpResponse::Ok().json(processes))
+}
+
+/// List the packages installed on an emulator, from the emulator itself while it
+/// runs and from the last inventory otherwise
+async fn list_apps(
     manager: web::Data<SharedEmulatorManager>,
     name: web::Path<String>,
-    package_name: web::Path<String>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.get_emulator(&name).await {
-        Some(emulator) => match emulator.stop_app(&package_name).await {
-            Ok(_) => HttpResponse::Ok().json(()),
-            Err(e) => HttpResponse::InternalServerError().json(ErrorResponse::from(e)),
-        },
-        None => HttpResponse::NotFound().json(ErrorResponse {
-            error: format!("Emulator {} not found", name),
-        }),
+    query: web::Query<ListAppsQuery>,
+) -> Result<HttpResponse, EmulatorError> {
+    // Refreshing the inventory must not race with the emulator being deleted
+    let (_guard, emulator) = manager.acquire(&name).await?;
+    let mut inventory = manager.list_packages(&emulator).await?;
+    if let Some(system) = query.system {
+        inventory.packages.retain(|package| package.system == system);
     }
+    Ok(HttpResponse::Ok().json(inventory))
 }
 
 /// Get emulator status
 async fn get_emulator_status(
     manager: web::Data<SharedEmulatorManager>,
     name: web::Path<String>,
-) -> HttpResponse {
-    let manager = manager.lock().await;
-    match manager.get_emulator(&name).await {
-        Some(emulator) => HttpResponse::Ok().json(EmulatorResponse {
-            name: name.to_string(),
-            port: emulator.port(),
-            adb_port: emulator.adb_port(),
-            status: if emulator.is_running().await.unwrap_or(false) {
-                "running"
-            } else {
-                "stopped"
-            }
-            .to_string(),
-        }),
-        None => HttpResponse::NotFound().json(ErrorResponse {
-            error: format!("Emulator {} not found", name),
-        }),
-    }
+) -> Result<HttpResponse, EmulatorError> {
+    // Status reads do not queue behind slow operations on the emulator
+    let emulator = manager
+        .get_emulator(&name)
+        .await
+        .ok_or_else(|| EmulatorError::NotFound(name.to_string()))?;
+    Ok(HttpResponse::Ok().json(EmulatorResponse::from(&emulator)))
 }
 
 /// Configure emulator management API routes
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/emulators")
+            .route("", web::get().to(list_emulators))
             .route("", web::post().to(create_emulator))
+            .route("/{name}", web::delete().to(delete_emulator))
             .route("/{name}/start", web::post().to(start_emulator))
             .route("/{name}/stop", web::post().to(stop_emulator))
             .route("/{name}/status", web::get().to(get_emulator_status))
+            .route("/{name}/logs", web::get().to(get_emulator_logs))
+            .route("/{name}/restart-policy", web::put().to(set_restart_policy))
+            .route("/{name}/launch-profile", web::put().to(set_launch_profile))
+            .route("/{name}/snapshots", web::get().to(list_snapshots))
+            .route("/{name}/snapshots", web::post().to(save_snapshot))
+            .route("/{name}/snapshots/{snapshot}", web::delete().to(delete_snapshot))
+            .route("/{name}/snapshots/{snapshot}/load", web::post().to(load_snapshot))
+            .route("/{name}/apps", web::get().to(list_apps))
             .route("/{name}/apps/install", web::post().to(install_app))
             .route("/{name}/apps/{package}/start", web::post().to(start_app))
             .route("/{name}/apps/{package}/stop", web::post().to(stop_app))
+            .route("/{name}/apps/{package}/processes", web::get().to(get_app_processes))
+            .route("/{name}/intents", web::post().to(send_intent))
     );
 }
@@ -0,0 +1,139 @@
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::error;
+
+use crate::artifacts::ArtifactError;
+use crate::emulator::adb_client::AdbClientError;
+use crate::emulator::app_manager::AppError;
+use crate::emulator::console::ConsoleError;
+use crate::emulator::port_manager::PortError;
+use crate::emulator::EmulatorError;
+use crate::models::ApiResponse;
+
+/// Failed emulator operations are answered with an `ApiResponse` carrying the
+/// error's code and message
+impl ResponseError for EmulatorError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EmulatorError::NotFound(_)
+            | EmulatorError::AvdNotFound(_)
+            | EmulatorError::SnapshotNotFound { .. } => StatusCode::NOT_FOUND,
+            EmulatorError::InvalidAvdConfig(_)
+            | EmulatorError::InvalidLaunchProfile(_)
+            | EmulatorError::InvalidSnapshotName(_)
+            | EmulatorError::InvalidQuery(_)
+            | EmulatorError::PortError(PortError::InvalidPort(_))
+            | EmulatorError::AppError(
+                AppError::InvalidApkPath(_)
+                | AppError::InvalidPackageName(_)
+                | AppError::InvalidActivity(_)
+                | AppError::InvalidFileName(_)
+                | AppError::InvalidInstallOption(_)
+                | AppError::InvalidIntent(_),
+            ) => StatusCode::BAD_REQUEST,
+            EmulatorError::InvalidTransition { .. }
+            | EmulatorError::StillActive { .. }
+            | EmulatorError::NotRunning { .. }
+            | EmulatorError::PortError(PortError::PortInUse(_))
+            | EmulatorError::AdbClientError(
+                AdbClientError::DeviceNotFound(_)
+                | AdbClientError::DeviceOffline(_)
+                | AdbClientError::Unauthorized(_),
+            ) => StatusCode::CONFLICT,
+            // The host is out of capacity or missing the SDK; retrying later may help
+            EmulatorError::PortError(PortError::NoAvailablePorts)
+            | EmulatorError::ToolNotFound(_)
+            | EmulatorError::AdbClientError(AdbClientError::ServerUnavailable(_)) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            EmulatorError::ConsoleError(ConsoleError::Rejected { .. }) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            EmulatorError::ConsoleError(_) => StatusCode::SERVICE_UNAVAILABLE,
+            EmulatorError::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            // The package manager turned the APK down, which retrying will not change
+            EmulatorError::AppError(AppError::InstallError {
+                failure: Some(failure),
+                ..
+            }) => match failure.as_str() {
+                "INSTALL_FAILED_INSUFFICIENT_STORAGE" => StatusCode::INSUFFICIENT_STORAGE,
+                "INSTALL_FAILED_INTERNAL_ERROR" | "INSTALL_FAILED_ABORTED" => {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                }
+                _ => StatusCode::UNPROCESSABLE_ENTITY,
+            },
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            error!("Request failed: {}", self);
+        }
+        HttpResponse::build(status).json(ApiResponse::<()>::error(self.code(), self.to_string()))
+    }
+}
+
+impl ResponseError for ArtifactError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ArtifactError::NotFound(_) => StatusCode::NOT_FOUND,
+            ArtifactError::InvalidId(_)
+            | ArtifactError::InvalidUpload(_)
+            | ArtifactError::NoLauncherActivity { .. } => StatusCode::BAD_REQUEST,
+            ArtifactError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            ArtifactError::Io(_) | ArtifactError::DbError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let status = self.status_code();
+        if status.is_server_error() {
+            error!("Request failed: {}", self);
+        }
+        HttpResponse::build(status).json(ApiResponse::<()>::error(self.code(), self.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        let cases = [
+            (EmulatorError::PortError(PortError::NoAvailablePorts), 503, "no_available_ports"),
+            (EmulatorError::AppError(AppError::InvalidApkPath("x".into())), 400, "invalid_apk_path"),
+            (
+                EmulatorError::from(AppError::Adb(AdbClientError::DeviceOffline("emulator-5554".into()))),
+                409,
+                "device_offline",
+            ),
+            (
+                EmulatorError::NotRunning {
+                    name: "pixel".into(),
+                    state: crate::emulator::EmulatorState::Stopped,
+                },
+                409,
+                "emulator_not_running",
+            ),
+            (EmulatorError::AdbError("boom".into()), 500, "adb_failed"),
+            (
+                EmulatorError::from(AppError::install("Failure [INSTALL_FAILED_VERSION_DOWNGRADE]".into())),
+                422,
+                "install_failed_version_downgrade",
+            ),
+            (
+                EmulatorError::from(AppError::install("Failure [INSTALL_FAILED_INSUFFICIENT_STORAGE]".into())),
+                507,
+                "install_failed_insufficient_storage",
+            ),
+            (EmulatorError::from(AppError::install("adb: failed to install".into())), 500, "install_failed"),
+        ];
+        for (error, status, code) in cases {
+            assert_eq!(error.status_code().as_u16(), status, "{}", error);
+            assert_eq!(error.code(), code);
+        }
+    }
+}
@@ -1,4 +1,7 @@
 // This module will contain handlers for various API endpoints
 // Currently empty as we'll implement specific handlers as needed
 
+pub mod artifact;
+pub mod avd;
 pub mod emulator;
+pub mod error;
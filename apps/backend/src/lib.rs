@@ -1,3 +1,5 @@
+pub mod apk;
+pub mod artifacts;
 pub mod config;
 pub mod db;
 pub mod emulator;
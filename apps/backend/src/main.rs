@@ -1,11 +1,12 @@
 use actix_web::{middleware, App, HttpServer, web};
 use anyhow::Result;
 use dotenv::dotenv;
-use log::info;
+use log::{info, warn};
 use std::sync::Arc;
-use tokio::sync::Mutex;
+use std::time::Duration;
 
 use backend::{
+    artifacts::ArtifactStore,
     config,
     db,
     emulator::EmulatorManager,
@@ -28,7 +29,34 @@ async fn main() -> Result<()> {
     info!("Database connection established");
     
     // Initialize the emulator manager
-    let emulator_manager = Arc::new(Mutex::new(EmulatorManager::new(db_pool.clone())));
+    let emulator_manager = EmulatorManager::new(db_pool.clone())
+        .with_boot_config(config.emulator.clone().into())
+        .with_supervisor_config(config.emulator.clone().into())
+        .with_command_policy(config.emulator.clone().into());
+
+    // Restore port allocations and emulator states left over from a previous run
+    let report = emulator_manager.reconcile().await?;
+    info!(
+        "Reconciled emulators: {} restored, {} running, {} stopped",
+        report.restored.len(),
+        report.running.len(),
+        report.stopped.len()
+    );
+    if !report.port_conflicts.is_empty() {
+        warn!("Emulators with conflicting ports: {:?}", report.port_conflicts);
+    }
+    if !report.unknown_serials.is_empty() {
+        warn!("Unknown emulators attached to adb: {:?}", report.unknown_serials);
+    }
+    let emulator_manager = Arc::new(emulator_manager);
+
+    // Store uploaded APKs and periodically drop the ones nothing uses anymore
+    let artifact_store = ArtifactStore::from_settings(&config.artifacts, db_pool.clone());
+    artifact_store.clone().spawn_cleanup(
+        Duration::from_secs(config.artifacts.cleanup_interval_secs),
+        Duration::from_secs(config.artifacts.retention_hours * 3600),
+    );
+    info!("Storing artifacts in {}", artifact_store.dir().display());
     
     // Create and start the HTTP server
     let server_config = config.server.clone();
@@ -39,12 +67,18 @@ async fn main() -> Result<()> {
             .app_data(web::Data::new(db_pool.clone()))
             // Add emulator manager to app state
             .app_data(web::Data::new(emulator_manager.clone()))
+            // Add artifact store to app state
+            .app_data(web::Data::new(artifact_store.clone()))
             // Enable logger middleware
             .wrap(middleware::Logger::default())
             // Configure routes
             .configure(routes::configure)
             // Configure emulator API routes
             .configure(handlers::emulator::configure)
+            // Configure AVD provisioning API routes
+            .configure(handlers::avd::configure)
+            // Configure artifact API routes
+            .configure(handlers::artifact::configure)
     })
     .bind((server_config.host, server_config.port))?
     .run()
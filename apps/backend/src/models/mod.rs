@@ -18,6 +18,21 @@ pub struct CreateAccountRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiResponse<T> {
     pub success: bool,
+    /// Machine-readable error code, set on failures
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
     pub message: Option<String>,
     pub data: Option<T>,
 }
+
+impl<T> ApiResponse<T> {
+    /// A failed response with an error code and a human-readable message
+    pub fn error(code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            success: false,
+            code: Some(code.into()),
+            message: Some(message.into()),
+            data: None,
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use actix_web::{http::StatusCode, test, web, App};
+use anyhow::Result;
+use std::env;
+use backend::{
+    artifacts::ArtifactStore,
+    db::{self, artifact::ArtifactRecord},
+    handlers,
+    models::ApiResponse,
+};
+
+const BOUNDARY: &str = "tikpilot-boundary";
+
+async fn setup_store() -> Result<ArtifactStore> {
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let dir = env::temp_dir().join(format!("tikpilot-artifacts-{}", uuid::Uuid::new_v4()));
+    Ok(ArtifactStore::new(dir, pool).with_max_size(1024))
+}
+
+/// A multipart upload of `content` as the `file` field
+fn upload(file_name: &str, content: &[u8]) -> test::TestRequest {
+    let mut body = format!(
+        "--{BOUNDARY}\r\n\
+         Content-Disposition: form-data; name=\"file\"; filename=\"{file_name}\"\r\n\
+         Content-Type: application/vnd.android.package-archive\r\n\r\n"
+    )
+    .into_bytes();
+    body.extend_from_slice(content);
+    body.extend_from_slice(format!("\r\n--{BOUNDARY}--\r\n").as_bytes());
+
+    test::TestRequest::post()
+        .uri("/artifacts/apks")
+        .insert_header((
+            "content-type",
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        ))
+        .set_payload(body)
+}
+
+#[actix_web::test]
+async fn test_upload_deduplicates_apks() -> Result<()> {
+    let store = setup_store().await?;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(store.clone()))
+            .configure(handlers::artifact::configure),
+    )
+    .await;
+
+    let resp = test::call_service(&app, upload("app.apk", b"PK\x03\x04first").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: ArtifactRecord = test::read_body_json(resp).await;
+    assert_eq!(created.file_name, "app.apk");
+    assert_eq!(created.size_bytes, 9);
+    assert!(store.path(&created.id)?.exists());
+
+    // The same content is stored once
+    let resp = test::call_service(&app, upload("again.apk", b"PK\x03\x04first").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let existing: ArtifactRecord = test::read_body_json(resp).await;
+    assert_eq!(existing.id, created.id);
+
+    let req = test::TestRequest::get().uri("/artifacts/apks").to_request();
+    let listed: Vec<ArtifactRecord> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(listed.len(), 1);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/artifacts/apks/{}", created.id))
+        .to_request();
+    let fetched: ArtifactRecord = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(fetched.id, created.id);
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/artifacts/apks/{}", created.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::OK);
+    assert!(!store.path(&created.id)?.exists());
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/artifacts/apks/{}", created.id))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    std::fs::remove_dir_all(store.dir())?;
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_upload_rejects_invalid_files() -> Result<()> {
+    let store = setup_store().await?;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(store.clone()))
+            .configure(handlers::artifact::configure),
+    )
+    .await;
+
+    let resp = test::call_service(&app, upload("notes.txt", b"hello").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("invalid_upload"));
+
+    let mut large = b"PK\x03\x04".to_vec();
+    large.resize(2048, 0);
+    let resp = test::call_service(&app, upload("large.apk", &large).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let req = test::TestRequest::get().uri("/artifacts/apks/..%2F..%2Fetc").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("invalid_artifact_id"));
+
+    std::fs::remove_dir_all(store.dir())?;
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use actix_web::{http::StatusCode, test, web, App};
+use anyhow::Result;
+use std::{env, fs};
+use std::sync::Arc;
+use backend::{
+    db,
+    emulator::{avd::AvdConfig, EmulatorManager},
+    handlers::{self, emulator::SharedEmulatorManager},
+};
+
+async fn setup_manager() -> Result<SharedEmulatorManager> {
+    let pool = db::create_pool("sqlite::memory:").await?;
+    Ok(Arc::new(EmulatorManager::new(pool)))
+}
+
+#[actix_web::test]
+async fn test_avd_config_roundtrip() -> Result<()> {
+    // Point the AVD home at a scratch directory with a single AVD
+    let home = env::temp_dir().join(format!("tikpilot-avd-home-{}", std::process::id()));
+    let avd = home.join("pixel_test.avd");
+    fs::create_dir_all(&avd)?;
+    fs::write(
+        avd.join("config.ini"),
+        "abi.type=x86_64\nhw.ramSize=2048\nhw.lcd.density=420\n",
+    )?;
+    env::set_var("ANDROID_AVD_HOME", &home);
+
+    let manager = setup_manager().await?;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(manager.clone()))
+            .configure(handlers::avd::configure),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/avds").to_request();
+    let avds: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(avds.len(), 1);
+    assert_eq!(avds[0]["name"], "pixel_test");
+    assert_eq!(avds[0]["abi"], "x86_64");
+
+    let patch = AvdConfig {
+        ram_size_mb: Some(4096),
+        data_partition_size_mb: Some(8192),
+        ..Default::default()
+    };
+    let req = test::TestRequest::patch()
+        .uri("/avds/pixel_test/config")
+        .set_json(&patch)
+        .to_request();
+    let config: AvdConfig = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(config.ram_size_mb, Some(4096));
+    assert_eq!(config.lcd_density, Some(420));
+
+    let written = fs::read_to_string(avd.join("config.ini"))?;
+    assert!(written.starts_with("abi.type=x86_64\nhw.ramSize=4096M\n"));
+    assert!(written.contains("disk.dataPartition.size=8192M"));
+
+    let req = test::TestRequest::patch()
+        .uri("/avds/pixel_test/config")
+        .set_json(AvdConfig {
+            cpu_cores: Some(0),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // Concurrent patches of different settings both end up in config.ini
+    let patches = [
+        AvdConfig {
+            cpu_cores: Some(2),
+            ..Default::default()
+        },
+        AvdConfig {
+            gpu_enabled: Some(true),
+            ..Default::default()
+        },
+    ];
+    let requests = patches.iter().map(|patch| {
+        let req = test::TestRequest::patch()
+            .uri("/avds/pixel_test/config")
+            .set_json(patch)
+            .to_request();
+        test::call_service(&app, req)
+    });
+    for resp in futures_util::future::join_all(requests).await {
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let req = test::TestRequest::get().uri("/avds/pixel_test/config").to_request();
+    let config: AvdConfig = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(config.cpu_cores, Some(2));
+    assert_eq!(config.gpu_enabled, Some(true));
+    assert_eq!(config.ram_size_mb, Some(4096));
+
+    let req = test::TestRequest::get().uri("/avds/missing/config").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    fs::remove_dir_all(&home)?;
+    Ok(())
+}
@@ -1,45 +1,54 @@
 use actix_web::{test, web, App};
 use anyhow::Result;
-use sqlx::sqlite::SqlitePool;
 use std::sync::Arc;
-use tikpilot_backend::{
-    config::Config,
+use backend::{
     db,
-    emulator::EmulatorManager,
-    handlers::emulator::{CreateEmulatorRequest, EmulatorResponse},
-    routes,
+    emulator::{
+        launch::{GpuMode, LaunchProfile},
+        listing::EmulatorPage,
+        supervisor::RestartPolicy,
+        EmulatorManager, EmulatorState,
+    },
+    handlers::{
+        self,
+        emulator::{
+            CreateEmulatorRequest, DeleteEmulatorResponse, EmulatorLogsResponse, EmulatorResponse,
+            SharedEmulatorManager,
+        },
+    },
+    models::ApiResponse,
 };
 
-async fn setup_test_app() -> Result<(
-    test::TestApp,
-    Arc<EmulatorManager>,
-)> {
+async fn setup_manager() -> Result<SharedEmulatorManager> {
     // Create in-memory SQLite database
-    let pool = SqlitePool::connect("sqlite::memory:").await?;
-    db::create_pool("sqlite::memory:").await?;
+    let pool = db::create_pool("sqlite::memory:").await?;
 
     // Create emulator manager
-    let emulator_manager = Arc::new(EmulatorManager::new(pool.clone()));
-
-    // Create test application
-    let app = test::init_service(
-        App::new()
-            .app_data(web::Data::new(emulator_manager.clone()))
-            .configure(routes::configure),
-    )
-    .await;
+    Ok(Arc::new(EmulatorManager::new(pool)))
+}
 
-    Ok((app, emulator_manager))
+macro_rules! test_app {
+    ($manager:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($manager.clone()))
+                .configure(handlers::emulator::configure),
+        )
+        .await
+    };
 }
 
 #[actix_web::test]
 async fn test_create_emulator() -> Result<()> {
-    let (app, _) = setup_test_app().await?;
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
 
     let req = test::TestRequest::post()
         .uri("/emulators")
         .set_json(&CreateEmulatorRequest {
             name: "test_avd".to_string(),
+            restart_policy: None,
+            launch_profile: None,
         })
         .to_request();
 
@@ -50,7 +59,8 @@ async fn test_create_emulator() -> Result<()> {
 
 #[actix_web::test]
 async fn test_get_emulator_status() -> Result<()> {
-    let (app, manager) = setup_test_app().await?;
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
 
     // Create an emulator first
     let emulator = manager.create_emulator("test_avd".to_string()).await?;
@@ -67,7 +77,8 @@ async fn test_get_emulator_status() -> Result<()> {
 
 #[actix_web::test]
 async fn test_nonexistent_emulator() -> Result<()> {
-    let (app, _) = setup_test_app().await?;
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
 
     // Try to get status of nonexistent emulator
     let req = test::TestRequest::get()
@@ -75,5 +86,214 @@ async fn test_nonexistent_emulator() -> Result<()> {
         .to_request();
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 404);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert!(!body.success);
+    assert_eq!(body.code.as_deref(), Some("emulator_not_found"));
+    assert_eq!(body.message.as_deref(), Some("Emulator nonexistent not found"));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_restart_policy_roundtrip() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+
+    let req = test::TestRequest::post()
+        .uri("/emulators")
+        .set_json(&CreateEmulatorRequest {
+            name: "test_avd".to_string(),
+            restart_policy: Some(RestartPolicy::OnFailure {
+                max_retries: 3,
+                backoff_ms: 500,
+            }),
+            launch_profile: None,
+        })
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        resp.restart_policy,
+        RestartPolicy::OnFailure { max_retries: 3, backoff_ms: 500 }
+    );
+
+    let req = test::TestRequest::put()
+        .uri("/emulators/test_avd/restart-policy")
+        .set_json(serde_json::json!({"policy": "always"}))
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.restart_policy, RestartPolicy::Always { backoff_ms: 1000 });
+
+    let req = test::TestRequest::get()
+        .uri("/emulators/test_avd/logs")
+        .to_request();
+    let resp: EmulatorLogsResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.restarts, 0);
+    assert!(resp.lines.is_empty());
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_launch_profile_roundtrip() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+
+    let req = test::TestRequest::post()
+        .uri("/emulators")
+        .set_json(&CreateEmulatorRequest {
+            name: "ci_avd".to_string(),
+            restart_policy: None,
+            launch_profile: Some(LaunchProfile {
+                no_audio: true,
+                cores: Some(2),
+                ..Default::default()
+            }),
+        })
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert!(resp.launch_profile.headless);
+    assert_eq!(resp.launch_profile.cores, Some(2));
+
+    let req = test::TestRequest::put()
+        .uri("/emulators/ci_avd/launch-profile")
+        .set_json(serde_json::json!({"headless": false, "gpu_mode": "host"}))
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert!(!resp.launch_profile.headless);
+    assert_eq!(resp.launch_profile.cores, None);
+
+    // The profile survives a reload from the database
+    let emulator = manager.get_emulator("ci_avd").await.unwrap();
+    assert_eq!(emulator.launch_profile().gpu_mode, Some(GpuMode::Host));
+
+    let req = test::TestRequest::put()
+        .uri("/emulators/ci_avd/launch-profile")
+        .set_json(serde_json::json!({"extra_flags": ["-qemu", "-enable-kvm"]}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_snapshots_of_stopped_emulator() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+
+    let req = test::TestRequest::get()
+        .uri("/emulators/test_avd/snapshots")
+        .to_request();
+    let resp: Vec<serde_json::Value> = test::call_and_read_body_json(&app, req).await;
+    assert!(resp.is_empty());
+
+    // Saving needs a running emulator
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/snapshots")
+        .set_json(serde_json::json!({"name": "clean"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("emulator_not_running"));
+
+    let req = test::TestRequest::delete()
+        .uri("/emulators/test_avd/snapshots/missing")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    // A booting emulator has its snapshots open
+    let mut emulator = manager.get_emulator("test_avd").await.unwrap();
+    manager.transition(&mut emulator, EmulatorState::Booting, None).await?;
+    let req = test::TestRequest::delete()
+        .uri("/emulators/test_avd/snapshots/clean")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("emulator_active"));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_delete_emulator() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+
+    let req = test::TestRequest::delete()
+        .uri("/emulators/test_avd")
+        .to_request();
+    let resp: DeleteEmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.name, "test_avd");
+    assert!(!resp.stopped);
+
+    let req = test::TestRequest::get()
+        .uri("/emulators/test_avd/status")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_delete_running_emulator_conflicts() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+    let mut emulator = manager.create_emulator("test_avd".to_string()).await?;
+    manager.transition(&mut emulator, EmulatorState::Booting, None).await?;
+
+    let req = test::TestRequest::delete()
+        .uri("/emulators/test_avd")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_list_emulators() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+    for name in ["ci-1", "ci-2", "dev-1"] {
+        manager.create_emulator(name.to_string()).await?;
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/emulators?prefix=ci-&limit=1&state=created")
+        .to_request();
+    let page: EmulatorPage = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].name, "ci-1");
+    assert!(!page.items[0].online);
+
+    let cursor = page.next_cursor.expect("a second page");
+    let req = test::TestRequest::get()
+        .uri(&format!("/emulators?prefix=ci-&limit=1&cursor={}", cursor))
+        .to_request();
+    let page: EmulatorPage = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(page.items[0].name, "ci-2");
+    assert!(page.next_cursor.is_none());
+
+    let req = test::TestRequest::get()
+        .uri("/emulators?state=bogus")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_app_operations_need_running_emulator() -> Result<()> {
+    let manager = setup_manager().await?;
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/com.example/stop")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("emulator_not_running"));
     Ok(())
 }
@@ -0,0 +1,844 @@
+use actix_web::{test, web, App};
+use anyhow::Result;
+use std::sync::Arc;
+use std::time::Duration;
+use backend::{
+    apk::ApkManifest,
+    artifacts::ArtifactStore,
+    db::{
+        self,
+        artifact::{ArtifactDb, ArtifactRecord},
+    },
+    emulator::{
+        activity::LaunchResult,
+        boot::BootConfig,
+        intent::{BroadcastResult, IntentResult},
+        packages::PackageInventory,
+        process::AppProcess,
+        runner::{CommandPolicy, FakeResponse, FakeRunner},
+        supervisor::SupervisorConfig,
+        EmulatorManager, EmulatorState,
+    },
+    handlers::{
+        self,
+        emulator::{
+            EmulatorLogsResponse, EmulatorResponse, InstallAppRequest, InstallAppResponse,
+            SharedEmulatorManager,
+            StartAppRequest,
+        },
+    },
+    models::ApiResponse,
+};
+
+/// `adb devices` listing the emulator on the first console port
+const DEVICE_ONLINE: &str = "List of devices attached\nemulator-5554\tdevice\n";
+
+/// A manager whose SDK tools are scripted by the returned fake runner
+async fn setup_manager() -> Result<(SharedEmulatorManager, FakeRunner)> {
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let runner = FakeRunner::new();
+    let manager = EmulatorManager::new(pool)
+        .with_runner(Arc::new(runner.clone()))
+        .with_supervisor_config(SupervisorConfig {
+            log_lines: 100,
+            stop_grace: Duration::from_millis(100),
+        })
+        .with_command_policy(CommandPolicy {
+            timeout: Duration::from_secs(1),
+            install_timeout: Duration::from_secs(1),
+            retries: 2,
+            backoff: Duration::from_millis(10),
+        });
+    Ok((Arc::new(manager), runner))
+}
+
+/// An emulator that adb lists as online, found running on startup
+async fn setup_running_emulator(manager: &SharedEmulatorManager, runner: &FakeRunner) -> Result<()> {
+    manager.create_emulator("test_avd".to_string()).await?;
+    runner.on("adb", &["devices"], FakeResponse::ok(DEVICE_ONLINE));
+    let report = manager.reconcile().await?;
+    assert_eq!(report.running, vec!["test_avd"]);
+    runner.clear_calls();
+    Ok(())
+}
+
+/// An artifact store in a fresh temporary directory holding one APK, and its ID
+async fn setup_artifact() -> Result<(ArtifactStore, String)> {
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let dir = std::env::temp_dir().join(format!("artifacts-{}", uuid::Uuid::new_v4()));
+    let store = ArtifactStore::new(dir, pool);
+    let apk = futures_util::stream::iter([Ok::<_, std::io::Error>(web::Bytes::from_static(
+        b"PK\x03\x04app",
+    ))]);
+    let (record, _) = store.save("app.apk", apk).await?;
+    Ok((store, record.id))
+}
+
+macro_rules! test_app {
+    ($manager:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($manager.clone()))
+                .configure(handlers::emulator::configure),
+        )
+        .await
+    };
+    ($manager:expr, $artifacts:expr) => {
+        test::init_service(
+            App::new()
+                .app_data(web::Data::new($manager.clone()))
+                .app_data(web::Data::new($artifacts.clone()))
+                .configure(handlers::emulator::configure),
+        )
+        .await
+    };
+}
+
+#[actix_web::test]
+async fn test_start_and_stop_emulator() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+    runner.on(
+        "emulator",
+        &[],
+        FakeResponse::ok("INFO    | Booting emulator\n").running_for(Duration::from_secs(30)),
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/start")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "booting");
+    assert_eq!(
+        runner.command_lines()[0],
+        "emulator -avd test_avd -port 5554 -no-window"
+    );
+
+    let process = manager
+        .registry()
+        .get("test_avd")
+        .await
+        .and_then(|instance| instance.process)
+        .expect("started emulator is supervised");
+
+    // The supervisor captures the process output
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let req = test::TestRequest::get().uri("/emulators/test_avd/logs").to_request();
+    let logs: EmulatorLogsResponse = test::call_and_read_body_json(&app, req).await;
+    assert!(logs.pid.is_some());
+    assert_eq!(logs.lines[0].line, "INFO    | Booting emulator");
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/stop")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "stopped");
+    assert!(runner
+        .command_lines()
+        .contains(&"adb -s emulator-5554 emu kill".to_string()));
+
+    // The fake emulator ignores the kill request, so the supervisor kills it
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while process.last_exit().is_none() {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_start_failure_marks_emulator_failed() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+    runner.on("emulator", &[], FakeResponse::missing());
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/start")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("tool_not_found"));
+
+    let emulator = manager.get_emulator("test_avd").await.unwrap();
+    assert_eq!(emulator.state(), EmulatorState::Failed);
+    assert_eq!(emulator.last_error(), Some("emulator is not installed or not on PATH"));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_hung_adb_server_start_times_out() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+    runner.stop_adb_server().on(
+        "adb",
+        &["start-server"],
+        FakeResponse::ok("").running_for(Duration::from_secs(30)),
+    );
+
+    // Listing reports the emulator offline instead of waiting for adb forever
+    let req = test::TestRequest::get().uri("/emulators").to_request();
+    let resp = tokio::time::timeout(Duration::from_secs(5), test::call_service(&app, req)).await?;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(runner.command_lines(), vec!["adb start-server"]);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_emulator_boots_to_running() -> Result<()> {
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let runner = FakeRunner::new();
+    let manager: SharedEmulatorManager = Arc::new(
+        EmulatorManager::new(pool)
+            .with_runner(Arc::new(runner.clone()))
+            .with_boot_config(BootConfig {
+                timeout: Duration::from_secs(5),
+                poll_interval: Duration::from_millis(50),
+            }),
+    );
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+    runner
+        .on("emulator", &[], FakeResponse::ok("").running_for(Duration::from_secs(30)))
+        .on("adb", &["devices"], FakeResponse::ok(DEVICE_ONLINE))
+        .on("adb", &["-s", "emulator-5554", "shell", "getprop sys.boot_completed"], FakeResponse::ok("1\n"))
+        .on("adb", &["-s", "emulator-5554", "shell", "getprop init.svc.bootanim"], FakeResponse::ok("stopped\n"));
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/start")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "booting");
+
+    let state = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            let emulator = manager.get_emulator("test_avd").await.unwrap();
+            if emulator.state() != EmulatorState::Booting {
+                return emulator.state();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await?;
+    assert_eq!(state, EmulatorState::Running);
+    assert!(runner
+        .command_lines()
+        .contains(&"adb -s emulator-5554 shell getprop init.svc.bootanim".to_string()));
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/stop")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "stopped");
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_stop_failed_emulator() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    let mut emulator = manager.create_emulator("test_avd".to_string()).await?;
+    manager.transition(&mut emulator, EmulatorState::Booting, None).await?;
+    manager
+        .transition(&mut emulator, EmulatorState::Failed, Some("boot timed out".to_string()))
+        .await?;
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/stop")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "stopped");
+    assert!(runner
+        .command_lines()
+        .contains(&"adb -s emulator-5554 emu kill".to_string()));
+
+    // The emulator is usually gone by the time it is stopped
+    let mut emulator = manager.get_emulator("test_avd").await.unwrap();
+    manager.transition(&mut emulator, EmulatorState::Booting, None).await?;
+    manager
+        .transition(&mut emulator, EmulatorState::Failed, Some("crashed".to_string()))
+        .await?;
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "emu", "kill"],
+        FakeResponse::fail(1, "error: device 'emulator-5554' not found"),
+    );
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/stop")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "stopped");
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_boot_timeout_kills_emulator() -> Result<()> {
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let runner = FakeRunner::new();
+    let manager: SharedEmulatorManager = Arc::new(
+        EmulatorManager::new(pool)
+            .with_runner(Arc::new(runner.clone()))
+            .with_boot_config(BootConfig {
+                timeout: Duration::from_millis(300),
+                poll_interval: Duration::from_millis(50),
+            }),
+    );
+    let app = test_app!(manager);
+    manager.create_emulator("test_avd".to_string()).await?;
+    // The emulator hangs without ever booting
+    runner.on("emulator", &[], FakeResponse::ok("").running_for(Duration::from_secs(30)));
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/start")
+        .to_request();
+    let resp: EmulatorResponse = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(resp.status, "booting");
+    let process = manager
+        .registry()
+        .get("test_avd")
+        .await
+        .and_then(|instance| instance.process)
+        .expect("started emulator is supervised");
+
+    let exit = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            if let Some(exit) = process.last_exit() {
+                return exit;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    })
+    .await?;
+    assert!(!exit.success);
+    assert_eq!(exit.signal, Some(9));
+
+    let emulator = manager.get_emulator("test_avd").await.unwrap();
+    assert_eq!(emulator.state(), EmulatorState::Failed);
+    assert!(emulator.last_error().unwrap().contains("boot did not complete"));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_install_app() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let (artifacts, artifact_id) = setup_artifact().await?;
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+    runner.on("adb", &["-s", "emulator-5554", "exec-out"], FakeResponse::ok("Success\n"));
+
+    // The APK is streamed to the package manager
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(&InstallAppRequest {
+            artifact_id: artifact_id.clone(),
+            split_artifact_ids: Vec::new(),
+            options: Default::default(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    assert_eq!(
+        runner.command_lines(),
+        vec!["adb -s emulator-5554 exec-out cmd package install -r -S 7"]
+    );
+
+    // Only uploaded artifacts can be installed
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(&InstallAppRequest {
+            artifact_id: "0".repeat(64),
+            split_artifact_ids: Vec::new(),
+            options: Default::default(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("artifact_not_found"));
+    assert_eq!(runner.calls().len(), 1);
+
+    runner.on("adb", &[], FakeResponse::fail(1, "cmd: Can't find service: package"));
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(&InstallAppRequest {
+            artifact_id: artifact_id.clone(),
+            split_artifact_ids: Vec::new(),
+            options: Default::default(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 500);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert!(!body.success);
+    assert_eq!(body.code.as_deref(), Some("install_failed"));
+
+    // Options become flags, and the package manager's reason becomes the code
+    runner.on(
+        "adb",
+        &[],
+        FakeResponse::fail(1, "Failure [INSTALL_FAILED_VERSION_DOWNGRADE]"),
+    );
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(serde_json::json!({
+            "artifact_id": artifact_id,
+            "options": {"grant_permissions": true, "user": 0},
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 422);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("install_failed_version_downgrade"));
+    assert_eq!(
+        runner.command_lines().last().unwrap(),
+        "adb -s emulator-5554 exec-out cmd package install -r -g --user 0 -S 7"
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(serde_json::json!({
+            "artifact_id": artifact_id,
+            "options": {"abi": "sparc"},
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("invalid_install_option"));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_install_split_apks() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let dir = std::env::temp_dir().join(format!("artifacts-{}", uuid::Uuid::new_v4()));
+    let artifacts = ArtifactStore::new(dir, pool.clone());
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+
+    // A base APK and ABI splits uploaded separately, known by their manifests
+    let mut ids = Vec::new();
+    for (content, split) in [
+        (b"PK\x03\x04base".as_slice(), None),
+        (b"PK\x03\x04x86_64".as_slice(), Some("config.x86_64")),
+        (b"PK\x03\x04arm64".as_slice(), Some("config.arm64_v8a")),
+    ] {
+        let upload = futures_util::stream::iter([Ok::<_, std::io::Error>(web::Bytes::copy_from_slice(content))]);
+        let (record, _) = artifacts.save("app.apk", upload).await?;
+        let manifest = ApkManifest {
+            package: "com.example".to_string(),
+            version_code: Some(1),
+            version_name: None,
+            min_sdk: None,
+            target_sdk: None,
+            permissions: Vec::new(),
+            launcher_activity: None,
+            split: split.map(str::to_string),
+        };
+        ArtifactDb::new(pool.clone())
+            .save_artifact(&ArtifactRecord::new(record.id.clone(), "app.apk".to_string(), 1, Some(manifest)))
+            .await?;
+        ids.push(record.id);
+    }
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "shell", "getprop ro.product.cpu.abilist"],
+        FakeResponse::ok("x86_64,x86\n"),
+    );
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "shell", "getprop ro.sf.lcd_density"],
+        FakeResponse::ok("420\n"),
+    );
+    runner.on("adb", &["-s", "emulator-5554", "exec-out"], FakeResponse::ok("Success\n"));
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "exec-out", "cmd package install-create -r"],
+        FakeResponse::ok("Success: created install session [1234]\n"),
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(&InstallAppRequest {
+            artifact_id: ids[0].clone(),
+            split_artifact_ids: ids[1..].to_vec(),
+            options: Default::default(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let report: InstallAppResponse = test::read_body_json(resp).await;
+    assert_eq!(report.device.unwrap().density, 420);
+    let splits: Vec<&str> = report.splits.iter().map(|split| split.name.as_str()).collect();
+    assert_eq!(splits, vec!["base", "config.x86_64"]);
+    assert_eq!(report.skipped_splits, vec!["config.arm64_v8a"]);
+    // Only the matching splits are written to the install session
+    assert_eq!(
+        runner.command_lines()[2..],
+        [
+            "adb -s emulator-5554 exec-out cmd package install-create -r",
+            "adb -s emulator-5554 exec-out cmd package install-write -S 8 1234 0.apk -",
+            "adb -s emulator-5554 exec-out cmd package install-write -S 10 1234 1.apk -",
+            "adb -s emulator-5554 exec-out cmd package install-commit 1234",
+        ]
+    );
+
+    // The base APK cannot be passed as a split
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(&InstallAppRequest {
+            artifact_id: ids[1].clone(),
+            split_artifact_ids: vec![ids[0].clone()],
+            options: Default::default(),
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("invalid_upload"));
+    assert_eq!(runner.calls().len(), 6);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_install_retries_and_timeouts() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let (artifacts, artifact_id) = setup_artifact().await?;
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+    let install = || {
+        test::TestRequest::post()
+            .uri("/emulators/test_avd/apps/install")
+            .set_json(&InstallAppRequest {
+                artifact_id: artifact_id.clone(),
+                split_artifact_ids: Vec::new(),
+                options: Default::default(),
+            })
+            .to_request()
+    };
+
+    // Transient failures are retried up to the configured number of times
+    runner.on("adb", &[], FakeResponse::fail(1, "error: device offline"));
+    let resp = test::call_service(&app, install()).await;
+    assert_eq!(resp.status(), 409);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("device_offline"));
+    assert_eq!(runner.calls().len(), 3);
+
+    // A hanging install is killed and reported as a timeout
+    runner.on("adb", &[], FakeResponse::ok("").running_for(Duration::from_secs(30)));
+    let started = std::time::Instant::now();
+    let resp = test::call_service(&app, install()).await;
+    assert_eq!(resp.status(), 504);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_operations_are_queued_per_emulator() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let (artifacts, artifact_id) = setup_artifact().await?;
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+    manager.create_emulator("other_avd".to_string()).await?;
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "exec-out"],
+        FakeResponse::ok("Success").running_for(Duration::from_millis(500)),
+    );
+
+    let install = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/install")
+        .set_json(&InstallAppRequest {
+            artifact_id: artifact_id.clone(),
+            split_artifact_ids: Vec::new(),
+            options: Default::default(),
+        })
+        .to_request();
+    let started = std::time::Instant::now();
+    let (install, status, stop) = tokio::join!(
+        test::call_service(&app, install),
+        async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let req = test::TestRequest::get().uri("/emulators/other_avd/status").to_request();
+            let resp = test::call_service(&app, req).await;
+            (resp.status(), started.elapsed())
+        },
+        async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            let req = test::TestRequest::post().uri("/emulators/test_avd/stop").to_request();
+            let resp = test::call_service(&app, req).await;
+            (resp.status(), started.elapsed())
+        },
+    );
+    assert!(install.status().is_success());
+
+    // Another emulator is not held up by the slow install
+    assert_eq!(status.0, 200);
+    assert!(status.1 < Duration::from_millis(400));
+
+    // Stopping the emulator waits for the install to finish
+    assert_eq!(stop.0, 200);
+    assert!(stop.1 >= Duration::from_millis(500));
+    assert_eq!(
+        runner.command_lines(),
+        vec![
+            "adb -s emulator-5554 exec-out cmd package install -r -S 7",
+            "adb -s emulator-5554 emu kill",
+        ]
+    );
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_rejects_shell_metacharacters() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let (artifacts, _) = setup_artifact().await?;
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/x;rm/start")
+        .set_json(&StartAppRequest {
+            activity: Some(".MainActivity".to_string()),
+            ..Default::default()
+        })
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("invalid_package_name"));
+
+    let req = test::TestRequest::post()
+        .uri("/emulators")
+        .set_json(serde_json::json!({"name": "avd -qemu"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+
+    assert!(runner.calls().is_empty());
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_start_app_defaults_to_launcher_activity() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let pool = db::create_pool("sqlite::memory:").await?;
+    let artifacts = ArtifactStore::new(std::env::temp_dir().join("unused-artifacts"), pool.clone());
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+
+    // An APK whose manifest declares a launcher activity was installed earlier
+    let artifact_id = "ab".repeat(32);
+    let manifest = ApkManifest {
+        package: "com.example".to_string(),
+        version_code: Some(1),
+        version_name: Some("1.0".to_string()),
+        min_sdk: Some(24),
+        target_sdk: Some(34),
+        permissions: Vec::new(),
+        launcher_activity: Some("com.example.MainActivity".to_string()),
+        split: None,
+    };
+    ArtifactDb::new(pool)
+        .save_artifact(&ArtifactRecord::new(artifact_id.clone(), "app.apk".to_string(), 1, Some(manifest)))
+        .await?;
+    artifacts.record_install(&artifact_id, "test_avd").await?;
+
+    let start = |package: &str| {
+        test::TestRequest::post()
+            .uri(&format!("/emulators/test_avd/apps/{}/start", package))
+            .set_json(StartAppRequest::default())
+            .to_request()
+    };
+
+    // The device resolves the launcher activity itself
+    runner.on("adb", &[], FakeResponse::ok("com.example/.ui.HomeActivity\n"));
+    let launch: LaunchResult = test::call_and_read_body_json(&app, start("com.example")).await;
+    assert_eq!(launch.component, "com.example/.ui.HomeActivity");
+    assert!(runner.command_lines()[0].contains("cmd package resolve-activity --brief"));
+    assert!(runner.command_lines()[1].ends_with("am start -n com.example/.ui.HomeActivity"));
+
+    // Without an answer from the device, the installed APK's manifest is used
+    runner.on("adb", &[], FakeResponse::ok("No activity found\n"));
+    let resp = test::call_service(&app, start("com.example")).await;
+    assert!(resp.status().is_success());
+    assert_eq!(runner.calls().len(), 4);
+    assert!(runner.command_lines()[3].ends_with("am start -n com.example/com.example.MainActivity"));
+
+    let resp = test::call_service(&app, start("com.other")).await;
+    assert_eq!(resp.status(), 400);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("launcher_activity_unknown"));
+    assert_eq!(runner.calls().len(), 5);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_start_app_waits_for_launch() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let (artifacts, _) = setup_artifact().await?;
+    let app = test_app!(manager, artifacts);
+    setup_running_emulator(&manager, &runner).await?;
+    runner.on(
+        "adb",
+        &[],
+        FakeResponse::ok(
+            "Starting: Intent { cmp=com.example/.MainActivity }\n\
+             Status: ok\n\
+             LaunchState: COLD\n\
+             Activity: com.example/.MainActivity\n\
+             TotalTime: 640\n\
+             WaitTime: 655\n\
+             Complete\n",
+        ),
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/apps/com.example/start")
+        .set_json(&StartAppRequest {
+            activity: Some(".MainActivity".to_string()),
+            wait: true,
+        })
+        .to_request();
+    let launch: LaunchResult = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(launch.status.as_deref(), Some("ok"));
+    assert_eq!(launch.launch_state.as_deref(), Some("COLD"));
+    assert_eq!(launch.total_time_ms, Some(640));
+    assert_eq!(
+        runner.command_lines(),
+        vec!["adb -s emulator-5554 shell am start -W -n com.example/.MainActivity"]
+    );
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_send_intent() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    setup_running_emulator(&manager, &runner).await?;
+    runner.on(
+        "adb",
+        &[],
+        FakeResponse::ok("Broadcasting: Intent { act=com.example.SYNC }\nBroadcast completed: result=1, data=\"done\"\n"),
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/intents")
+        .set_json(serde_json::json!({
+            "target": "broadcast",
+            "action": "com.example.SYNC",
+            "package": "com.example",
+            "extras": [
+                {"key": "full", "type": "bool", "value": true},
+                {"key": "ids", "type": "string_array", "value": ["1", "2"]},
+            ],
+        }))
+        .to_request();
+    let result: IntentResult = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        result.broadcast,
+        Some(BroadcastResult {
+            result_code: 1,
+            data: Some("done".to_string()),
+        })
+    );
+    assert_eq!(
+        runner.command_lines(),
+        vec!["adb -s emulator-5554 shell am broadcast -a com.example.SYNC -p com.example --ez full true --esa ids 1,2"]
+    );
+
+    let req = test::TestRequest::post()
+        .uri("/emulators/test_avd/intents")
+        .set_json(serde_json::json!({"flags": ["FLAG_ACTIVITY_NEW_TASK"]}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: ApiResponse<()> = test::read_body_json(resp).await;
+    assert_eq!(body.code.as_deref(), Some("invalid_intent"));
+    assert_eq!(runner.calls().len(), 1);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_app_processes() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    setup_running_emulator(&manager, &runner).await?;
+    runner.on(
+        "adb",
+        &[],
+        FakeResponse::ok(
+            "PID   USER           RSS STIME    NAME\n\
+             4242  u0_a123     154320 09:14:55 com.foo\n\
+             4301  u0_a123      61288 09:15:02 com.foo:sync\n\
+             4400  u0_a124      88004 09:20:00 com.foobar\n",
+        ),
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/emulators/test_avd/apps/com.foo/processes")
+        .to_request();
+    let processes: Vec<AppProcess> = test::call_and_read_body_json(&app, req).await;
+    let names: Vec<&str> = processes.iter().map(|process| process.name.as_str()).collect();
+    assert_eq!(names, vec!["com.foo", "com.foo:sync"]);
+    assert_eq!(processes[1].rss_kb, 61288);
+    Ok(())
+}
+
+#[actix_web::test]
+async fn test_list_apps() -> Result<()> {
+    let (manager, runner) = setup_manager().await?;
+    let app = test_app!(manager);
+    setup_running_emulator(&manager, &runner).await?;
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "shell", "pm list packages -f --show-versioncode"],
+        FakeResponse::ok(
+            "package:/data/app/~~Zq==/com.foo-Xw==/base.apk=com.foo versionCode:12\n\
+             package:/system/priv-app/Settings/Settings.apk=com.android.settings versionCode:34\n",
+        ),
+    );
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "shell", "pm list packages -s"],
+        FakeResponse::ok("package:com.android.settings\n"),
+    );
+    runner.on(
+        "adb",
+        &["-s", "emulator-5554", "shell", "dumpsys package"],
+        FakeResponse::ok("Packages:\n  Package [com.foo] (1f2e3d):\n    versionName=1.2.0\n"),
+    );
+
+    let req = test::TestRequest::get().uri("/emulators/test_avd/apps").to_request();
+    let inventory: PackageInventory = test::call_and_read_body_json(&app, req).await;
+    assert!(inventory.live);
+    assert!(inventory.refreshed_at.is_some());
+    let names: Vec<&str> = inventory.packages.iter().map(|package| package.package.as_str()).collect();
+    assert_eq!(names, vec!["com.android.settings", "com.foo"]);
+    assert_eq!(inventory.packages[1].version_name.as_deref(), Some("1.2.0"));
+
+    // Once the emulator stops, the last inventory is served without asking it
+    let mut emulator = manager.get_emulator("test_avd").await.unwrap();
+    manager.transition(&mut emulator, EmulatorState::Stopping, None).await?;
+    manager.transition(&mut emulator, EmulatorState::Stopped, None).await?;
+    let calls = runner.calls().len();
+    let req = test::TestRequest::get()
+        .uri("/emulators/test_avd/apps?system=false")
+        .to_request();
+    let cached: PackageInventory = test::call_and_read_body_json(&app, req).await;
+    assert!(!cached.live);
+    assert_eq!(cached.refreshed_at, inventory.refreshed_at);
+    assert_eq!(cached.packages, vec![inventory.packages[1].clone()]);
+    assert_eq!(runner.calls().len(), calls);
+
+    let req = test::TestRequest::get().uri("/emulators/other/apps").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+    Ok(())
+}